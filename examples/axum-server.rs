@@ -80,7 +80,13 @@ async fn store_token(
     let user_id = Some(Uuid::new_v4());
 
     if let Err(e) = state.store
-        .store_token(&token_response, client_id, user_id, &scopes)
+        .store_token(
+            &token_response,
+            client_id,
+            user_id,
+            &scopes,
+            Some(Duration::from_secs(30 * 24 * 3600)),
+        )
         .await
     {
         return (
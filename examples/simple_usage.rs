@@ -26,7 +26,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Fixed: borrow the Duration with &
     token_response.set_expires_in(Some(&std::time::Duration::from_secs(7200)));
 
-    store.store_token(&token_response, "my-app", None, &scopes).await?;
+    store.store_token(&token_response, "my-app", None, &scopes, None).await?;
 
     // Lookup
     if let Some(found) = store.get_by_access_token(&token).await? {
@@ -0,0 +1,4 @@
+-- Add down migration script here
+DROP INDEX IF EXISTS idx_oauth2_refresh_expires_at;
+DROP INDEX IF EXISTS idx_oauth2_expires_at;
+ALTER TABLE oauth2_tokens DROP COLUMN IF EXISTS refresh_expires_at;
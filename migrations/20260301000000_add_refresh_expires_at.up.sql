@@ -0,0 +1,10 @@
+-- Refresh tokens get their own expiry, independent of the access token's expires_at
+ALTER TABLE oauth2_tokens ADD COLUMN IF NOT EXISTS refresh_expires_at TIMESTAMPTZ;
+
+-- Preserve existing behaviour: refresh tokens already stored expire with their access token
+UPDATE oauth2_tokens
+SET refresh_expires_at = expires_at
+WHERE refresh_token_hash IS NOT NULL;
+
+CREATE INDEX IF NOT EXISTS idx_oauth2_expires_at ON oauth2_tokens(expires_at);
+CREATE INDEX IF NOT EXISTS idx_oauth2_refresh_expires_at ON oauth2_tokens(refresh_expires_at);
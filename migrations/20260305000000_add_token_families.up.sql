@@ -0,0 +1,7 @@
+-- Token families for refresh-token rotation and reuse detection.
+-- Existing rows each start their own family.
+ALTER TABLE oauth2_tokens
+    ADD COLUMN IF NOT EXISTS family_id UUID NOT NULL DEFAULT gen_random_uuid(),
+    ADD COLUMN IF NOT EXISTS rotated_at TIMESTAMPTZ;
+
+CREATE INDEX IF NOT EXISTS idx_oauth2_family_id ON oauth2_tokens(family_id);
@@ -0,0 +1,3 @@
+-- Add down migration script here
+DROP INDEX IF EXISTS idx_oauth2_codes_expires_at;
+DROP TABLE IF EXISTS oauth2_authorization_codes;
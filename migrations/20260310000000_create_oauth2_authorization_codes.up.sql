@@ -0,0 +1,16 @@
+-- Authorization codes (RFC 6749 §4.1), stored hashed like tokens
+CREATE TABLE IF NOT EXISTS oauth2_authorization_codes (
+    id                    UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    code_hash             TEXT NOT NULL UNIQUE,
+    client_id             TEXT NOT NULL,
+    user_id               UUID NOT NULL,
+    redirect_uri          TEXT,
+    scopes                TEXT[] NOT NULL,
+    code_challenge        TEXT,
+    code_challenge_method TEXT,
+    nonce                 TEXT,
+    issued_at             TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    expires_at            TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_oauth2_codes_expires_at ON oauth2_authorization_codes(expires_at);
@@ -0,0 +1,17 @@
+-- Device authorization grant (RFC 8628)
+CREATE TABLE IF NOT EXISTS oauth2_device_codes (
+    id               UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    device_code_hash TEXT NOT NULL UNIQUE,
+    user_code        TEXT NOT NULL UNIQUE,
+    client_id        TEXT NOT NULL,
+    scopes           TEXT[] NOT NULL,
+    status           TEXT NOT NULL DEFAULT 'pending'
+                     CHECK (status IN ('pending', 'approved', 'denied')),
+    user_id          UUID,
+    interval_secs    INTEGER NOT NULL,
+    last_polled_at   TIMESTAMPTZ,
+    issued_at        TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    expires_at       TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_oauth2_device_codes_expires_at ON oauth2_device_codes(expires_at);
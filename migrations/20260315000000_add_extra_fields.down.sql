@@ -0,0 +1,2 @@
+-- Add down migration script here
+ALTER TABLE oauth2_tokens DROP COLUMN IF EXISTS extra_fields;
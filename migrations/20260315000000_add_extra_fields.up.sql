@@ -0,0 +1,2 @@
+-- Provider-specific fields of the token response (e.g. OIDC id_token)
+ALTER TABLE oauth2_tokens ADD COLUMN IF NOT EXISTS extra_fields JSONB NOT NULL DEFAULT '{}'::jsonb;
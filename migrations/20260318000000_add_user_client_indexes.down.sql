@@ -0,0 +1,3 @@
+-- Add down migration script here
+DROP INDEX IF EXISTS idx_oauth2_client_id;
+DROP INDEX IF EXISTS idx_oauth2_user_client;
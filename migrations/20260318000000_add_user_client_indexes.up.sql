@@ -0,0 +1,3 @@
+-- Support bulk revocation by user and/or client
+CREATE INDEX IF NOT EXISTS idx_oauth2_user_client ON oauth2_tokens(user_id, client_id);
+CREATE INDEX IF NOT EXISTS idx_oauth2_client_id ON oauth2_tokens(client_id);
@@ -0,0 +1,2 @@
+-- Keyset pagination of a user's tokens (ORDER BY issued_at DESC, id DESC)
+CREATE INDEX IF NOT EXISTS idx_oauth2_user_issued_at ON oauth2_tokens(user_id, issued_at DESC, id DESC);
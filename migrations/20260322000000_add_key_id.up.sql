@@ -0,0 +1,2 @@
+-- Which hashing key (pepper) produced each row's hashes; NULL = unkeyed BLAKE3
+ALTER TABLE oauth2_tokens ADD COLUMN IF NOT EXISTS key_id TEXT;
@@ -0,0 +1,6 @@
+-- Which TokenHasher produced each row's hashes, so mixed-algorithm tables keep working
+ALTER TABLE oauth2_tokens ADD COLUMN IF NOT EXISTS hash_algorithm TEXT NOT NULL DEFAULT 'blake3';
+
+UPDATE oauth2_tokens
+SET hash_algorithm = 'blake3-keyed'
+WHERE key_id IS NOT NULL;
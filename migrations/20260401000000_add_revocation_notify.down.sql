@@ -0,0 +1,4 @@
+-- Add down migration script here
+DROP TRIGGER IF EXISTS oauth2_tokens_notify_revoked_delete ON oauth2_tokens;
+DROP TRIGGER IF EXISTS oauth2_tokens_notify_revoked_update ON oauth2_tokens;
+DROP FUNCTION IF EXISTS oauth2_notify_revoked();
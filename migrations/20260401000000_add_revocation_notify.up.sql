@@ -0,0 +1,40 @@
+-- Announce revoked and deleted tokens on the oauth2_token_revoked channel so caches on
+-- other instances can purge them (see CachedTokenStore::listen_for_revocations).
+-- Payloads are comma-separated access token hashes, 100 per notification to stay well
+-- under NOTIFY's 8000 byte limit.
+CREATE OR REPLACE FUNCTION oauth2_notify_revoked() RETURNS trigger
+LANGUAGE plpgsql AS $$
+BEGIN
+    IF TG_OP = 'UPDATE' THEN
+        PERFORM pg_notify('oauth2_token_revoked', string_agg(hash, ','))
+        FROM (
+            SELECT n.access_token_hash AS hash, (row_number() OVER () - 1) / 100 AS batch
+            FROM new_rows n
+            JOIN old_rows o ON o.id = n.id
+            WHERE n.revoked AND NOT o.revoked
+        ) revoked
+        GROUP BY batch;
+    ELSE
+        -- Revoked rows were announced when revoked, and caches never serve expired ones.
+        PERFORM pg_notify('oauth2_token_revoked', string_agg(hash, ','))
+        FROM (
+            SELECT access_token_hash AS hash, (row_number() OVER () - 1) / 100 AS batch
+            FROM old_rows
+            WHERE NOT revoked AND (expires_at IS NULL OR expires_at > NOW())
+        ) deleted
+        GROUP BY batch;
+    END IF;
+
+    RETURN NULL;
+END;
+$$;
+
+CREATE TRIGGER oauth2_tokens_notify_revoked_update
+    AFTER UPDATE ON oauth2_tokens
+    REFERENCING OLD TABLE AS old_rows NEW TABLE AS new_rows
+    FOR EACH STATEMENT EXECUTE FUNCTION oauth2_notify_revoked();
+
+CREATE TRIGGER oauth2_tokens_notify_revoked_delete
+    AFTER DELETE ON oauth2_tokens
+    REFERENCING OLD TABLE AS old_rows
+    FOR EACH STATEMENT EXECUTE FUNCTION oauth2_notify_revoked();
@@ -0,0 +1,3 @@
+-- Add down migration script here
+ALTER TABLE oauth2_tokens DROP COLUMN IF EXISTS use_count;
+ALTER TABLE oauth2_tokens DROP COLUMN IF EXISTS last_used_at;
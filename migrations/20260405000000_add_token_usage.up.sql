@@ -0,0 +1,3 @@
+-- Usage recorded by PgTokenStore::with_usage_tracking
+ALTER TABLE oauth2_tokens ADD COLUMN IF NOT EXISTS last_used_at TIMESTAMPTZ;
+ALTER TABLE oauth2_tokens ADD COLUMN IF NOT EXISTS use_count BIGINT NOT NULL DEFAULT 0;
@@ -0,0 +1,5 @@
+-- Add down migration script here
+ALTER TABLE oauth2_tokens DROP COLUMN IF EXISTS max_expires_at;
+ALTER TABLE oauth2_tokens DROP COLUMN IF EXISTS sliding_window_secs;
+ALTER TABLE oauth2_tokens DROP COLUMN IF EXISTS idle_expires_at;
+ALTER TABLE oauth2_tokens DROP COLUMN IF EXISTS idle_timeout_secs;
@@ -0,0 +1,5 @@
+-- Idle timeout and sliding expiration, see PgTokenStore::with_idle_timeout
+ALTER TABLE oauth2_tokens ADD COLUMN IF NOT EXISTS idle_timeout_secs BIGINT;
+ALTER TABLE oauth2_tokens ADD COLUMN IF NOT EXISTS idle_expires_at TIMESTAMPTZ;
+ALTER TABLE oauth2_tokens ADD COLUMN IF NOT EXISTS sliding_window_secs BIGINT;
+ALTER TABLE oauth2_tokens ADD COLUMN IF NOT EXISTS max_expires_at TIMESTAMPTZ;
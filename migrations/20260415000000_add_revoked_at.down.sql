@@ -0,0 +1,4 @@
+-- Add down migration script here
+DROP TRIGGER IF EXISTS oauth2_tokens_stamp_revoked_at ON oauth2_tokens;
+DROP FUNCTION IF EXISTS oauth2_stamp_revoked_at();
+ALTER TABLE oauth2_tokens DROP COLUMN IF EXISTS revoked_at;
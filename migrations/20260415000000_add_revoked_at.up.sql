@@ -0,0 +1,23 @@
+-- When each token was revoked, so cleanup can keep revoked rows for a retention period
+-- (see PgTokenStore::cleanup_batched). Set by a trigger so every revocation path,
+-- including manual SQL, records it. Rows revoked earlier count as revoked now.
+ALTER TABLE oauth2_tokens ADD COLUMN IF NOT EXISTS revoked_at TIMESTAMPTZ;
+
+UPDATE oauth2_tokens
+SET revoked_at = COALESCE(rotated_at, NOW())
+WHERE revoked AND revoked_at IS NULL;
+
+CREATE OR REPLACE FUNCTION oauth2_stamp_revoked_at() RETURNS trigger
+LANGUAGE plpgsql AS $$
+BEGIN
+    IF NEW.revoked AND NOT OLD.revoked THEN
+        NEW.revoked_at := NOW();
+    END IF;
+
+    RETURN NEW;
+END;
+$$;
+
+CREATE TRIGGER oauth2_tokens_stamp_revoked_at
+    BEFORE UPDATE OF revoked ON oauth2_tokens
+    FOR EACH ROW EXECUTE FUNCTION oauth2_stamp_revoked_at();
@@ -0,0 +1,5 @@
+-- Add down migration script here
+DROP INDEX IF EXISTS idx_oauth2_idle_expires_at;
+DROP INDEX IF EXISTS idx_oauth2_access_only_expires_at;
+DROP INDEX IF EXISTS idx_oauth2_rotated_refresh_expires_at;
+DROP INDEX IF EXISTS idx_oauth2_revoked_at;
@@ -0,0 +1,11 @@
+-- Batched cleanup (PgTokenStore::cleanup_batched) deletes each kind of dead row with its
+-- own statement, walking one of these indexes in order. Each index only covers the rows
+-- its statement looks at, so a batch never rescans rows that are not yet removable.
+CREATE INDEX IF NOT EXISTS idx_oauth2_revoked_at
+    ON oauth2_tokens(revoked_at) WHERE revoked AND rotated_at IS NULL;
+CREATE INDEX IF NOT EXISTS idx_oauth2_rotated_refresh_expires_at
+    ON oauth2_tokens(refresh_expires_at) WHERE rotated_at IS NOT NULL;
+CREATE INDEX IF NOT EXISTS idx_oauth2_access_only_expires_at
+    ON oauth2_tokens(expires_at) WHERE refresh_token_hash IS NULL;
+CREATE INDEX IF NOT EXISTS idx_oauth2_idle_expires_at
+    ON oauth2_tokens(idle_expires_at) WHERE rotated_at IS NULL;
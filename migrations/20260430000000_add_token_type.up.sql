@@ -0,0 +1,3 @@
+-- Type of each issued access token, returned by introspection. NULL for tokens stored
+-- before it was recorded.
+ALTER TABLE oauth2_tokens ADD COLUMN IF NOT EXISTS token_type TEXT;
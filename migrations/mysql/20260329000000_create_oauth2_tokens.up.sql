@@ -0,0 +1,27 @@
+-- MySQL / MariaDB schema for MySqlTokenStore, equivalent to the Postgres migrations up to hash_algorithm.
+-- UUIDs are hyphenated CHAR(36), scopes and extra fields are JSON, and timestamps are UTC DATETIME(6).
+CREATE TABLE IF NOT EXISTS oauth2_tokens (
+    id                 CHAR(36) NOT NULL PRIMARY KEY,
+    access_token_hash  VARCHAR(255) NOT NULL UNIQUE,
+    refresh_token_hash VARCHAR(255),
+    client_id          VARCHAR(255) NOT NULL,
+    user_id            CHAR(36),
+    scopes             JSON NOT NULL,
+    issued_at          DATETIME(6) NOT NULL,
+    expires_at         DATETIME(6),
+    revoked            BOOLEAN NOT NULL DEFAULT FALSE,
+    refresh_expires_at DATETIME(6),
+    family_id          CHAR(36) NOT NULL,
+    rotated_at         DATETIME(6),
+    extra_fields       JSON NOT NULL,
+    key_id             VARCHAR(255),
+    hash_algorithm     VARCHAR(64) NOT NULL DEFAULT 'blake3',
+
+    INDEX idx_oauth2_refresh_hash (refresh_token_hash),
+    INDEX idx_oauth2_expires_at (expires_at),
+    INDEX idx_oauth2_refresh_expires_at (refresh_expires_at),
+    INDEX idx_oauth2_family_id (family_id),
+    INDEX idx_oauth2_user_client (user_id, client_id),
+    INDEX idx_oauth2_client_id (client_id),
+    INDEX idx_oauth2_user_issued_at (user_id, issued_at DESC, id DESC)
+);
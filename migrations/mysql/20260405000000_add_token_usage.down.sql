@@ -0,0 +1,4 @@
+-- Add down migration script here
+ALTER TABLE oauth2_tokens
+    DROP COLUMN use_count,
+    DROP COLUMN last_used_at;
@@ -0,0 +1,4 @@
+-- Usage recorded by MySqlTokenStore::with_usage_tracking
+ALTER TABLE oauth2_tokens
+    ADD COLUMN last_used_at DATETIME(6),
+    ADD COLUMN use_count BIGINT NOT NULL DEFAULT 0;
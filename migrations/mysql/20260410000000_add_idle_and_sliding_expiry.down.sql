@@ -0,0 +1,6 @@
+-- Add down migration script here
+ALTER TABLE oauth2_tokens
+    DROP COLUMN max_expires_at,
+    DROP COLUMN sliding_window_secs,
+    DROP COLUMN idle_expires_at,
+    DROP COLUMN idle_timeout_secs;
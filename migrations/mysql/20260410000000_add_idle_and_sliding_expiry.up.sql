@@ -0,0 +1,6 @@
+-- Idle timeout and sliding expiration, see MySqlTokenStore::with_idle_timeout
+ALTER TABLE oauth2_tokens
+    ADD COLUMN idle_timeout_secs BIGINT,
+    ADD COLUMN idle_expires_at DATETIME(6),
+    ADD COLUMN sliding_window_secs BIGINT,
+    ADD COLUMN max_expires_at DATETIME(6);
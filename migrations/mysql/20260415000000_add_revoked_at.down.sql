@@ -0,0 +1,3 @@
+-- Add down migration script here
+DROP TRIGGER IF EXISTS oauth2_tokens_stamp_revoked_at;
+ALTER TABLE oauth2_tokens DROP COLUMN revoked_at;
@@ -0,0 +1,12 @@
+-- When each token was revoked, set by a trigger on every revocation path.
+-- Rows revoked earlier count as revoked now.
+ALTER TABLE oauth2_tokens ADD COLUMN revoked_at DATETIME(6);
+
+UPDATE oauth2_tokens
+SET revoked_at = COALESCE(rotated_at, UTC_TIMESTAMP(6))
+WHERE revoked AND revoked_at IS NULL;
+
+CREATE TRIGGER oauth2_tokens_stamp_revoked_at
+    BEFORE UPDATE ON oauth2_tokens
+    FOR EACH ROW
+    SET NEW.revoked_at = IF(NEW.revoked AND NOT OLD.revoked, UTC_TIMESTAMP(6), NEW.revoked_at);
@@ -0,0 +1,2 @@
+-- Type of each issued access token, returned by introspection.
+ALTER TABLE oauth2_tokens ADD COLUMN token_type VARCHAR(64);
@@ -0,0 +1,28 @@
+-- SQLite schema for SqliteTokenStore, equivalent to the Postgres migrations up to hash_algorithm.
+-- UUIDs are hyphenated text, scopes and extra fields are JSON text, and timestamps are
+-- fixed-width RFC 3339 UTC text so they compare correctly as strings.
+CREATE TABLE IF NOT EXISTS oauth2_tokens (
+    id                 TEXT PRIMARY KEY NOT NULL,
+    access_token_hash  TEXT NOT NULL UNIQUE,
+    refresh_token_hash TEXT,
+    client_id          TEXT NOT NULL,
+    user_id            TEXT,
+    scopes             TEXT NOT NULL DEFAULT '[]',
+    issued_at          TEXT NOT NULL,
+    expires_at         TEXT,
+    revoked            INTEGER NOT NULL DEFAULT 0,
+    refresh_expires_at TEXT,
+    family_id          TEXT NOT NULL,
+    rotated_at         TEXT,
+    extra_fields       TEXT NOT NULL DEFAULT '{}',
+    key_id             TEXT,
+    hash_algorithm     TEXT NOT NULL DEFAULT 'blake3'
+);
+
+CREATE INDEX IF NOT EXISTS idx_oauth2_refresh_hash ON oauth2_tokens(refresh_token_hash);
+CREATE INDEX IF NOT EXISTS idx_oauth2_expires_at ON oauth2_tokens(expires_at);
+CREATE INDEX IF NOT EXISTS idx_oauth2_refresh_expires_at ON oauth2_tokens(refresh_expires_at);
+CREATE INDEX IF NOT EXISTS idx_oauth2_family_id ON oauth2_tokens(family_id);
+CREATE INDEX IF NOT EXISTS idx_oauth2_user_client ON oauth2_tokens(user_id, client_id);
+CREATE INDEX IF NOT EXISTS idx_oauth2_client_id ON oauth2_tokens(client_id);
+CREATE INDEX IF NOT EXISTS idx_oauth2_user_issued_at ON oauth2_tokens(user_id, issued_at DESC, id DESC);
@@ -0,0 +1,3 @@
+-- Add down migration script here
+ALTER TABLE oauth2_tokens DROP COLUMN use_count;
+ALTER TABLE oauth2_tokens DROP COLUMN last_used_at;
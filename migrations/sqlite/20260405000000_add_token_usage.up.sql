@@ -0,0 +1,3 @@
+-- Usage recorded by SqliteTokenStore::with_usage_tracking
+ALTER TABLE oauth2_tokens ADD COLUMN last_used_at TEXT;
+ALTER TABLE oauth2_tokens ADD COLUMN use_count INTEGER NOT NULL DEFAULT 0;
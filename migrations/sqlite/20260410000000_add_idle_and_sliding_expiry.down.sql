@@ -0,0 +1,5 @@
+-- Add down migration script here
+ALTER TABLE oauth2_tokens DROP COLUMN max_expires_at;
+ALTER TABLE oauth2_tokens DROP COLUMN sliding_window_secs;
+ALTER TABLE oauth2_tokens DROP COLUMN idle_expires_at;
+ALTER TABLE oauth2_tokens DROP COLUMN idle_timeout_secs;
@@ -0,0 +1,5 @@
+-- Idle timeout and sliding expiration, see SqliteTokenStore::with_idle_timeout
+ALTER TABLE oauth2_tokens ADD COLUMN idle_timeout_secs INTEGER;
+ALTER TABLE oauth2_tokens ADD COLUMN idle_expires_at TEXT;
+ALTER TABLE oauth2_tokens ADD COLUMN sliding_window_secs INTEGER;
+ALTER TABLE oauth2_tokens ADD COLUMN max_expires_at TEXT;
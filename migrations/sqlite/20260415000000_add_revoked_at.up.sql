@@ -0,0 +1,17 @@
+-- When each token was revoked, set by a trigger on every revocation path.
+-- Rows revoked earlier count as revoked now.
+ALTER TABLE oauth2_tokens ADD COLUMN revoked_at TEXT;
+
+UPDATE oauth2_tokens
+SET revoked_at = coalesce(rotated_at, strftime('%Y-%m-%dT%H:%M:%f000Z', 'now'))
+WHERE revoked AND revoked_at IS NULL;
+
+-- Same fixed-width format as the store writes: seconds with six fractional digits.
+CREATE TRIGGER IF NOT EXISTS oauth2_tokens_stamp_revoked_at
+    AFTER UPDATE OF revoked ON oauth2_tokens
+    FOR EACH ROW WHEN NEW.revoked AND NOT OLD.revoked
+BEGIN
+    UPDATE oauth2_tokens
+    SET revoked_at = strftime('%Y-%m-%dT%H:%M:%f000Z', 'now')
+    WHERE id = NEW.id;
+END;
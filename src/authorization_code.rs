@@ -0,0 +1,185 @@
+//! Short-lived authorization codes (RFC 6749 §4.1) with PKCE (RFC 7636).
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use oauth2::{AuthorizationCode, PkceCodeChallenge, PkceCodeVerifier};
+use sqlx::FromRow;
+use std::time::Duration;
+use subtle::ConstantTimeEq;
+use uuid::Uuid;
+
+use crate::{Error, PgTokenStore};
+
+/// What an authorization code was issued for, captured at the authorization endpoint.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuthorizationCodeGrant {
+    pub client_id: String,
+    pub user_id: Uuid,
+    /// The `redirect_uri` from the authorization request, if one was sent.
+    pub redirect_uri: Option<String>,
+    pub scopes: Vec<String>,
+    /// PKCE `code_challenge` from the authorization request.
+    pub code_challenge: Option<String>,
+    /// PKCE `code_challenge_method`; RFC 7636 defaults to `plain` when absent.
+    pub code_challenge_method: Option<String>,
+    /// OpenID Connect `nonce`, echoed back into the ID token.
+    pub nonce: Option<String>,
+}
+
+/// A stored authorization code record (returned once, by [`AuthorizationCodeStore::consume_code`]).
+#[derive(Debug, Clone, FromRow)]
+pub struct StoredAuthorizationCode {
+    pub id: Uuid,
+    pub code_hash: String,
+    pub client_id: String,
+    pub user_id: Uuid,
+    pub redirect_uri: Option<String>,
+    pub scopes: Vec<String>,
+    pub code_challenge: Option<String>,
+    pub code_challenge_method: Option<String>,
+    pub nonce: Option<String>,
+    pub issued_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl StoredAuthorizationCode {
+    /// Check the token request's `code_verifier` against the stored challenge, in constant
+    /// time.
+    fn verify_pkce(&self, verifier: Option<&PkceCodeVerifier>) -> Result<(), Error> {
+        let (challenge, verifier) = match (&self.code_challenge, verifier) {
+            (None, None) => return Ok(()),
+            (None, Some(_)) => {
+                return Err(Error::InvalidGrant("code_verifier sent but no code_challenge was issued".into()));
+            }
+            (Some(_), None) => return Err(Error::InvalidGrant("code_verifier required".into())),
+            (Some(challenge), Some(verifier)) => (challenge, verifier),
+        };
+
+        let matches = match self.code_challenge_method.as_deref().unwrap_or("plain") {
+            "S256" => {
+                let computed = PkceCodeChallenge::from_code_verifier_sha256(verifier);
+                bool::from(computed.as_str().as_bytes().ct_eq(challenge.as_bytes()))
+            }
+            "plain" => bool::from(verifier.secret().as_bytes().ct_eq(challenge.as_bytes())),
+            other => {
+                return Err(Error::InvalidGrant(format!("unsupported code_challenge_method {other}")));
+            }
+        };
+
+        if !matches {
+            return Err(Error::InvalidGrant("code_verifier does not match code_challenge".into()));
+        }
+
+        Ok(())
+    }
+}
+
+/// Storage for authorization codes between the authorization and token endpoints.
+#[async_trait]
+pub trait AuthorizationCodeStore: Send + Sync + 'static {
+    /// Persist a newly issued authorization code (hashed) for `expires_in`.
+    async fn store_code(
+        &self,
+        code: &AuthorizationCode,
+        grant: &AuthorizationCodeGrant,
+        expires_in: Duration,
+    ) -> Result<(), Error>;
+
+    /// Redeem an authorization code exactly once.
+    ///
+    /// The code is deleted before any check runs, so a failed redemption also burns it.
+    /// Fails with [`Error::NotFound`] if the code is unknown or already used, and
+    /// [`Error::InvalidGrant`] if it expired or the client, `redirect_uri` or PKCE
+    /// verifier do not match.
+    async fn consume_code(
+        &self,
+        code: &AuthorizationCode,
+        client_id: &str,
+        redirect_uri: Option<&str>,
+        verifier: Option<&PkceCodeVerifier>,
+    ) -> Result<StoredAuthorizationCode, Error>;
+}
+
+#[async_trait]
+impl AuthorizationCodeStore for PgTokenStore {
+    async fn store_code(
+        &self,
+        code: &AuthorizationCode,
+        grant: &AuthorizationCodeGrant,
+        expires_in: Duration,
+    ) -> Result<(), Error> {
+        let hash = self.hash_token(code.secret())?;
+        let expires_at = Utc::now() + expires_in;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO oauth2_authorization_codes (
+                code_hash,
+                client_id,
+                user_id,
+                redirect_uri,
+                scopes,
+                code_challenge,
+                code_challenge_method,
+                nonce,
+                issued_at,
+                expires_at
+            ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, NOW(), $9)
+            "#,
+            hash,
+            grant.client_id,
+            grant.user_id,
+            grant.redirect_uri,
+            &grant.scopes,
+            grant.code_challenge,
+            grant.code_challenge_method,
+            grant.nonce,
+            expires_at,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn consume_code(
+        &self,
+        code: &AuthorizationCode,
+        client_id: &str,
+        redirect_uri: Option<&str>,
+        verifier: Option<&PkceCodeVerifier>,
+    ) -> Result<StoredAuthorizationCode, Error> {
+        let hashes = self.lookup_hashes(code.secret())?;
+
+        // DELETE ... RETURNING is the single-use guarantee: concurrent redemptions
+        // race on the row and only one of them gets it back.
+        let stored = sqlx::query_as!(
+            StoredAuthorizationCode,
+            r#"
+            DELETE FROM oauth2_authorization_codes
+            WHERE code_hash = ANY($1)
+            RETURNING *
+            "#,
+            &hashes
+        )
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or(Error::NotFound)?;
+
+        if stored.expires_at <= Utc::now() {
+            return Err(Error::InvalidGrant("authorization code expired".into()));
+        }
+
+        if stored.client_id != client_id {
+            return Err(Error::InvalidGrant("authorization code was issued to another client".into()));
+        }
+
+        if stored.redirect_uri.as_deref() != redirect_uri {
+            return Err(Error::InvalidGrant("redirect_uri mismatch".into()));
+        }
+
+        stored.verify_pkce(verifier)?;
+
+        Ok(stored)
+    }
+}
@@ -0,0 +1,362 @@
+//! Bearer-token authentication for axum resource servers (RFC 6750, enabled with the
+//! `axum` feature).
+//!
+//! [`BearerAuthLayer`] validates the `Authorization: Bearer` header of every request
+//! against an [`OAuth2TokenStore`] and hands the token to handlers as an
+//! [`AuthenticatedToken`]; [`RequireScopeLayer`] additionally guards routes by scope.
+//! Failures are answered with the RFC 6750 §3 `WWW-Authenticate` challenge.
+
+use async_trait::async_trait;
+use axum::{
+    extract::{FromRequestParts, Request},
+    http::{header, request::Parts, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tower_layer::Layer;
+use tower_service::Service;
+
+use crate::endpoints::ErrorBody;
+use crate::{OAuth2TokenStore, ScopeRules, StoredToken};
+
+const REALM: &str = "oauth2";
+
+/// Why a request was not let through, answered per RFC 6750 §3.1.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum BearerError {
+    /// No bearer token was presented. Answered with a bare challenge and no error code.
+    #[error("no bearer token")]
+    MissingToken,
+
+    /// The `Authorization` header was malformed or repeated.
+    #[error("malformed bearer credentials")]
+    InvalidRequest,
+
+    /// The token is unknown, expired or revoked.
+    #[error("invalid bearer token")]
+    InvalidToken,
+
+    /// The token is valid but lacks the listed scopes.
+    #[error("insufficient scope; requires {}", .0.join(" "))]
+    InsufficientScope(Vec<String>),
+
+    /// The token store failed.
+    #[error("token validation failed")]
+    ServerError,
+}
+
+impl IntoResponse for BearerError {
+    fn into_response(self) -> Response {
+        let (status, error) = match &self {
+            BearerError::MissingToken => (StatusCode::UNAUTHORIZED, None),
+            BearerError::InvalidRequest => (StatusCode::BAD_REQUEST, Some("invalid_request")),
+            BearerError::InvalidToken => (StatusCode::UNAUTHORIZED, Some("invalid_token")),
+            BearerError::InsufficientScope(_) => (StatusCode::FORBIDDEN, Some("insufficient_scope")),
+            BearerError::ServerError => {
+                return (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorBody { error: "server_error" }))
+                    .into_response();
+            }
+        };
+
+        let mut challenge = format!("Bearer realm=\"{REALM}\"");
+        if let Some(error) = error {
+            challenge.push_str(&format!(", error=\"{error}\""));
+        }
+        if let BearerError::InsufficientScope(scopes) = &self {
+            challenge.push_str(&format!(", scope=\"{}\"", scopes.join(" ")));
+        }
+
+        let mut response = match error {
+            Some(error) => (status, Json(ErrorBody { error })).into_response(),
+            None => status.into_response(),
+        };
+
+        // Scope tokens cannot contain quotes or backslashes (RFC 6749 §3.3), so this only
+        // fails on scopes the authorization server should never have issued.
+        let challenge = HeaderValue::from_str(&challenge)
+            .unwrap_or_else(|_| HeaderValue::from_static("Bearer realm=\"oauth2\""));
+        response
+            .headers_mut()
+            .insert(header::WWW_AUTHENTICATE, challenge);
+
+        response
+    }
+}
+
+/// The validated token of the current request, inserted by [`BearerAuthLayer`].
+///
+/// Extracting it from a route without the layer fails with a 500, since that is a
+/// wiring mistake rather than a client error.
+#[derive(Debug, Clone)]
+pub struct AuthenticatedToken(pub StoredToken);
+
+#[async_trait]
+impl<St: Send + Sync> FromRequestParts<St> for AuthenticatedToken {
+    type Rejection = BearerError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &St) -> Result<Self, Self::Rejection> {
+        parts.extensions.get::<AuthenticatedToken>().cloned().ok_or_else(|| {
+            tracing::error!("AuthenticatedToken extracted on a route without BearerAuthLayer");
+            BearerError::ServerError
+        })
+    }
+}
+
+/// Raw token of an `Authorization: Bearer` header (RFC 6750 §2.1).
+fn bearer_token(headers: &HeaderMap) -> Result<&str, BearerError> {
+    let mut values = headers.get_all(header::AUTHORIZATION).iter();
+    let Some(value) = values.next() else {
+        return Err(BearerError::MissingToken);
+    };
+    if values.next().is_some() {
+        return Err(BearerError::InvalidRequest);
+    }
+
+    let value = value.to_str().map_err(|_| BearerError::InvalidRequest)?;
+    let (scheme, token) = value.split_once(' ').unwrap_or((value, ""));
+
+    // Credentials for another scheme are not a bearer token at all.
+    if !scheme.eq_ignore_ascii_case("Bearer") {
+        return Err(BearerError::MissingToken);
+    }
+
+    let token = token.trim();
+    if token.is_empty() || token.contains(char::is_whitespace) {
+        return Err(BearerError::InvalidRequest);
+    }
+
+    Ok(token)
+}
+
+/// Validate the request's bearer token against `store`.
+async fn authenticate<S: OAuth2TokenStore>(
+    store: &S,
+    headers: &HeaderMap,
+) -> Result<StoredToken, BearerError> {
+    let token = oauth2::AccessToken::new(bearer_token(headers)?.to_string());
+
+    match store.get_by_access_token(&token).await {
+        Ok(Some(stored)) => Ok(stored),
+        Ok(None) => Err(BearerError::InvalidToken),
+        Err(error) => {
+            tracing::warn!(%error, "bearer token validation failed");
+            Err(BearerError::ServerError)
+        }
+    }
+}
+
+/// [`Layer`] requiring a valid bearer token on every request it wraps.
+///
+/// ```ignore
+/// let app = Router::new()
+///     .route("/orders", get(list_orders))
+///     .layer(BearerAuthLayer::new(store));
+///
+/// async fn list_orders(AuthenticatedToken(token): AuthenticatedToken) -> impl IntoResponse {
+///     format!("orders of {:?}", token.user_id)
+/// }
+/// ```
+pub struct BearerAuthLayer<S> {
+    store: Arc<S>,
+}
+
+impl<S> Clone for BearerAuthLayer<S> {
+    fn clone(&self) -> Self {
+        Self {
+            store: Arc::clone(&self.store),
+        }
+    }
+}
+
+impl<S: OAuth2TokenStore> BearerAuthLayer<S> {
+    /// Validate tokens against `store`.
+    pub fn new(store: Arc<S>) -> Self {
+        Self { store }
+    }
+}
+
+impl<S, I> Layer<I> for BearerAuthLayer<S> {
+    type Service = BearerAuth<S, I>;
+
+    fn layer(&self, inner: I) -> Self::Service {
+        BearerAuth {
+            store: Arc::clone(&self.store),
+            inner,
+        }
+    }
+}
+
+/// Service produced by [`BearerAuthLayer`].
+pub struct BearerAuth<S, I> {
+    store: Arc<S>,
+    inner: I,
+}
+
+impl<S, I: Clone> Clone for BearerAuth<S, I> {
+    fn clone(&self) -> Self {
+        Self {
+            store: Arc::clone(&self.store),
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<S, I> Service<Request> for BearerAuth<S, I>
+where
+    S: OAuth2TokenStore,
+    I: Service<Request, Response = Response> + Clone + Send + 'static,
+    I::Future: Send,
+{
+    type Response = Response;
+    type Error = I::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, I::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: Request) -> Self::Future {
+        let store = Arc::clone(&self.store);
+
+        // Only the service that was polled is known to be ready.
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+
+        Box::pin(async move {
+            match authenticate(store.as_ref(), request.headers()).await {
+                Ok(token) => {
+                    request.extensions_mut().insert(AuthenticatedToken(token));
+                    inner.call(request).await
+                }
+                Err(error) => Ok(error.into_response()),
+            }
+        })
+    }
+}
+
+/// Whether a token needs every listed scope or just one of them.
+#[derive(Debug, Clone, Copy)]
+enum ScopeRequirement {
+    All,
+    Any,
+}
+
+/// Route guard rejecting tokens that lack the required scopes with `403 insufficient_scope`.
+///
+/// It reads the [`AuthenticatedToken`] inserted by [`BearerAuthLayer`], which therefore
+/// has to wrap it:
+///
+/// ```ignore
+/// let app = Router::new()
+///     .route(
+///         "/orders",
+///         get(list_orders).merge(post(create_order).layer(RequireScopeLayer::all(["orders:write"]))),
+///     )
+///     .layer(BearerAuthLayer::new(store));
+/// ```
+#[derive(Clone)]
+pub struct RequireScopeLayer {
+    scopes: Arc<[String]>,
+    requirement: ScopeRequirement,
+    rules: Arc<ScopeRules>,
+}
+
+impl RequireScopeLayer {
+    /// Require every one of `scopes`.
+    pub fn all<I, T>(scopes: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<String>,
+    {
+        Self::new(scopes, ScopeRequirement::All)
+    }
+
+    /// Require at least one of `scopes`.
+    pub fn any<I, T>(scopes: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<String>,
+    {
+        Self::new(scopes, ScopeRequirement::Any)
+    }
+
+    fn new<I, T>(scopes: I, requirement: ScopeRequirement) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<String>,
+    {
+        Self {
+            scopes: scopes.into_iter().map(Into::into).collect(),
+            requirement,
+            rules: Arc::new(ScopeRules::exact()),
+        }
+    }
+
+    /// Match scopes under `rules` instead of exactly.
+    pub fn with_rules(mut self, rules: ScopeRules) -> Self {
+        self.rules = Arc::new(rules);
+        self
+    }
+
+    fn check(&self, token: Option<&AuthenticatedToken>) -> Result<(), BearerError> {
+        let Some(AuthenticatedToken(token)) = token else {
+            tracing::error!("RequireScopeLayer used on a route without BearerAuthLayer");
+            return Err(BearerError::ServerError);
+        };
+
+        let allowed = match self.requirement {
+            ScopeRequirement::All => token.has_all_with(&self.rules, &self.scopes),
+            ScopeRequirement::Any => token.has_any_with(&self.rules, &self.scopes),
+        };
+
+        if allowed {
+            Ok(())
+        } else {
+            Err(BearerError::InsufficientScope(self.scopes.to_vec()))
+        }
+    }
+}
+
+impl<I> Layer<I> for RequireScopeLayer {
+    type Service = RequireScope<I>;
+
+    fn layer(&self, inner: I) -> Self::Service {
+        RequireScope {
+            guard: self.clone(),
+            inner,
+        }
+    }
+}
+
+/// Service produced by [`RequireScopeLayer`].
+#[derive(Clone)]
+pub struct RequireScope<I> {
+    guard: RequireScopeLayer,
+    inner: I,
+}
+
+impl<I> Service<Request> for RequireScope<I>
+where
+    I: Service<Request, Response = Response> + Clone + Send + 'static,
+    I::Future: Send,
+{
+    type Response = Response;
+    type Error = I::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, I::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        if let Err(error) = self.guard.check(request.extensions().get()) {
+            return Box::pin(async move { Ok(error.into_response()) });
+        }
+
+        Box::pin(self.inner.call(request))
+    }
+}
@@ -0,0 +1,489 @@
+//! Read-through in-process cache in front of any [`OAuth2TokenStore`].
+
+use async_trait::async_trait;
+use chrono::Utc;
+use oauth2::{
+    AccessToken, ExtraTokenFields, RefreshToken, Scope, StandardTokenResponse, TokenResponse,
+    TokenType,
+};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+use crate::{Error, OAuth2TokenStore, PageRequest, StoredToken, TokenPage, TokenTypeHint};
+
+const DEFAULT_CAPACITY: usize = 10_000;
+const DEFAULT_TTL: Duration = Duration::from_secs(60);
+const DEFAULT_NEGATIVE_TTL: Duration = Duration::from_secs(5);
+
+/// Cache key for a raw access token. Only the BLAKE3 hash is kept in memory.
+pub(crate) fn cache_key(token: &str) -> String {
+    blake3::hash(token.as_bytes()).to_hex().to_string()
+}
+
+struct Entry {
+    /// `None` caches a miss.
+    token: Option<StoredToken>,
+    fresh_until: Instant,
+    /// Position in [`Lru::order`].
+    tick: u64,
+}
+
+/// Bounded map evicting the least recently used entry when full.
+struct Lru {
+    entries: HashMap<String, Entry>,
+    /// Recency order: oldest tick first.
+    order: BTreeMap<u64, String>,
+    /// Stored `access_token_hash` of each cached token to its cache key.
+    by_hash: HashMap<String, String>,
+    /// Row id of each cached token to its cache key.
+    by_id: HashMap<Uuid, String>,
+    /// Cache keys of the cached tokens of each family.
+    by_family: HashMap<Uuid, HashSet<String>>,
+    /// Bumped by every eviction for a revocation. A lookup only caches what it read from
+    /// the inner store if no revocation was evicted meanwhile: the row may predate it.
+    generation: u64,
+    next_tick: u64,
+    capacity: usize,
+}
+
+impl Lru {
+    fn new(capacity: usize) -> Self {
+        Self {
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+            by_hash: HashMap::new(),
+            by_id: HashMap::new(),
+            by_family: HashMap::new(),
+            generation: 0,
+            next_tick: 0,
+            capacity,
+        }
+    }
+
+    /// Cached lookup result for `key`; `None` on a miss or a stale entry.
+    ///
+    /// Entries whose token has passed `expires_at` or its idle deadline count as stale
+    /// regardless of TTL.
+    fn get(&mut self, key: &str) -> Option<Option<StoredToken>> {
+        let entry = self.entries.get(key)?;
+
+        let stale = entry.fresh_until <= Instant::now()
+            || entry
+                .token
+                .as_ref()
+                .and_then(StoredToken::usable_until)
+                .is_some_and(|e| e <= Utc::now());
+
+        if stale {
+            self.remove(key);
+            return None;
+        }
+
+        let tick = self.next_tick;
+        self.next_tick += 1;
+
+        let entry = self.entries.get_mut(key)?;
+        self.order.remove(&entry.tick);
+        self.order.insert(tick, key.to_string());
+        entry.tick = tick;
+
+        Some(entry.token.clone())
+    }
+
+    fn insert(&mut self, key: String, token: Option<StoredToken>, ttl: Duration) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        self.remove(&key);
+
+        while self.entries.len() >= self.capacity {
+            let Some((_, oldest)) = self.order.pop_first() else {
+                break;
+            };
+            self.remove(&oldest);
+        }
+
+        let tick = self.next_tick;
+        self.next_tick += 1;
+
+        self.order.insert(tick, key.clone());
+        if let Some(t) = &token {
+            self.index(t, &key);
+        }
+        self.entries.insert(
+            key,
+            Entry {
+                token,
+                fresh_until: Instant::now() + ttl,
+                tick,
+            },
+        );
+    }
+
+    fn index(&mut self, token: &StoredToken, key: &str) {
+        self.by_hash.insert(token.access_token_hash.clone(), key.to_string());
+        self.by_id.insert(token.id, key.to_string());
+        self.by_family
+            .entry(token.family_id)
+            .or_default()
+            .insert(key.to_string());
+    }
+
+    fn unindex(&mut self, token: &StoredToken, key: &str) {
+        self.by_hash.remove(&token.access_token_hash);
+        self.by_id.remove(&token.id);
+        if let Some(keys) = self.by_family.get_mut(&token.family_id) {
+            keys.remove(key);
+            if keys.is_empty() {
+                self.by_family.remove(&token.family_id);
+            }
+        }
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.order.remove(&entry.tick);
+            if let Some(t) = entry.token {
+                self.unindex(&t, key);
+            }
+        }
+    }
+
+    /// Drop the entry for `key` because its token was revoked or replaced.
+    fn invalidate(&mut self, key: &str) {
+        self.generation += 1;
+        self.remove(key);
+    }
+
+    /// Drop the cached token whose stored `access_token_hash` is `hash`.
+    fn remove_by_hash(&mut self, hash: &str) {
+        self.generation += 1;
+        if let Some(key) = self.by_hash.get(hash).cloned() {
+            self.remove(&key);
+        }
+    }
+
+    /// Drop the cached token with row id `id`.
+    fn remove_by_id(&mut self, id: Uuid) {
+        self.generation += 1;
+        if let Some(key) = self.by_id.get(&id).cloned() {
+            self.remove(&key);
+        }
+    }
+
+    /// Drop every cached token of a rotation family.
+    fn remove_family(&mut self, family_id: Uuid) {
+        self.generation += 1;
+        for key in self.by_family.remove(&family_id).unwrap_or_default() {
+            self.remove(&key);
+        }
+    }
+
+    /// Drop every cached token matching `evict`. Cached misses are kept.
+    fn evict_where(&mut self, evict: impl Fn(&StoredToken) -> bool) {
+        self.generation += 1;
+        let keys: Vec<String> = self
+            .entries
+            .iter()
+            .filter(|(_, entry)| entry.token.as_ref().is_some_and(&evict))
+            .map(|(key, _)| key.clone())
+            .collect();
+
+        for key in keys {
+            self.remove(&key);
+        }
+    }
+}
+
+/// Decorator caching [`OAuth2TokenStore::get_by_access_token`] results of another store.
+///
+/// Entries are keyed by a hash of the access token, held in a bounded LRU and expire
+/// after a TTL; a token is never served past its `expires_at`. Misses are cached too,
+/// for a shorter TTL. Writes and revocations made through this instance evict affected
+/// entries immediately; revocations by refresh token look the row up in the inner store
+/// (see [`OAuth2TokenStore::find_by_refresh_token`]) to find them. A lookup that was
+/// reading from the inner store while such an eviction happened does not cache its
+/// result. Revocations made elsewhere (another process or instance) are
+/// only seen once the entry's TTL runs out, unless the cache listens for them with
+/// [`CachedTokenStore::listen_for_revocations`].
+///
+/// Cache hits never reach the inner store, so they neither reset an idle timeout nor
+/// slide `expires_at`; keep the TTL well below the idle timeout.
+///
+/// Clones share the same cache.
+pub struct CachedTokenStore<S> {
+    inner: Arc<S>,
+    cache: Arc<Mutex<Lru>>,
+    ttl: Duration,
+    negative_ttl: Duration,
+}
+
+impl<S> Clone for CachedTokenStore<S> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: Arc::clone(&self.inner),
+            cache: Arc::clone(&self.cache),
+            ttl: self.ttl,
+            negative_ttl: self.negative_ttl,
+        }
+    }
+}
+
+impl<S: OAuth2TokenStore> CachedTokenStore<S> {
+    /// Wrap `inner` with a cache of up to 10 000 entries, a 60 second TTL and a
+    /// 5 second TTL for misses.
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner: Arc::new(inner),
+            cache: Arc::new(Mutex::new(Lru::new(DEFAULT_CAPACITY))),
+            ttl: DEFAULT_TTL,
+            negative_ttl: DEFAULT_NEGATIVE_TTL,
+        }
+    }
+
+    /// Hold at most `capacity` entries; `0` disables caching.
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.cache = Arc::new(Mutex::new(Lru::new(capacity)));
+        self
+    }
+
+    /// How long a found token is served from the cache.
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// How long a lookup that found nothing is served from the cache.
+    pub fn with_negative_ttl(mut self, ttl: Duration) -> Self {
+        self.negative_ttl = ttl;
+        self
+    }
+
+    /// The wrapped store, e.g. for its [`AuthorizationCodeStore`](crate::AuthorizationCodeStore)
+    /// implementation.
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// Drop every cached entry.
+    pub fn clear(&self) {
+        let mut cache = self.cache();
+        let mut cleared = Lru::new(cache.capacity);
+        cleared.generation = cache.generation + 1;
+        *cache = cleared;
+    }
+
+    /// Drop cached tokens by their stored `access_token_hash`, e.g. when another
+    /// instance announces a revocation.
+    pub fn invalidate_hashes<'a>(&self, hashes: impl IntoIterator<Item = &'a str>) {
+        let mut cache = self.cache();
+        for hash in hashes {
+            cache.remove_by_hash(hash);
+        }
+    }
+
+    fn cache(&self) -> MutexGuard<'_, Lru> {
+        // The cache is always consistent between statements, so a poisoned lock is safe to reuse.
+        self.cache.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Evict the cached tokens that a revocation matching `evict` may have revoked.
+    fn evict_where(&self, evict: impl Fn(&StoredToken) -> bool) {
+        self.cache().evict_where(evict);
+    }
+
+    /// Forget a family after reuse detection revoked it.
+    fn evict_on_reuse<T>(&self, result: &Result<T, Error>) {
+        if let Err(Error::RefreshTokenReuse(family_id)) = result {
+            self.cache().remove_family(*family_id);
+        }
+    }
+
+    /// Evict the entries a revocation of refresh token `token` may have revoked: the
+    /// token's own row, or its whole family if `family`.
+    ///
+    /// Entries are keyed by access token, so the row is looked up (without side effects)
+    /// in the inner store to find them.
+    async fn evict_refresh_token(&self, token: &RefreshToken, family: bool) {
+        match self.inner.find_by_refresh_token(token).await {
+            Ok(Some(row)) if family => self.cache().remove_family(row.family_id),
+            Ok(Some(row)) => self.cache().remove_by_id(row.id),
+            Ok(None) => {}
+            Err(error) => {
+                tracing::warn!(%error, "could not look up revoked refresh token; clearing cache");
+                self.clear();
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl<S: OAuth2TokenStore> OAuth2TokenStore for CachedTokenStore<S> {
+    async fn store_token<EF, TT>(
+        &self,
+        token: &StandardTokenResponse<EF, TT>,
+        client_id: &str,
+        user_id: Option<Uuid>,
+        scopes: &[Scope],
+        refresh_expires_in: Option<Duration>,
+    ) -> Result<(), Error>
+    where
+        EF: ExtraTokenFields + Sync,
+        TT: TokenType + Sync,
+    {
+        self.inner
+            .store_token(token, client_id, user_id, scopes, refresh_expires_in)
+            .await?;
+
+        // A cached miss for this token would hide it until the negative TTL runs out.
+        self.cache().invalidate(&cache_key(token.access_token().secret()));
+
+        Ok(())
+    }
+
+    async fn get_by_access_token(&self, token: &AccessToken) -> Result<Option<StoredToken>, Error> {
+        let key = cache_key(token.secret());
+
+        let generation = {
+            let mut cache = self.cache();
+            if let Some(cached) = cache.get(&key) {
+                return Ok(cached);
+            }
+            cache.generation
+        };
+
+        let found = self.inner.get_by_access_token(token).await?;
+
+        // A revocation evicted while the row was being read may predate it; caching it
+        // now would serve a revoked token for the whole TTL.
+        let mut cache = self.cache();
+        if cache.generation == generation {
+            let ttl = if found.is_some() { self.ttl } else { self.negative_ttl };
+            cache.insert(key, found.clone(), ttl);
+        }
+
+        Ok(found)
+    }
+
+    async fn get_by_refresh_token(&self, token: &RefreshToken) -> Result<Option<StoredToken>, Error> {
+        let result = self.inner.get_by_refresh_token(token).await;
+        self.evict_on_reuse(&result);
+        result
+    }
+
+    async fn find_by_access_token(&self, token: &AccessToken) -> Result<Option<StoredToken>, Error> {
+        self.inner.find_by_access_token(token).await
+    }
+
+    async fn find_by_refresh_token(&self, token: &RefreshToken) -> Result<Option<StoredToken>, Error> {
+        self.inner.find_by_refresh_token(token).await
+    }
+
+    async fn revoke_by_access_token(&self, token: &AccessToken) -> Result<(), Error> {
+        self.inner.revoke_by_access_token(token).await?;
+        self.cache().invalidate(&cache_key(token.secret()));
+        Ok(())
+    }
+
+    async fn revoke_by_refresh_token(&self, token: &RefreshToken) -> Result<(), Error> {
+        self.inner.revoke_by_refresh_token(token).await?;
+        self.evict_refresh_token(token, false).await;
+        Ok(())
+    }
+
+    async fn rotate_refresh_token<EF, TT>(
+        &self,
+        old: &RefreshToken,
+        new: &StandardTokenResponse<EF, TT>,
+        refresh_expires_in: Option<Duration>,
+    ) -> Result<StoredToken, Error>
+    where
+        EF: ExtraTokenFields + Sync,
+        TT: TokenType + Sync,
+    {
+        let result = self
+            .inner
+            .rotate_refresh_token(old, new, refresh_expires_in)
+            .await;
+        self.evict_on_reuse(&result);
+
+        let stored = result?;
+
+        // The rotated-out row shares the new row's family and is now revoked.
+        let mut cache = self.cache();
+        cache.remove_family(stored.family_id);
+        cache.invalidate(&cache_key(new.access_token().secret()));
+
+        Ok(stored)
+    }
+
+    async fn revoke(
+        &self,
+        token: &str,
+        token_type_hint: Option<TokenTypeHint>,
+        client_id: &str,
+    ) -> Result<(), Error> {
+        self.inner.revoke(token, token_type_hint, client_id).await?;
+
+        let key = cache_key(token);
+        let cached_access_token = {
+            let mut cache = self.cache();
+            let cached = cache.entries.get(&key).is_some_and(|e| e.token.is_some());
+            cache.invalidate(&key);
+            cached
+        };
+
+        if !cached_access_token {
+            // Possibly a refresh token, whose whole family was revoked.
+            self.evict_refresh_token(&RefreshToken::new(token.to_string()), true)
+                .await;
+        }
+
+        Ok(())
+    }
+
+    async fn list_tokens_for_user(&self, user_id: Uuid, page: PageRequest) -> Result<TokenPage, Error> {
+        self.inner.list_tokens_for_user(user_id, page).await
+    }
+
+    async fn revoke_by_id(&self, id: Uuid) -> Result<(), Error> {
+        self.inner.revoke_by_id(id).await?;
+        self.cache().remove_by_id(id);
+        Ok(())
+    }
+
+    async fn revoke_all_for_user(&self, user_id: Uuid, except: Option<Uuid>) -> Result<usize, Error> {
+        let revoked = self.inner.revoke_all_for_user(user_id, except).await?;
+        self.evict_where(|t| t.user_id == Some(user_id) && Some(t.id) != except);
+        Ok(revoked)
+    }
+
+    async fn revoke_all_for_client(&self, client_id: &str, except: Option<Uuid>) -> Result<usize, Error> {
+        let revoked = self.inner.revoke_all_for_client(client_id, except).await?;
+        self.evict_where(|t| t.client_id == client_id && Some(t.id) != except);
+        Ok(revoked)
+    }
+
+    async fn revoke_all_for_user_and_client(
+        &self,
+        user_id: Uuid,
+        client_id: &str,
+        except: Option<Uuid>,
+    ) -> Result<usize, Error> {
+        let revoked = self
+            .inner
+            .revoke_all_for_user_and_client(user_id, client_id, except)
+            .await?;
+        self.evict_where(|t| {
+            t.user_id == Some(user_id) && t.client_id == client_id && Some(t.id) != except
+        });
+        Ok(revoked)
+    }
+
+    async fn cleanup(&self) -> Result<usize, Error> {
+        self.inner.cleanup().await
+    }
+}
@@ -0,0 +1,570 @@
+//! Batched, throttled cleanup for large Postgres tables.
+//!
+//! [`OAuth2TokenStore::cleanup`](crate::OAuth2TokenStore::cleanup) deletes everything in
+//! one statement. [`PgTokenStore::cleanup_batched`] deletes the same rows a bounded batch
+//! at a time, pausing in between so vacuum and replication keep up, and can keep revoked
+//! and expired rows around for a retention period first. [`CleanupTask`] runs it on a
+//! schedule.
+
+use chrono::{DateTime, TimeDelta, Utc};
+use rand::Rng;
+use sqlx::Connection;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use tokio::task::JoinHandle;
+
+use crate::{Error, PgTokenStore};
+
+const DEFAULT_BATCH_SIZE: u32 = 1000;
+const DEFAULT_PAUSE: Duration = Duration::from_millis(50);
+
+/// Session advisory lock taken by [`CleanupTask`] runs (`"oauth2cl"` in ASCII).
+const DEFAULT_LOCK_KEY: i64 = 0x6f61_7574_6832_636c;
+
+/// How [`PgTokenStore::cleanup_batched`] paces its deletes.
+#[derive(Debug, Clone, Copy)]
+pub struct CleanupOptions {
+    batch_size: u32,
+    pause: Duration,
+    retention: Duration,
+}
+
+impl Default for CleanupOptions {
+    fn default() -> Self {
+        Self {
+            batch_size: DEFAULT_BATCH_SIZE,
+            pause: DEFAULT_PAUSE,
+            retention: Duration::ZERO,
+        }
+    }
+}
+
+impl CleanupOptions {
+    /// 1000 rows per batch, 50 ms apart, no retention.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Delete at most `rows` (at least 1) rows per statement.
+    pub fn with_batch_size(mut self, rows: u32) -> Self {
+        self.batch_size = rows.max(1);
+        self
+    }
+
+    /// Sleep for `pause` after every full batch.
+    pub fn with_pause(mut self, pause: Duration) -> Self {
+        self.pause = pause;
+        self
+    }
+
+    /// Keep rows for `retention` after they were revoked or expired, e.g. for audits.
+    ///
+    /// Revoked tokens count from `revoked_at`, expired ones from the later of their
+    /// access- and refresh-token expiry (or their idle deadline), and codes from
+    /// `expires_at`.
+    pub fn with_retention(mut self, retention: Duration) -> Self {
+        self.retention = retention;
+        self
+    }
+}
+
+/// Table a cleanup batch deleted from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CleanupTable {
+    Tokens,
+    AuthorizationCodes,
+    DeviceCodes,
+}
+
+impl CleanupTable {
+    /// The table's name in the schema.
+    pub fn name(self) -> &'static str {
+        match self {
+            CleanupTable::Tokens => "oauth2_tokens",
+            CleanupTable::AuthorizationCodes => "oauth2_authorization_codes",
+            CleanupTable::DeviceCodes => "oauth2_device_codes",
+        }
+    }
+}
+
+/// Progress after one batch, handed to the `on_batch` callback of
+/// [`PgTokenStore::cleanup_batched`].
+#[derive(Debug, Clone, Copy)]
+pub struct CleanupProgress {
+    pub table: CleanupTable,
+    /// 1-based number of this batch within the run, across all tables.
+    pub batch: u32,
+    /// Rows this batch deleted.
+    pub deleted: u64,
+    /// Rows deleted from `table` so far in this run.
+    pub table_deleted: u64,
+    /// How long this batch's statement took.
+    pub elapsed: Duration,
+}
+
+/// Totals of one [`PgTokenStore::cleanup_batched`] run.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CleanupReport {
+    pub tokens: u64,
+    pub authorization_codes: u64,
+    pub device_codes: u64,
+    /// Statements run, including the last, partial batch of each table.
+    pub batches: u32,
+    pub elapsed: Duration,
+}
+
+impl CleanupReport {
+    /// Rows deleted from every table.
+    pub fn total(&self) -> u64 {
+        self.tokens + self.authorization_codes + self.device_codes
+    }
+}
+
+impl PgTokenStore {
+    /// Remove the rows [`cleanup`](crate::OAuth2TokenStore::cleanup) would, in batches.
+    ///
+    /// Each table is drained with `DELETE`s of at most `options`' batch size, sleeping
+    /// between full batches, so no statement holds many row locks or builds up a large
+    /// transaction. Rows already locked by another transaction (or a concurrent cleanup)
+    /// are skipped until the next run. `on_batch` is called after every batch, which is
+    /// also logged at debug level.
+    pub async fn cleanup_batched<F>(
+        &self,
+        options: &CleanupOptions,
+        on_batch: F,
+    ) -> Result<CleanupReport, Error>
+    where
+        F: FnMut(&CleanupProgress) + Send,
+    {
+        self.cleanup_batched_until(options, on_batch, &AtomicBool::new(false))
+            .await
+    }
+
+    /// [`PgTokenStore::cleanup_batched`] that checks `stop` between batches and returns
+    /// early, with what was deleted so far, once it is set.
+    async fn cleanup_batched_until<F>(
+        &self,
+        options: &CleanupOptions,
+        mut on_batch: F,
+        stop: &AtomicBool,
+    ) -> Result<CleanupReport, Error>
+    where
+        F: FnMut(&CleanupProgress) + Send,
+    {
+        let started = Instant::now();
+
+        // Fixed for the whole run, so rows expiring meanwhile cannot keep it going.
+        let cutoff = TimeDelta::from_std(options.retention)
+            .ok()
+            .and_then(|retention| Utc::now().checked_sub_signed(retention))
+            .unwrap_or(DateTime::<Utc>::MIN_UTC);
+
+        let mut report = CleanupReport::default();
+
+        for table in [
+            CleanupTable::Tokens,
+            CleanupTable::AuthorizationCodes,
+            CleanupTable::DeviceCodes,
+        ] {
+            if stop.load(Ordering::Relaxed) {
+                break;
+            }
+            let mut table_deleted = 0;
+
+            loop {
+                let batch_started = Instant::now();
+                let deleted = self.delete_batch(table, cutoff, options.batch_size).await?;
+                table_deleted += deleted;
+                report.batches += 1;
+
+                let progress = CleanupProgress {
+                    table,
+                    batch: report.batches,
+                    deleted,
+                    table_deleted,
+                    elapsed: batch_started.elapsed(),
+                };
+                tracing::debug!(
+                    table = table.name(),
+                    batch = progress.batch,
+                    deleted,
+                    table_deleted,
+                    elapsed_ms = progress.elapsed.as_millis() as u64,
+                    "cleanup batch"
+                );
+                on_batch(&progress);
+
+                if deleted < u64::from(options.batch_size) || stop.load(Ordering::Relaxed) {
+                    break;
+                }
+                tokio::time::sleep(options.pause).await;
+            }
+
+            match table {
+                CleanupTable::Tokens => report.tokens = table_deleted,
+                CleanupTable::AuthorizationCodes => report.authorization_codes = table_deleted,
+                CleanupTable::DeviceCodes => report.device_codes = table_deleted,
+            }
+        }
+
+        report.elapsed = started.elapsed();
+        Ok(report)
+    }
+
+    /// Delete up to `limit` removable rows of `table` that became removable before `cutoff`.
+    async fn delete_batch(
+        &self,
+        table: CleanupTable,
+        cutoff: DateTime<Utc>,
+        limit: u32,
+    ) -> Result<u64, Error> {
+        let limit = i64::from(limit);
+
+        let res = match table {
+            CleanupTable::Tokens => {
+                // Each kind of dead token has its own statement and index, so no batch
+                // rescans rows that are not removable yet. Fill the batch kind by kind.
+                let mut deleted = 0;
+                for kind in DeadToken::ALL {
+                    let remaining = limit - deleted as i64;
+                    if remaining == 0 {
+                        break;
+                    }
+                    deleted += self.delete_dead_tokens(kind, cutoff, remaining).await?;
+                }
+                return Ok(deleted);
+            }
+            CleanupTable::AuthorizationCodes => {
+                sqlx::query!(
+                    r#"
+                    DELETE FROM oauth2_authorization_codes
+                    WHERE id IN (
+                        SELECT id FROM oauth2_authorization_codes
+                        WHERE expires_at < $1
+                        LIMIT $2
+                        FOR UPDATE SKIP LOCKED
+                    )
+                    "#,
+                    cutoff,
+                    limit,
+                )
+                .execute(&self.pool)
+                .await?
+            }
+            CleanupTable::DeviceCodes => {
+                sqlx::query!(
+                    r#"
+                    DELETE FROM oauth2_device_codes
+                    WHERE id IN (
+                        SELECT id FROM oauth2_device_codes
+                        WHERE expires_at < $1
+                        LIMIT $2
+                        FOR UPDATE SKIP LOCKED
+                    )
+                    "#,
+                    cutoff,
+                    limit,
+                )
+                .execute(&self.pool)
+                .await?
+            }
+        };
+
+        Ok(res.rows_affected())
+    }
+
+    /// Delete up to `limit` tokens of one [`DeadToken`] kind that became removable before
+    /// `cutoff`, in the order of the kind's index.
+    async fn delete_dead_tokens(
+        &self,
+        kind: DeadToken,
+        cutoff: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<u64, Error> {
+        let res = match kind {
+            DeadToken::Revoked => {
+                sqlx::query!(
+                    r#"
+                    DELETE FROM oauth2_tokens
+                    WHERE id IN (
+                        SELECT id FROM oauth2_tokens
+                        WHERE revoked AND rotated_at IS NULL
+                          AND revoked_at < $1
+                        ORDER BY revoked_at
+                        LIMIT $2
+                        FOR UPDATE SKIP LOCKED
+                    )
+                    "#,
+                    cutoff,
+                    limit,
+                )
+                .execute(&self.pool)
+                .await?
+            }
+            DeadToken::Rotated => {
+                // Kept for reuse detection until the refresh token expires, for good if it
+                // never does. Rows whose refresh token expired before `cutoff` were rotated
+                // before it too, so in this order the removable rows come first.
+                sqlx::query!(
+                    r#"
+                    DELETE FROM oauth2_tokens
+                    WHERE id IN (
+                        SELECT id FROM oauth2_tokens
+                        WHERE rotated_at IS NOT NULL
+                          AND refresh_expires_at < NOW()
+                          AND COALESCE(revoked_at, rotated_at) < $1
+                        ORDER BY refresh_expires_at
+                        LIMIT $2
+                        FOR UPDATE SKIP LOCKED
+                    )
+                    "#,
+                    cutoff,
+                    limit,
+                )
+                .execute(&self.pool)
+                .await?
+            }
+            DeadToken::Expired => {
+                sqlx::query!(
+                    r#"
+                    DELETE FROM oauth2_tokens
+                    WHERE id IN (
+                        SELECT id FROM oauth2_tokens
+                        WHERE refresh_token_hash IS NULL
+                          AND expires_at < $1
+                        ORDER BY expires_at
+                        LIMIT $2
+                        FOR UPDATE SKIP LOCKED
+                    )
+                    "#,
+                    cutoff,
+                    limit,
+                )
+                .execute(&self.pool)
+                .await?
+            }
+            DeadToken::RefreshExpired => {
+                sqlx::query!(
+                    r#"
+                    DELETE FROM oauth2_tokens
+                    WHERE id IN (
+                        SELECT id FROM oauth2_tokens
+                        WHERE refresh_expires_at < $1
+                          AND expires_at < $1
+                        ORDER BY refresh_expires_at
+                        LIMIT $2
+                        FOR UPDATE SKIP LOCKED
+                    )
+                    "#,
+                    cutoff,
+                    limit,
+                )
+                .execute(&self.pool)
+                .await?
+            }
+            DeadToken::IdledOut => {
+                // Rotated rows stay for reuse detection, see `Rotated`.
+                sqlx::query!(
+                    r#"
+                    DELETE FROM oauth2_tokens
+                    WHERE id IN (
+                        SELECT id FROM oauth2_tokens
+                        WHERE rotated_at IS NULL
+                          AND idle_expires_at < $1
+                        ORDER BY idle_expires_at
+                        LIMIT $2
+                        FOR UPDATE SKIP LOCKED
+                    )
+                    "#,
+                    cutoff,
+                    limit,
+                )
+                .execute(&self.pool)
+                .await?
+            }
+        };
+
+        Ok(res.rows_affected())
+    }
+}
+
+/// The kinds of token rows [`PgTokenStore::cleanup_batched`] removes, each found through
+/// its own index.
+#[derive(Debug, Clone, Copy)]
+enum DeadToken {
+    /// Revoked and not rotated, by `revoked_at`.
+    Revoked,
+    /// Rotated, once the refresh token expired, by `refresh_expires_at`.
+    Rotated,
+    /// Access token expired and no refresh token, by `expires_at`.
+    Expired,
+    /// Access and refresh token expired, by `refresh_expires_at`.
+    RefreshExpired,
+    /// Past the idle deadline and not rotated, by `idle_expires_at`.
+    IdledOut,
+}
+
+impl DeadToken {
+    const ALL: [DeadToken; 5] = [
+        DeadToken::Revoked,
+        DeadToken::Rotated,
+        DeadToken::Expired,
+        DeadToken::RefreshExpired,
+        DeadToken::IdledOut,
+    ];
+}
+
+/// Background job running [`PgTokenStore::cleanup_batched`] every interval.
+///
+/// Each run first takes a Postgres session advisory lock on a dedicated connection, so
+/// with several replicas only one cleans at a time and the others skip that run. The
+/// lock goes away with the connection, even if the task is aborted mid-run. Runs are
+/// logged with their removed counts: `info` on completion, `warn` on failure.
+///
+/// ```ignore
+/// let cleanup = CleanupTask::new(store.clone(), Duration::from_secs(15 * 60))
+///     .with_jitter(Duration::from_secs(60))
+///     .with_options(CleanupOptions::new().with_retention(Duration::from_secs(90 * 86_400)))
+///     .spawn(async {
+///         let _ = tokio::signal::ctrl_c().await;
+///     });
+///
+/// // ... on shutdown, wait for the current batch to finish
+/// cleanup.await?;
+/// ```
+#[derive(Clone)]
+pub struct CleanupTask {
+    store: PgTokenStore,
+    interval: Duration,
+    jitter: Duration,
+    options: CleanupOptions,
+    lock_key: i64,
+}
+
+impl CleanupTask {
+    /// Clean `store` every `interval`, plus up to a tenth of it as random jitter.
+    pub fn new(store: PgTokenStore, interval: Duration) -> Self {
+        Self {
+            store,
+            interval,
+            jitter: interval / 10,
+            options: CleanupOptions::default(),
+            lock_key: DEFAULT_LOCK_KEY,
+        }
+    }
+
+    /// Wait a random extra delay of up to `jitter` before every run, so replicas started
+    /// together do not all wake at once.
+    pub fn with_jitter(mut self, jitter: Duration) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Batch size, pause and retention of each run.
+    pub fn with_options(mut self, options: CleanupOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Use another advisory lock key, e.g. to clean several schemas in one database
+    /// independently.
+    pub fn with_lock_key(mut self, key: i64) -> Self {
+        self.lock_key = key;
+        self
+    }
+
+    /// Run on the current tokio runtime until `shutdown` completes.
+    ///
+    /// A run in progress when `shutdown` completes stops after its current batch; the
+    /// returned handle resolves once it has.
+    pub fn spawn<F>(self, shutdown: F) -> JoinHandle<()>
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        tokio::spawn(async move {
+            tokio::pin!(shutdown);
+
+            loop {
+                tokio::select! {
+                    _ = &mut shutdown => break,
+                    _ = tokio::time::sleep(self.next_delay()) => {}
+                }
+
+                let stop = AtomicBool::new(false);
+                let run = self.run_until(&stop);
+                tokio::pin!(run);
+
+                let stopping = tokio::select! {
+                    _ = &mut run => false,
+                    _ = &mut shutdown => {
+                        stop.store(true, Ordering::Relaxed);
+                        run.await;
+                        true
+                    }
+                };
+
+                if stopping {
+                    break;
+                }
+            }
+
+            tracing::debug!("token cleanup task stopped");
+        })
+    }
+
+    /// Run once now. Returns `None` if another instance holds the lock.
+    pub async fn run_once(&self) -> Result<Option<CleanupReport>, Error> {
+        self.try_run(&AtomicBool::new(false)).await
+    }
+
+    fn next_delay(&self) -> Duration {
+        if self.jitter.is_zero() {
+            return self.interval;
+        }
+        self.interval + rand::thread_rng().gen_range(Duration::ZERO..self.jitter)
+    }
+
+    /// One scheduled run, logging its outcome.
+    async fn run_until(&self, stop: &AtomicBool) {
+        match self.try_run(stop).await {
+            Ok(Some(report)) => tracing::info!(
+                tokens = report.tokens,
+                authorization_codes = report.authorization_codes,
+                device_codes = report.device_codes,
+                batches = report.batches,
+                elapsed_ms = report.elapsed.as_millis() as u64,
+                "token cleanup finished"
+            ),
+            Ok(None) => tracing::debug!("token cleanup skipped; another instance holds the lock"),
+            Err(error) => tracing::warn!(%error, "token cleanup failed"),
+        }
+    }
+
+    async fn try_run(&self, stop: &AtomicBool) -> Result<Option<CleanupReport>, Error> {
+        // Taken out of the pool so the session, and with it the lock, ends when it is dropped.
+        let mut conn = self.store.pool.acquire().await?.detach();
+
+        let locked = sqlx::query_scalar!(
+            r#"SELECT pg_try_advisory_lock($1) AS "locked!""#,
+            self.lock_key
+        )
+        .fetch_one(&mut conn)
+        .await?;
+
+        if !locked {
+            let _ = conn.close().await;
+            return Ok(None);
+        }
+
+        let report = self
+            .store
+            .cleanup_batched_until(&self.options, |_| {}, stop)
+            .await;
+
+        // Closing the session releases the lock.
+        let _ = conn.close().await;
+
+        report.map(Some)
+    }
+}
@@ -0,0 +1,385 @@
+//! Device authorization grant storage (RFC 8628).
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use oauth2::{DeviceCode, Scope, UserCode};
+use rand::Rng;
+use sqlx::FromRow;
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::{Error, PgTokenStore};
+
+/// Characters used in generated user codes: consonants only, so codes cannot spell
+/// words and are easy to read out (RFC 8628 §6.1).
+const USER_CODE_ALPHABET: &[u8] = b"BCDFGHJKLMNPQRSTVWXZ";
+
+/// RFC 8628 §3.5: every `slow_down` response adds 5 seconds to the polling interval.
+const SLOW_DOWN_STEP_SECS: i32 = 5;
+
+/// User codes [`DeviceCodeStore::issue_device_code`] tries before giving up. With 20^8
+/// possible codes, even a few collisions in a row mean something else is wrong.
+const USER_CODE_ATTEMPTS: usize = 5;
+
+/// Unique constraint on `oauth2_device_codes.user_code`.
+const USER_CODE_CONSTRAINT: &str = "oauth2_device_codes_user_code_key";
+
+/// Generate an 8-character user code formatted as `XXXX-XXXX`.
+pub fn generate_user_code() -> UserCode {
+    let mut rng = rand::thread_rng();
+    let chars: String = (0..8)
+        .map(|_| USER_CODE_ALPHABET[rng.gen_range(0..USER_CODE_ALPHABET.len())] as char)
+        .collect();
+
+    UserCode::new(format!("{}-{}", &chars[..4], &chars[4..]))
+}
+
+/// Canonical form of a user code as typed by a person: uppercase, without
+/// separators or whitespace.
+fn normalize_user_code(user_code: &str) -> String {
+    user_code
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_uppercase())
+        .collect()
+}
+
+/// Where a device authorization stands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, sqlx::Type)]
+#[sqlx(type_name = "text", rename_all = "lowercase")]
+pub enum DeviceCodeStatus {
+    Pending,
+    Approved,
+    Denied,
+}
+
+/// A stored device authorization record.
+#[derive(Debug, Clone, FromRow)]
+pub struct StoredDeviceCode {
+    pub id: Uuid,
+    pub device_code_hash: String,
+    /// Normalized user code (see [`DeviceCodeStore::find_by_user_code`]).
+    pub user_code: String,
+    pub client_id: String,
+    pub scopes: Vec<String>,
+    pub status: DeviceCodeStatus,
+    /// The user who approved or denied the request.
+    pub user_id: Option<Uuid>,
+    /// Minimum seconds between polls; grows on every `slow_down`.
+    pub interval_secs: i32,
+    pub last_polled_at: Option<DateTime<Utc>>,
+    pub issued_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Outcome of a token-endpoint poll with a device code (RFC 8628 §3.5).
+#[derive(Debug, Clone)]
+pub enum DevicePoll {
+    /// The user has not acted yet (`authorization_pending`).
+    Pending,
+    /// The client polled before `interval` elapsed (`slow_down`); `interval` is the new,
+    /// increased interval.
+    SlowDown { interval: Duration },
+    /// The user approved. Issue tokens for this grant; the device code is consumed.
+    Approved(StoredDeviceCode),
+    /// The user denied the request (`access_denied`). The device code is consumed.
+    Denied,
+    /// The device code expired before the user acted (`expired_token`).
+    Expired,
+}
+
+/// Storage for device authorization requests.
+#[async_trait]
+pub trait DeviceCodeStore: Send + Sync + 'static {
+    /// Persist a new device authorization. The device code is hashed; the user code is
+    /// stored normalized so it can be looked up as the user types it.
+    ///
+    /// Fails with [`Error::UserCodeTaken`] if an unexpired request already has this user
+    /// code. Expired requests give theirs up.
+    async fn store_device_code(
+        &self,
+        device_code: &DeviceCode,
+        user_code: &UserCode,
+        client_id: &str,
+        scopes: &[Scope],
+        expires_in: Duration,
+        interval: Duration,
+    ) -> Result<(), Error>;
+
+    /// Persist a new device authorization under a user code from [`generate_user_code`],
+    /// generating a new one while the code is taken. Returns the stored user code.
+    async fn issue_device_code(
+        &self,
+        device_code: &DeviceCode,
+        client_id: &str,
+        scopes: &[Scope],
+        expires_in: Duration,
+        interval: Duration,
+    ) -> Result<UserCode, Error> {
+        for _ in 1..USER_CODE_ATTEMPTS {
+            let user_code = generate_user_code();
+            match self
+                .store_device_code(device_code, &user_code, client_id, scopes, expires_in, interval)
+                .await
+            {
+                Err(Error::UserCodeTaken) => continue,
+                result => return result.map(|()| user_code),
+            }
+        }
+
+        let user_code = generate_user_code();
+        self.store_device_code(device_code, &user_code, client_id, scopes, expires_in, interval)
+            .await?;
+        Ok(user_code)
+    }
+
+    /// Find a pending, unexpired request by user code (for the verification page).
+    async fn find_by_user_code(&self, user_code: &str) -> Result<Option<StoredDeviceCode>, Error>;
+
+    /// Approve a pending request on behalf of `user_id`.
+    ///
+    /// Fails with [`Error::NotFound`] if no pending, unexpired request has this user code.
+    async fn approve(&self, user_code: &str, user_id: Uuid) -> Result<(), Error>;
+
+    /// Deny a pending request on behalf of `user_id`.
+    ///
+    /// Fails with [`Error::NotFound`] if no pending, unexpired request has this user code.
+    async fn deny(&self, user_code: &str, user_id: Uuid) -> Result<(), Error>;
+
+    /// Poll with a device code, enforcing the polling interval.
+    ///
+    /// Fails with [`Error::NotFound`] if the device code is unknown, already consumed, or
+    /// expired and its user code since reused, and [`Error::InvalidGrant`] if it belongs
+    /// to another client.
+    async fn poll(&self, device_code: &DeviceCode, client_id: &str) -> Result<DevicePoll, Error>;
+}
+
+impl PgTokenStore {
+    /// Move a pending request to `status`, recording who decided.
+    async fn decide_device_code(
+        &self,
+        user_code: &str,
+        user_id: Uuid,
+        status: DeviceCodeStatus,
+    ) -> Result<(), Error> {
+        let res = sqlx::query!(
+            r#"
+            UPDATE oauth2_device_codes
+            SET status = $3, user_id = $2
+            WHERE user_code = $1
+              AND status = 'pending'
+              AND expires_at > NOW()
+            "#,
+            normalize_user_code(user_code),
+            user_id,
+            status as DeviceCodeStatus,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        if res.rows_affected() == 0 {
+            return Err(Error::NotFound);
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl DeviceCodeStore for PgTokenStore {
+    async fn store_device_code(
+        &self,
+        device_code: &DeviceCode,
+        user_code: &UserCode,
+        client_id: &str,
+        scopes: &[Scope],
+        expires_in: Duration,
+        interval: Duration,
+    ) -> Result<(), Error> {
+        let hash = self.hash_token(device_code.secret())?;
+        let scopes_str: Vec<String> = scopes.iter().map(|s| s.to_string()).collect();
+        let expires_at = Utc::now() + expires_in;
+        let interval_secs = i32::try_from(interval.as_secs())
+            .map_err(|e| Error::Other(Box::new(e)))?;
+        let user_code = normalize_user_code(user_code.secret());
+
+        let mut tx = self.pool.begin().await?;
+
+        // An expired request can no longer be approved; free its user code rather than
+        // wait for cleanup.
+        sqlx::query!(
+            r#"
+            DELETE FROM oauth2_device_codes
+            WHERE user_code = $1 AND expires_at <= NOW()
+            "#,
+            user_code,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO oauth2_device_codes (
+                device_code_hash,
+                user_code,
+                client_id,
+                scopes,
+                status,
+                interval_secs,
+                issued_at,
+                expires_at
+            ) VALUES ($1, $2, $3, $4, 'pending', $5, NOW(), $6)
+            "#,
+            hash,
+            user_code,
+            client_id,
+            &scopes_str,
+            interval_secs,
+            expires_at,
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::Database(db) if db.constraint() == Some(USER_CODE_CONSTRAINT) => {
+                Error::UserCodeTaken
+            }
+            e => e.into(),
+        })?;
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn find_by_user_code(&self, user_code: &str) -> Result<Option<StoredDeviceCode>, Error> {
+        let row = sqlx::query_as!(
+            StoredDeviceCode,
+            r#"
+            SELECT
+                id,
+                device_code_hash,
+                user_code,
+                client_id,
+                scopes,
+                status AS "status: DeviceCodeStatus",
+                user_id,
+                interval_secs,
+                last_polled_at,
+                issued_at,
+                expires_at
+            FROM oauth2_device_codes
+            WHERE user_code = $1
+              AND status = 'pending'
+              AND expires_at > NOW()
+            "#,
+            normalize_user_code(user_code)
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row)
+    }
+
+    async fn approve(&self, user_code: &str, user_id: Uuid) -> Result<(), Error> {
+        self.decide_device_code(user_code, user_id, DeviceCodeStatus::Approved)
+            .await
+    }
+
+    async fn deny(&self, user_code: &str, user_id: Uuid) -> Result<(), Error> {
+        self.decide_device_code(user_code, user_id, DeviceCodeStatus::Denied)
+            .await
+    }
+
+    async fn poll(&self, device_code: &DeviceCode, client_id: &str) -> Result<DevicePoll, Error> {
+        let hashes = self.lookup_hashes(device_code.secret())?;
+
+        let mut tx = self.pool.begin().await?;
+
+        let current = sqlx::query_as!(
+            StoredDeviceCode,
+            r#"
+            SELECT
+                id,
+                device_code_hash,
+                user_code,
+                client_id,
+                scopes,
+                status AS "status: DeviceCodeStatus",
+                user_id,
+                interval_secs,
+                last_polled_at,
+                issued_at,
+                expires_at
+            FROM oauth2_device_codes
+            WHERE device_code_hash = ANY($1)
+            FOR UPDATE
+            "#,
+            &hashes
+        )
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(Error::NotFound)?;
+
+        if current.client_id != client_id {
+            return Err(Error::InvalidGrant("device code was issued to another client".into()));
+        }
+
+        let now = Utc::now();
+
+        if current.expires_at <= now {
+            return Ok(DevicePoll::Expired);
+        }
+
+        if current.status != DeviceCodeStatus::Pending {
+            sqlx::query!(
+                r#"
+                DELETE FROM oauth2_device_codes
+                WHERE id = $1
+                "#,
+                current.id
+            )
+            .execute(&mut *tx)
+            .await?;
+
+            tx.commit().await?;
+
+            return Ok(match current.status {
+                DeviceCodeStatus::Approved => DevicePoll::Approved(current),
+                _ => DevicePoll::Denied,
+            });
+        }
+
+        let too_fast = current.last_polled_at.is_some_and(|last| {
+            now - last < chrono::Duration::seconds(current.interval_secs.into())
+        });
+
+        let interval_secs = if too_fast {
+            current.interval_secs + SLOW_DOWN_STEP_SECS
+        } else {
+            current.interval_secs
+        };
+
+        sqlx::query!(
+            r#"
+            UPDATE oauth2_device_codes
+            SET last_polled_at = $2, interval_secs = $3
+            WHERE id = $1
+            "#,
+            current.id,
+            now,
+            interval_secs,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        if too_fast {
+            Ok(DevicePoll::SlowDown {
+                interval: Duration::from_secs(interval_secs as u64),
+            })
+        } else {
+            Ok(DevicePoll::Pending)
+        }
+    }
+}
@@ -0,0 +1,235 @@
+//! Ready-made axum routers for OAuth2 token endpoints (enabled with the `axum` feature).
+//!
+//! Every endpoint requires client authentication, either HTTP Basic
+//! (`client_secret_basic`) or `client_id`/`client_secret` form fields
+//! (`client_secret_post`). Verifying the credentials is delegated to a
+//! [`ClientAuthenticator`], since this crate does not manage client registrations.
+
+use async_trait::async_trait;
+use axum::{
+    extract::State,
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::post,
+    Form, Json, Router,
+};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use percent_encoding::percent_decode_str;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::{Error, OAuth2TokenStore, TokenTypeHint};
+
+/// Verifies the credentials of the client calling an endpoint.
+#[async_trait]
+pub trait ClientAuthenticator: Send + Sync + 'static {
+    /// Return `true` if `client_secret` is valid for `client_id`.
+    async fn authenticate(&self, client_id: &str, client_secret: Option<&str>) -> Result<bool, Error>;
+}
+
+struct EndpointState<S, A> {
+    store: Arc<S>,
+    authenticator: Arc<A>,
+}
+
+impl<S, A> Clone for EndpointState<S, A> {
+    fn clone(&self) -> Self {
+        Self {
+            store: Arc::clone(&self.store),
+            authenticator: Arc::clone(&self.authenticator),
+        }
+    }
+}
+
+/// Form body of an RFC 7662 introspection request.
+#[derive(Debug, Deserialize)]
+struct IntrospectionForm {
+    token: Option<String>,
+    token_type_hint: Option<String>,
+    client_id: Option<String>,
+    client_secret: Option<String>,
+}
+
+/// Form body of an RFC 7009 revocation request.
+#[derive(Debug, Deserialize)]
+struct RevocationForm {
+    token: Option<String>,
+    token_type_hint: Option<String>,
+    client_id: Option<String>,
+    client_secret: Option<String>,
+}
+
+/// OAuth2 error body (RFC 6749 §5.2).
+#[derive(Debug, Serialize)]
+pub(crate) struct ErrorBody {
+    pub(crate) error: &'static str,
+}
+
+fn error_response(status: StatusCode, error: &'static str) -> Response {
+    let mut response = (status, Json(ErrorBody { error })).into_response();
+
+    if status == StatusCode::UNAUTHORIZED {
+        response.headers_mut().insert(
+            header::WWW_AUTHENTICATE,
+            header::HeaderValue::from_static("Basic realm=\"oauth2\""),
+        );
+    }
+
+    response
+}
+
+/// Extract client credentials from the `Authorization: Basic` header, falling back
+/// to the form fields when there is no Basic header (e.g. a `Bearer` one).
+fn client_credentials(
+    headers: &HeaderMap,
+    form_client_id: Option<&str>,
+    form_client_secret: Option<&str>,
+) -> Option<(String, Option<String>)> {
+    let basic = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Basic "));
+
+    if let Some(encoded) = basic {
+        let decoded = String::from_utf8(STANDARD.decode(encoded.trim()).ok()?).ok()?;
+        let (id, secret) = decoded.split_once(':')?;
+
+        // RFC 6749 §2.3.1: both parts are form-urlencoded before being joined.
+        let unescape = |s: &str| {
+            percent_decode_str(&s.replace('+', " "))
+                .decode_utf8()
+                .map(|s| s.into_owned())
+                .ok()
+        };
+
+        return Some((unescape(id)?, Some(unescape(secret)?)));
+    }
+
+    form_client_id.map(|id| (id.to_string(), form_client_secret.map(str::to_string)))
+}
+
+/// Authenticate the calling client, or produce the `invalid_client` response.
+async fn authenticate_client<A: ClientAuthenticator>(
+    authenticator: &A,
+    headers: &HeaderMap,
+    form_client_id: Option<&str>,
+    form_client_secret: Option<&str>,
+) -> Result<String, Response> {
+    let Some((client_id, client_secret)) =
+        client_credentials(headers, form_client_id, form_client_secret)
+    else {
+        return Err(error_response(StatusCode::UNAUTHORIZED, "invalid_client"));
+    };
+
+    match authenticator
+        .authenticate(&client_id, client_secret.as_deref())
+        .await
+    {
+        Ok(true) => Ok(client_id),
+        Ok(false) => Err(error_response(StatusCode::UNAUTHORIZED, "invalid_client")),
+        Err(_) => Err(error_response(StatusCode::INTERNAL_SERVER_ERROR, "server_error")),
+    }
+}
+
+async fn introspect<S, A>(
+    State(state): State<EndpointState<S, A>>,
+    headers: HeaderMap,
+    Form(form): Form<IntrospectionForm>,
+) -> Response
+where
+    S: OAuth2TokenStore,
+    A: ClientAuthenticator,
+{
+    if let Err(response) = authenticate_client(
+        state.authenticator.as_ref(),
+        &headers,
+        form.client_id.as_deref(),
+        form.client_secret.as_deref(),
+    )
+    .await
+    {
+        return response;
+    }
+
+    let Some(token) = form.token else {
+        return error_response(StatusCode::BAD_REQUEST, "invalid_request");
+    };
+
+    // Unknown hints are ignored (RFC 7662 §2.1).
+    let hint = form
+        .token_type_hint
+        .as_deref()
+        .and_then(|h| h.parse::<TokenTypeHint>().ok());
+
+    match state.store.introspect(&token, hint).await {
+        Ok(response) => Json(response).into_response(),
+        Err(_) => error_response(StatusCode::INTERNAL_SERVER_ERROR, "server_error"),
+    }
+}
+
+async fn revoke<S, A>(
+    State(state): State<EndpointState<S, A>>,
+    headers: HeaderMap,
+    Form(form): Form<RevocationForm>,
+) -> Response
+where
+    S: OAuth2TokenStore,
+    A: ClientAuthenticator,
+{
+    let client_id = match authenticate_client(
+        state.authenticator.as_ref(),
+        &headers,
+        form.client_id.as_deref(),
+        form.client_secret.as_deref(),
+    )
+    .await
+    {
+        Ok(client_id) => client_id,
+        Err(response) => return response,
+    };
+
+    let Some(token) = form.token else {
+        return error_response(StatusCode::BAD_REQUEST, "invalid_request");
+    };
+
+    // Unknown hints are ignored (RFC 7009 §2.1).
+    let hint = form
+        .token_type_hint
+        .as_deref()
+        .and_then(|h| h.parse::<TokenTypeHint>().ok());
+
+    // Unknown and already-revoked tokens still get 200 (RFC 7009 §2.2). So do tokens of
+    // other clients, which are left alone: an error would tell the caller they exist.
+    match state.store.revoke(&token, hint, &client_id).await {
+        Ok(()) | Err(Error::UnauthorizedClient) => StatusCode::OK.into_response(),
+        Err(_) => error_response(StatusCode::INTERNAL_SERVER_ERROR, "server_error"),
+    }
+}
+
+/// Router serving `POST /introspect` (RFC 7662).
+pub fn introspection_router<S, A>(store: Arc<S>, authenticator: Arc<A>) -> Router
+where
+    S: OAuth2TokenStore,
+    A: ClientAuthenticator,
+{
+    Router::new()
+        .route("/introspect", post(introspect::<S, A>))
+        .with_state(EndpointState {
+            store,
+            authenticator,
+        })
+}
+
+/// Router serving `POST /revoke` (RFC 7009).
+pub fn revocation_router<S, A>(store: Arc<S>, authenticator: Arc<A>) -> Router
+where
+    S: OAuth2TokenStore,
+    A: ClientAuthenticator,
+{
+    Router::new()
+        .route("/revoke", post(revoke::<S, A>))
+        .with_state(EndpointState {
+            store,
+            authenticator,
+        })
+}
@@ -0,0 +1,117 @@
+//! Idle timeout and sliding expiration.
+//!
+//! A store's policy is stamped onto each token when it is issued (`idle_timeout_secs`,
+//! `sliding_window_secs`, `max_expires_at`), so changing it only affects tokens issued
+//! afterwards. Lookups then push the token's deadlines forward with [`touch`].
+
+use chrono::{DateTime, TimeDelta, Utc};
+use std::time::Duration;
+
+use crate::StoredToken;
+
+/// Deadlines only move once they would advance by this fraction of their window, so a
+/// busy token costs one write per tenth of its window rather than one per lookup.
+const TOUCH_STEP_DIVISOR: i32 = 10;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct ExpiryPolicy {
+    pub(crate) idle_timeout: Option<Duration>,
+    /// Sliding expiration is on when set; `expires_at` never moves past issuance plus this.
+    pub(crate) max_lifetime: Option<Duration>,
+}
+
+/// Expiry columns of a token issued under an [`ExpiryPolicy`].
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ExpiryStamp {
+    pub(crate) expires_at: Option<DateTime<Utc>>,
+    pub(crate) idle_timeout_secs: Option<i64>,
+    pub(crate) idle_expires_at: Option<DateTime<Utc>>,
+    pub(crate) sliding_window_secs: Option<i64>,
+    pub(crate) max_expires_at: Option<DateTime<Utc>>,
+}
+
+/// Whole seconds of `d`, at least one.
+fn secs(d: Duration) -> i64 {
+    i64::try_from(d.as_secs()).unwrap_or(i64::MAX).max(1)
+}
+
+impl ExpiryPolicy {
+    /// Deadlines for a token issued at `now` whose access token lasts `expires_in`.
+    ///
+    /// Sliding expiration only applies to tokens that expire at all; the window it
+    /// slides by is the token's own `expires_in`.
+    pub(crate) fn stamp(&self, now: DateTime<Utc>, expires_in: Option<Duration>) -> ExpiryStamp {
+        let idle_timeout_secs = self.idle_timeout.map(secs);
+
+        let (sliding_window_secs, max_expires_at) = match (self.max_lifetime, expires_in) {
+            (Some(max), Some(window)) => (Some(secs(window)), Some(now + max)),
+            _ => (None, None),
+        };
+
+        let expires_at = match (expires_in.map(|d| now + d), max_expires_at) {
+            (Some(e), Some(max)) => Some(e.min(max)),
+            (e, _) => e,
+        };
+
+        ExpiryStamp {
+            expires_at,
+            idle_timeout_secs,
+            idle_expires_at: idle_timeout_secs.map(|s| now + TimeDelta::seconds(s)),
+            sliding_window_secs,
+            max_expires_at,
+        }
+    }
+}
+
+/// Record activity on `token` at `now`: reset its idle deadline and, if `slide`, push
+/// `expires_at` forward by its sliding window, capped at `max_expires_at`.
+///
+/// Returns `true` when the new deadlines have to be written back. Small moves are
+/// skipped (see [`TOUCH_STEP_DIVISOR`]), so a token may lapse up to a tenth of its
+/// window earlier than its last use plus the window.
+pub(crate) fn touch(token: &mut StoredToken, now: DateTime<Utc>, slide: bool) -> bool {
+    let mut changed = false;
+
+    if let (Some(timeout), Some(idle_expires_at)) = (token.idle_timeout_secs, token.idle_expires_at) {
+        let timeout = TimeDelta::seconds(timeout);
+        let next = now + timeout;
+        if next - idle_expires_at >= timeout / TOUCH_STEP_DIVISOR {
+            token.idle_expires_at = Some(next);
+            changed = true;
+        }
+    }
+
+    if slide
+        && let (Some(window), Some(expires_at)) = (token.sliding_window_secs, token.expires_at)
+    {
+        let window = TimeDelta::seconds(window);
+        let mut next = now + window;
+        if let Some(max) = token.max_expires_at {
+            next = next.min(max);
+        }
+
+        let reaches_cap = token.max_expires_at == Some(next);
+        if next > expires_at && (next - expires_at >= window / TOUCH_STEP_DIVISOR || reaches_cap) {
+            token.expires_at = Some(next);
+            changed = true;
+        }
+    }
+
+    changed
+}
+
+impl StoredToken {
+    /// Whether the token went unused past its idle timeout.
+    pub(crate) fn is_idle_expired(&self, now: DateTime<Utc>) -> bool {
+        self.idle_expires_at.is_some_and(|e| e <= now)
+    }
+
+    /// The earlier of `expires_at` and the idle deadline; caches never serve a token
+    /// past it.
+    pub(crate) fn usable_until(&self) -> Option<DateTime<Utc>> {
+        match (self.expires_at, self.idle_expires_at) {
+            (Some(e), Some(i)) => Some(e.min(i)),
+            (e, i) => e.or(i),
+        }
+    }
+}
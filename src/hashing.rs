@@ -0,0 +1,236 @@
+//! Pluggable token hashing.
+//!
+//! Every row records the `hash_algorithm` (and, for keyed algorithms, the `key_id`) it was
+//! hashed with. A store hashes new rows with its current [`TokenHasher`] and looks tokens
+//! up under the current hasher, any legacy ones and unkeyed BLAKE3 (the original
+//! scheme), so algorithms and keys can be changed without invalidating live tokens. A
+//! lookup hash only matches rows recorded with the hasher that computed it.
+//!
+//! Keyed algorithms take a server-side secret (a "pepper"), so a database dump alone is
+//! not enough to test guessed or leaked tokens offline.
+
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::sync::Arc;
+
+use crate::Error;
+
+/// Hashes token values before they are stored or looked up.
+pub trait TokenHasher: Send + Sync + 'static {
+    /// Tag stored in the `hash_algorithm` column, e.g. `"hmac-sha256"`.
+    fn algorithm(&self) -> &str;
+
+    /// Identifier of the secret key, stored in the `key_id` column. `None` for unkeyed
+    /// algorithms.
+    fn key_id(&self) -> Option<&str> {
+        None
+    }
+
+    /// Hash a token value to the hex string stored in the database.
+    fn hash(&self, token: &str) -> Result<String, Error>;
+}
+
+/// Plain BLAKE3 (the default, and the crate's original behaviour).
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Blake3Hasher;
+
+impl TokenHasher for Blake3Hasher {
+    fn algorithm(&self) -> &str {
+        "blake3"
+    }
+
+    fn hash(&self, token: &str) -> Result<String, Error> {
+        Ok(hex::encode(blake3::hash(token.as_bytes()).as_bytes()))
+    }
+}
+
+/// Context string for deriving a 32-byte BLAKE3 key from a pepper of any length.
+const KEY_DERIVATION_CONTEXT: &str = "oauth2-pg-store 2026-03 token hashing key";
+
+/// A secret pepper used to key token hashes, identified by `id`: BLAKE3 in keyed mode.
+#[derive(Clone)]
+pub struct HashingKey {
+    id: String,
+    key: [u8; 32],
+}
+
+impl HashingKey {
+    /// Derive a hashing key from `pepper`. `id` is stored with every row hashed
+    /// under this key and must be unique across current and retired keys.
+    pub fn new(id: impl Into<String>, pepper: &[u8]) -> Self {
+        Self {
+            id: id.into(),
+            key: blake3::derive_key(KEY_DERIVATION_CONTEXT, pepper),
+        }
+    }
+
+    /// The identifier recorded in the `key_id` column.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+}
+
+impl TokenHasher for HashingKey {
+    fn algorithm(&self) -> &str {
+        "blake3-keyed"
+    }
+
+    fn key_id(&self) -> Option<&str> {
+        Some(&self.id)
+    }
+
+    fn hash(&self, token: &str) -> Result<String, Error> {
+        Ok(hex::encode(blake3::keyed_hash(&self.key, token.as_bytes()).as_bytes()))
+    }
+}
+
+impl fmt::Debug for HashingKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HashingKey")
+            .field("id", &self.id)
+            .field("key", &"[redacted]")
+            .finish()
+    }
+}
+
+/// HMAC-SHA256, for deployments that must use FIPS-approved primitives.
+#[derive(Clone)]
+pub struct HmacSha256Hasher {
+    key_id: String,
+    key: Vec<u8>,
+}
+
+impl HmacSha256Hasher {
+    /// Use `key` as the HMAC secret. `key_id` is stored with every row hashed under it.
+    pub fn new(key_id: impl Into<String>, key: &[u8]) -> Self {
+        Self {
+            key_id: key_id.into(),
+            key: key.to_vec(),
+        }
+    }
+}
+
+impl TokenHasher for HmacSha256Hasher {
+    fn algorithm(&self) -> &str {
+        "hmac-sha256"
+    }
+
+    fn key_id(&self) -> Option<&str> {
+        Some(&self.key_id)
+    }
+
+    fn hash(&self, token: &str) -> Result<String, Error> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.key)
+            .map_err(|e| Error::Hashing(e.to_string()))?;
+        mac.update(token.as_bytes());
+        Ok(hex::encode(mac.finalize().into_bytes()))
+    }
+}
+
+impl fmt::Debug for HmacSha256Hasher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HmacSha256Hasher")
+            .field("key_id", &self.key_id)
+            .field("key", &"[redacted]")
+            .finish()
+    }
+}
+
+/// Plain SHA-256.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Sha256Hasher;
+
+impl TokenHasher for Sha256Hasher {
+    fn algorithm(&self) -> &str {
+        "sha256"
+    }
+
+    fn hash(&self, token: &str) -> Result<String, Error> {
+        Ok(hex::encode(Sha256::digest(token.as_bytes())))
+    }
+}
+
+/// The hashers a store writes with and accepts on lookup.
+#[derive(Clone)]
+pub(crate) struct Hashers {
+    /// Hasher for new rows.
+    pub(crate) current: Arc<dyn TokenHasher>,
+    /// Hashers still accepted on lookup for rows written before a change.
+    pub(crate) legacy: Vec<Arc<dyn TokenHasher>>,
+    /// Also accept rows hashed with plain BLAKE3, the original scheme.
+    pub(crate) unkeyed_fallback: bool,
+}
+
+impl Default for Hashers {
+    fn default() -> Self {
+        Self {
+            current: Arc::new(Blake3Hasher),
+            legacy: Vec::new(),
+            unkeyed_fallback: true,
+        }
+    }
+}
+
+impl Hashers {
+    /// Every hasher a stored row may have been written with: current first, then legacy
+    /// ones, then plain BLAKE3 unless the fallback is off or it is already listed.
+    fn lookup_hashers(&self) -> impl Iterator<Item = &dyn TokenHasher> {
+        let configured = std::iter::once(&self.current)
+            .chain(&self.legacy)
+            .map(|hasher| hasher.as_ref());
+
+        let listed = configured
+            .clone()
+            .any(|h| h.algorithm() == Blake3Hasher.algorithm() && h.key_id().is_none());
+        let fallback = (self.unkeyed_fallback && !listed).then_some(&Blake3Hasher as &dyn TokenHasher);
+
+        configured.chain(fallback)
+    }
+
+    /// Every hash `token` may be stored under, in lookup order, for tables that do not
+    /// record their hasher.
+    pub(crate) fn hashes(&self, token: &str) -> Result<Vec<String>, Error> {
+        self.lookup_hashers().map(|hasher| hasher.hash(token)).collect()
+    }
+
+    /// Every hash `token` may be stored under, each with the hasher that computed it.
+    pub(crate) fn candidates(&self, token: &str) -> Result<Candidates, Error> {
+        let mut candidates = Candidates::default();
+        for hasher in self.lookup_hashers() {
+            candidates.hashes.push(hasher.hash(token)?);
+            candidates.algorithms.push(hasher.algorithm().to_string());
+            candidates.key_ids.push(hasher.key_id().unwrap_or_default().to_string());
+        }
+        Ok(candidates)
+    }
+}
+
+/// Lookup hashes of one token as parallel arrays, ready for `UNNEST`.
+///
+/// A row matches a candidate only if its hash, `hash_algorithm` and `key_id` all agree,
+/// so the columns pick the hasher a row is checked against.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Candidates {
+    pub(crate) hashes: Vec<String>,
+    pub(crate) algorithms: Vec<String>,
+    /// `""` for unkeyed hashers, to compare against `COALESCE(key_id, '')`.
+    pub(crate) key_ids: Vec<String>,
+}
+
+impl Candidates {
+    /// `(hash, hash_algorithm, key_id)` of each candidate.
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (&str, &str, &str)> {
+        self.hashes
+            .iter()
+            .zip(&self.algorithms)
+            .zip(&self.key_ids)
+            .map(|((hash, algorithm), key_id)| (hash.as_str(), algorithm.as_str(), key_id.as_str()))
+    }
+
+    /// Whether a row storing `hash`, written with `algorithm` and `key_id`, matches.
+    pub(crate) fn matches(&self, hash: &str, algorithm: &str, key_id: Option<&str>) -> bool {
+        self.iter()
+            .any(|c| c == (hash, algorithm, key_id.unwrap_or_default()))
+    }
+}
@@ -0,0 +1,116 @@
+//! RFC 7662 token introspection types.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+use crate::StoredToken;
+
+/// Which kind of token the caller believes it is presenting (RFC 7009 / RFC 7662 `token_type_hint`).
+///
+/// The hint only decides which lookup runs first; the other is still tried on a miss.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenTypeHint {
+    AccessToken,
+    RefreshToken,
+}
+
+impl FromStr for TokenTypeHint {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "access_token" => Ok(Self::AccessToken),
+            "refresh_token" => Ok(Self::RefreshToken),
+            _ => Err(()),
+        }
+    }
+}
+
+impl TokenTypeHint {
+    /// Lookup order for a token of unknown kind: the hinted kind first, access tokens
+    /// first when there is no hint.
+    pub(crate) fn lookup_order(hint: Option<Self>) -> [Self; 2] {
+        match hint {
+            Some(Self::RefreshToken) => [Self::RefreshToken, Self::AccessToken],
+            _ => [Self::AccessToken, Self::RefreshToken],
+        }
+    }
+}
+
+impl StoredToken {
+    /// Whether the token, found by a lookup of `kind`, can still be used at `now`: not
+    /// revoked or rotated, not idled out, and not past the expiry of that kind.
+    pub(crate) fn is_active(&self, kind: TokenTypeHint, now: DateTime<Utc>) -> bool {
+        let expires_at = match kind {
+            TokenTypeHint::AccessToken => self.expires_at,
+            TokenTypeHint::RefreshToken => self.refresh_expires_at,
+        };
+
+        !self.revoked
+            && self.rotated_at.is_none()
+            && !self.is_idle_expired(now)
+            && expires_at.is_none_or(|e| e > now)
+    }
+}
+
+/// Introspection response body as defined by RFC 7662 §2.2.
+///
+/// Inactive tokens serialize to `{"active": false}` only, so nothing about an
+/// unknown, expired or revoked token is disclosed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IntrospectionResponse {
+    pub active: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sub: Option<String>,
+    /// Expiry as seconds since the Unix epoch.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exp: Option<i64>,
+    /// Issue time as seconds since the Unix epoch.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub iat: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token_type: Option<String>,
+}
+
+impl IntrospectionResponse {
+    /// Response for a token that is unknown, expired or revoked.
+    pub fn inactive() -> Self {
+        Self {
+            active: false,
+            scope: None,
+            client_id: None,
+            sub: None,
+            exp: None,
+            iat: None,
+            token_type: None,
+        }
+    }
+
+    /// Response for a valid token found through the lookup of the given kind.
+    ///
+    /// `exp` comes from `expires_at` for access tokens and `refresh_expires_at`
+    /// for refresh tokens. `token_type` is the type the access token was issued with, and
+    /// omitted for refresh tokens and tokens stored before it was recorded.
+    pub fn active(token: &StoredToken, kind: TokenTypeHint) -> Self {
+        let (exp, token_type) = match kind {
+            TokenTypeHint::AccessToken => (token.expires_at, token.token_type.clone()),
+            TokenTypeHint::RefreshToken => (token.refresh_expires_at, None),
+        };
+
+        Self {
+            active: true,
+            scope: Some(token.scopes.join(" ")),
+            client_id: Some(token.client_id.clone()),
+            sub: token.user_id.map(|id| id.to_string()),
+            exp: exp.map(|t| t.timestamp()),
+            iat: Some(token.issued_at.timestamp()),
+            token_type,
+        }
+    }
+}
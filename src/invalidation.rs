@@ -0,0 +1,80 @@
+//! Cross-instance cache invalidation over Postgres `LISTEN`/`NOTIFY`.
+//!
+//! Triggers on `oauth2_tokens` announce every revoked or deleted row on
+//! [`REVOCATION_CHANNEL`]; each instance's listener purges those tokens from its
+//! [`CachedTokenStore`].
+
+use sqlx::postgres::PgListener;
+use sqlx::PgPool;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+use crate::{CachedTokenStore, OAuth2TokenStore};
+
+/// Channel carrying comma-separated `access_token_hash` values of revoked tokens.
+pub const REVOCATION_CHANNEL: &str = "oauth2_token_revoked";
+
+const MIN_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+impl<S: OAuth2TokenStore> CachedTokenStore<S> {
+    /// Spawn a background task purging tokens revoked by any instance sharing `pool`'s
+    /// database from this cache (and its clones).
+    ///
+    /// The listener reconnects on its own with exponential backoff. Notifications sent
+    /// while it was disconnected are lost, so the whole cache is cleared every time it
+    /// (re)connects. Abort the returned handle to stop listening.
+    pub fn listen_for_revocations(&self, pool: PgPool) -> JoinHandle<()> {
+        tokio::spawn(run_listener(self.clone(), pool))
+    }
+}
+
+async fn run_listener<S: OAuth2TokenStore>(cache: CachedTokenStore<S>, pool: PgPool) {
+    let mut backoff = MIN_BACKOFF;
+
+    loop {
+        match listen(&cache, &pool).await {
+            Ok(listener) => {
+                backoff = MIN_BACKOFF;
+                let error = receive(&cache, listener).await;
+                tracing::warn!(%error, "revocation listener failed; reconnecting");
+            }
+            Err(error) => {
+                tracing::warn!(%error, ?backoff, "revocation listener could not connect");
+            }
+        }
+
+        tokio::time::sleep(backoff).await;
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+}
+
+/// Connect and subscribe, then drop anything that may have been revoked unseen.
+async fn listen<S: OAuth2TokenStore>(
+    cache: &CachedTokenStore<S>,
+    pool: &PgPool,
+) -> Result<PgListener, sqlx::Error> {
+    let mut listener = PgListener::connect_with(pool).await?;
+    listener.listen(REVOCATION_CHANNEL).await?;
+    cache.clear();
+    Ok(listener)
+}
+
+/// Apply notifications until the listener fails to reconnect.
+async fn receive<S: OAuth2TokenStore>(
+    cache: &CachedTokenStore<S>,
+    mut listener: PgListener,
+) -> sqlx::Error {
+    loop {
+        match listener.try_recv().await {
+            Ok(Some(notification)) => cache.invalidate_hashes(notification.payload().split(',')),
+            // The connection dropped and `PgListener` has reconnected; anything sent in
+            // between is lost.
+            Ok(None) => {
+                tracing::warn!("revocation listener reconnected; clearing cache");
+                cache.clear();
+            }
+            Err(error) => return error,
+        }
+    }
+}
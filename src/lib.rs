@@ -4,18 +4,73 @@
 //! Tokens are **never stored in plaintext** — they're hashed with BLAKE3 before insertion.
 //! Designed to work alongside the `oauth2` crate when building an OAuth2 authorization server
 //! or token introspection endpoint.
+//!
+//! [`InMemoryTokenStore`] implements the same [`OAuth2TokenStore`] contract without a
+//! database, for unit tests and local development. The `sqlite` and `mysql` features add
+//! `SqliteTokenStore` and `MySqlTokenStore` for deployments without Postgres.
 
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
 use oauth2::{
-    basic::BasicTokenType,
-    AccessToken, EmptyExtraTokenFields, RefreshToken, Scope,
-    StandardTokenResponse, TokenResponse,
+    AccessToken, ExtraTokenFields, RefreshToken, Scope,
+    StandardTokenResponse, TokenResponse, TokenType,
 };
-use sqlx::{PgPool, FromRow};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgConnection, PgExecutor, PgPool};
+use std::sync::Arc;
+use std::time::Duration;
 use thiserror::Error;
 use uuid::Uuid;
 
+mod authorization_code;
+mod cache;
+mod cleanup;
+mod device_code;
+mod expiry;
+mod hashing;
+mod introspection;
+mod invalidation;
+mod limits;
+mod memory;
+#[cfg(feature = "mysql")]
+mod mysql;
+mod pagination;
+#[cfg(feature = "redis")]
+mod redis_cache;
+mod scope;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+mod usage;
+
+#[cfg(feature = "axum")]
+pub mod bearer;
+#[cfg(feature = "axum")]
+pub mod endpoints;
+
+pub use authorization_code::{AuthorizationCodeGrant, AuthorizationCodeStore, StoredAuthorizationCode};
+pub use cache::CachedTokenStore;
+pub use cleanup::{CleanupOptions, CleanupProgress, CleanupReport, CleanupTable, CleanupTask};
+pub use device_code::{
+    generate_user_code, DeviceCodeStatus, DeviceCodeStore, DevicePoll, StoredDeviceCode,
+};
+use expiry::ExpiryPolicy;
+use hashing::{Candidates, Hashers};
+use usage::UsageRecorder;
+pub use hashing::{Blake3Hasher, HashingKey, HmacSha256Hasher, Sha256Hasher, TokenHasher};
+pub use introspection::{IntrospectionResponse, TokenTypeHint};
+pub use invalidation::REVOCATION_CHANNEL;
+pub use limits::{LimitPolicy, TokenLimits};
+pub use memory::InMemoryTokenStore;
+#[cfg(feature = "mysql")]
+pub use mysql::MySqlTokenStore;
+pub use pagination::{PageCursor, PageRequest, TokenPage};
+#[cfg(feature = "redis")]
+pub use redis_cache::RedisCachedTokenStore;
+pub use scope::ScopeRules;
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteTokenStore;
+
 /// Main error type for this crate.
 #[derive(Debug, Error)]
 pub enum Error {
@@ -28,15 +83,43 @@ pub enum Error {
     #[error("token expired or revoked")]
     InvalidToken,
 
+    /// An already-rotated refresh token was presented again. Every token in the
+    /// family has been revoked.
+    #[error("refresh token reuse detected; token family {0} revoked")]
+    RefreshTokenReuse(Uuid),
+
+    #[error("token was not issued to this client")]
+    UnauthorizedClient,
+
+    #[error("invalid grant: {0}")]
+    InvalidGrant(String),
+
     #[error("hashing error: {0}")]
     Hashing(String),
 
+    #[error("serialization error: {0}")]
+    Serialization(#[from] serde_json::Error),
+
+    /// A cache backend failed, e.g. Redis was unreachable while applying a revocation.
+    #[error("cache error: {0}")]
+    Cache(String),
+
+    /// Storing the token would exceed an active-token limit set with
+    /// [`PgTokenStore::with_token_limits`] under [`LimitPolicy::Reject`].
+    #[error("active token limit exceeded: {0}")]
+    LimitExceeded(String),
+
+    /// Another unexpired device authorization holds the user code. Retry with a new one,
+    /// or let [`DeviceCodeStore::issue_device_code`] generate it.
+    #[error("user code already in use")]
+    UserCodeTaken,
+
     #[error("other error: {0}")]
     Other(#[from] Box<dyn std::error::Error + Send + Sync>),
 }
 
 /// A stored token record (what you get back when looking up by token).
-#[derive(Debug, Clone, FromRow)]
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
 pub struct StoredToken {
     pub id: Uuid,
     pub access_token_hash: String,
@@ -47,67 +130,454 @@ pub struct StoredToken {
     pub issued_at: DateTime<Utc>,
     pub expires_at: Option<DateTime<Utc>>,
     pub revoked: bool,
+    /// When the token was revoked, recorded by the database; `None` while it is not.
+    #[serde(default)]
+    pub revoked_at: Option<DateTime<Utc>>,
+    /// When the refresh token stops being usable. Independent of `expires_at`,
+    /// which only governs the access token.
+    pub refresh_expires_at: Option<DateTime<Utc>>,
+    /// Shared by every token descended from the same original grant via rotation.
+    pub family_id: Uuid,
+    /// Set when this row's refresh token was exchanged through rotation.
+    pub rotated_at: Option<DateTime<Utc>>,
+    /// Serialized extra fields of the stored token response (`{}` if there were none).
+    pub extra_fields: serde_json::Value,
+    /// Key the hashes were computed with ([`TokenHasher::key_id`]); `None` for unkeyed hashes.
+    pub key_id: Option<String>,
+    /// Algorithm the hashes were computed with ([`TokenHasher::algorithm`]).
+    pub hash_algorithm: String,
+    /// Last access-token lookup recorded by usage tracking; `None` if never recorded.
+    #[serde(default)]
+    pub last_used_at: Option<DateTime<Utc>>,
+    /// Access-token lookups recorded by usage tracking.
+    #[serde(default)]
+    pub use_count: i64,
+    /// Idle timeout the token was issued with; `None` if it never idles out.
+    #[serde(default)]
+    pub idle_timeout_secs: Option<i64>,
+    /// When the token lapses unless used before then. Moved forward on use.
+    #[serde(default)]
+    pub idle_expires_at: Option<DateTime<Utc>>,
+    /// Set for sliding expiration: each use moves `expires_at` to this far from now.
+    #[serde(default)]
+    pub sliding_window_secs: Option<i64>,
+    /// Cap on a sliding `expires_at`, fixed at issuance.
+    #[serde(default)]
+    pub max_expires_at: Option<DateTime<Utc>>,
+    /// `token_type` of the stored token response, e.g. `bearer`; `None` for tokens stored
+    /// before it was recorded.
+    #[serde(default)]
+    pub token_type: Option<String>,
+}
+
+impl StoredToken {
+    /// Deserialize the stored extra fields back into the provider's type,
+    /// e.g. a struct with an OIDC `id_token`.
+    pub fn typed_extra_fields<EF: DeserializeOwned>(&self) -> Result<EF, Error> {
+        Ok(serde_json::from_value(self.extra_fields.clone())?)
+    }
 }
 
 /// Abstract trait for token storage backends.
 #[async_trait]
 pub trait OAuth2TokenStore: Send + Sync + 'static {
     /// Store a newly issued token response.
-    async fn store_token(
+    ///
+    /// The access token expires after `token.expires_in()`; the refresh token (if any)
+    /// expires after `refresh_expires_in`. `None` means no expiry. The response's extra
+    /// fields (e.g. an OIDC `id_token`) are kept as JSON, see [`StoredToken::typed_extra_fields`].
+    async fn store_token<EF, TT>(
         &self,
-        token: &StandardTokenResponse<EmptyExtraTokenFields, BasicTokenType>,
+        token: &StandardTokenResponse<EF, TT>,
         client_id: &str,
         user_id: Option<Uuid>,
         scopes: &[Scope],
-    ) -> Result<(), Error>;
+        refresh_expires_in: Option<Duration>,
+    ) -> Result<(), Error>
+    where
+        EF: ExtraTokenFields + Sync,
+        TT: TokenType + Sync;
 
     /// Look up token metadata by access token value.
     async fn get_by_access_token(&self, token: &AccessToken) -> Result<Option<StoredToken>, Error>;
 
     /// Look up by refresh token (if present).
+    ///
+    /// Presenting a refresh token that was already rotated revokes its whole token
+    /// family and fails with [`Error::RefreshTokenReuse`].
     async fn get_by_refresh_token(&self, token: &RefreshToken) -> Result<Option<StoredToken>, Error>;
 
+    /// Look up a token by access token value without side effects, e.g. to inspect it.
+    ///
+    /// Unlike [`get_by_access_token`](Self::get_by_access_token), revoked and expired
+    /// tokens are returned too, and the lookup neither counts as a use nor moves the
+    /// token's idle or sliding deadlines.
+    async fn find_by_access_token(&self, token: &AccessToken) -> Result<Option<StoredToken>, Error>;
+
+    /// Look up a token by refresh token value without side effects, e.g. to inspect it.
+    ///
+    /// Revoked, expired and rotated tokens are returned too. A rotated token found here
+    /// does not revoke its family, unlike with
+    /// [`get_by_refresh_token`](Self::get_by_refresh_token).
+    async fn find_by_refresh_token(&self, token: &RefreshToken) -> Result<Option<StoredToken>, Error>;
+
     /// Mark a token as revoked by its access token value.
     async fn revoke_by_access_token(&self, token: &AccessToken) -> Result<(), Error>;
 
     /// Mark revoked by refresh token.
     async fn revoke_by_refresh_token(&self, token: &RefreshToken) -> Result<(), Error>;
 
-    /// Remove expired/revoked tokens (run periodically via cron/job).
+    /// Atomically exchange a refresh token for a new token pair.
+    ///
+    /// The old row is revoked and `new` is stored with the old row's client, user and
+    /// scopes and token family. Fails with [`Error::NotFound`] if `old` is unknown,
+    /// [`Error::RefreshTokenReuse`] if it was already rotated (the family is revoked), or
+    /// [`Error::InvalidToken`] if it was revoked or expired.
+    async fn rotate_refresh_token<EF, TT>(
+        &self,
+        old: &RefreshToken,
+        new: &StandardTokenResponse<EF, TT>,
+        refresh_expires_in: Option<Duration>,
+    ) -> Result<StoredToken, Error>
+    where
+        EF: ExtraTokenFields + Sync,
+        TT: TokenType + Sync;
+
+    /// RFC 7009 revocation of a raw token value on behalf of `client_id`.
+    ///
+    /// Unknown or already-revoked tokens succeed (the operation is idempotent). A token
+    /// issued to a different client fails with [`Error::UnauthorizedClient`]. Revoking a
+    /// refresh token also revokes every access token from the same grant.
+    async fn revoke(
+        &self,
+        token: &str,
+        token_type_hint: Option<TokenTypeHint>,
+        client_id: &str,
+    ) -> Result<(), Error>;
+
+    /// List a user's active tokens (access or refresh token still usable), newest first.
+    ///
+    /// Returned rows have their hash columns blanked, so they are safe to hand to a UI.
+    async fn list_tokens_for_user(&self, user_id: Uuid, page: PageRequest) -> Result<TokenPage, Error>;
+
+    /// Revoke a single token by its row id, e.g. from a "signed-in devices" list.
+    ///
+    /// Fails with [`Error::NotFound`] if no such row exists.
+    async fn revoke_by_id(&self, id: Uuid) -> Result<(), Error>;
+
+    /// Revoke every active token held by `user_id` (e.g. after a password change).
+    ///
+    /// `except` keeps one token id alive, typically the session making the request.
+    /// Returns the number of tokens revoked.
+    async fn revoke_all_for_user(&self, user_id: Uuid, except: Option<Uuid>) -> Result<usize, Error>;
+
+    /// Revoke every active token issued to `client_id` (e.g. when a client is disabled).
+    ///
+    /// `except` keeps one token id alive. Returns the number of tokens revoked.
+    async fn revoke_all_for_client(&self, client_id: &str, except: Option<Uuid>) -> Result<usize, Error>;
+
+    /// Revoke every active token `user_id` holds for `client_id` (e.g. "sign out of this app").
+    ///
+    /// `except` keeps one token id alive. Returns the number of tokens revoked.
+    async fn revoke_all_for_user_and_client(
+        &self,
+        user_id: Uuid,
+        client_id: &str,
+        except: Option<Uuid>,
+    ) -> Result<usize, Error>;
+
+    /// Remove expired/revoked tokens (run periodically via cron/job, or [`CleanupTask`]
+    /// for Postgres).
+    ///
+    /// Backends that also hold authorization or device codes remove expired codes here
+    /// too; the returned count covers every deleted row. On large Postgres tables, prefer
+    /// [`PgTokenStore::cleanup_batched`], which deletes in batches and supports retention.
     async fn cleanup(&self) -> Result<usize, Error>;
+
+    /// RFC 7662 introspection of a raw token value.
+    ///
+    /// Tries the access-token lookup, then the refresh-token lookup (reversed when the
+    /// hint says `refresh_token`). Unknown, expired, revoked and rotated tokens are
+    /// reported as inactive rather than as an error.
+    ///
+    /// Uses the `find_*` lookups, so introspection is read-only: it never counts as a
+    /// use, moves deadlines or revokes the family of a rotated refresh token.
+    async fn introspect(
+        &self,
+        token: &str,
+        token_type_hint: Option<TokenTypeHint>,
+    ) -> Result<IntrospectionResponse, Error> {
+        for kind in TokenTypeHint::lookup_order(token_type_hint) {
+            let found = match kind {
+                TokenTypeHint::AccessToken => {
+                    self.find_by_access_token(&AccessToken::new(token.to_string())).await?
+                }
+                TokenTypeHint::RefreshToken => {
+                    self.find_by_refresh_token(&RefreshToken::new(token.to_string())).await?
+                }
+            };
+
+            if let Some(stored) = found {
+                if !stored.is_active(kind, Utc::now()) {
+                    break;
+                }
+                return Ok(IntrospectionResponse::active(&stored, kind));
+            }
+        }
+
+        Ok(IntrospectionResponse::inactive())
+    }
+}
+
+/// Metadata stored alongside a token response on insert.
+struct NewTokenRow<'a> {
+    client_id: &'a str,
+    user_id: Option<Uuid>,
+    scopes: &'a [String],
+    refresh_expires_in: Option<Duration>,
+    /// Existing family to join (rotation); `None` starts a new family.
+    family_id: Option<Uuid>,
+}
+
+impl NewTokenRow<'_> {
+    /// The complete row for `token` issued at `now`, hashed with the current hasher.
+    ///
+    /// Used by backends that cannot return the inserted row from the database.
+    fn build<EF, TT>(
+        self,
+        token: &StandardTokenResponse<EF, TT>,
+        hashers: &Hashers,
+        expiry: ExpiryPolicy,
+        now: DateTime<Utc>,
+    ) -> Result<StoredToken, Error>
+    where
+        EF: ExtraTokenFields,
+        TT: TokenType,
+    {
+        let access_token_hash = hashers.current.hash(token.access_token().secret())?;
+
+        let refresh_token_hash = token
+            .refresh_token()
+            .map(|r: &RefreshToken| hashers.current.hash(r.secret()))
+            .transpose()?;
+
+        let refresh_expires_at = refresh_token_hash
+            .as_ref()
+            .and(self.refresh_expires_in)
+            .map(|d| now + d);
+
+        let stamp = expiry.stamp(now, token.expires_in());
+
+        Ok(StoredToken {
+            id: Uuid::new_v4(),
+            access_token_hash,
+            refresh_token_hash,
+            client_id: self.client_id.to_string(),
+            user_id: self.user_id,
+            scopes: self.scopes.to_vec(),
+            issued_at: now,
+            expires_at: stamp.expires_at,
+            revoked: false,
+            revoked_at: None,
+            refresh_expires_at,
+            family_id: self.family_id.unwrap_or_else(Uuid::new_v4),
+            rotated_at: None,
+            extra_fields: serde_json::to_value(token.extra_fields())?,
+            key_id: hashers.current.key_id().map(str::to_string),
+            hash_algorithm: hashers.current.algorithm().to_string(),
+            last_used_at: None,
+            use_count: 0,
+            idle_timeout_secs: stamp.idle_timeout_secs,
+            idle_expires_at: stamp.idle_expires_at,
+            sliding_window_secs: stamp.sliding_window_secs,
+            max_expires_at: stamp.max_expires_at,
+            token_type: Some(token_type_name(token.token_type())?),
+        })
+    }
+}
+
+/// The `token_type` a response serializes, e.g. `bearer` for [`oauth2::basic::BasicTokenType::Bearer`].
+fn token_type_name<TT: TokenType>(token_type: &TT) -> Result<String, Error> {
+    match serde_json::to_value(token_type)? {
+        serde_json::Value::String(name) => Ok(name),
+        other => Ok(other.to_string()),
+    }
 }
 
 /// Concrete Postgres implementation using `sqlx`.
 #[derive(Clone)]
 pub struct PgTokenStore {
     pool: PgPool,
+    hashers: Arc<Hashers>,
+    usage: Option<Arc<UsageRecorder>>,
+    expiry: ExpiryPolicy,
+    limits: TokenLimits,
 }
 
 impl PgTokenStore {
     /// Create a new store connected to the given Postgres pool.
     pub fn new(pool: PgPool) -> Self {
-        Self { pool }
+        Self {
+            pool,
+            hashers: Arc::default(),
+            usage: None,
+            expiry: ExpiryPolicy::default(),
+            limits: TokenLimits::default(),
+        }
+    }
+
+    /// Hash new tokens with a keyed BLAKE3 hash under `key` (a server-side pepper).
+    ///
+    /// Unkeyed rows stay valid through the unkeyed fallback; add earlier keys with
+    /// [`PgTokenStore::with_retired_hashing_key`].
+    pub fn with_hashing_key(self, key: HashingKey) -> Self {
+        self.with_hasher(key)
+    }
+
+    /// Keep accepting tokens hashed under a key that has been rotated out.
+    pub fn with_retired_hashing_key(self, key: HashingKey) -> Self {
+        self.with_legacy_hasher(key)
+    }
+
+    /// Hash new tokens with `hasher` instead of plain BLAKE3.
+    ///
+    /// Lookups try `hasher`, then every legacy hasher, then plain BLAKE3; add the
+    /// previous hasher with [`PgTokenStore::with_legacy_hasher`] to keep rows it wrote
+    /// valid.
+    pub fn with_hasher(mut self, hasher: impl TokenHasher) -> Self {
+        Arc::make_mut(&mut self.hashers).current = Arc::new(hasher);
+        self
+    }
+
+    /// Keep accepting tokens hashed by an algorithm or key that is no longer current.
+    pub fn with_legacy_hasher(mut self, hasher: impl TokenHasher) -> Self {
+        Arc::make_mut(&mut self.hashers).legacy.push(Arc::new(hasher));
+        self
     }
 
-    /// Hash a token value before storing/lookup using BLAKE3 (deterministic, fast, cryptographically secure).
+    /// Stop accepting rows hashed with plain BLAKE3 unless it is the current or a legacy
+    /// hasher, e.g. once every token issued before a pepper was configured has expired.
+    pub fn without_unkeyed_fallback(mut self) -> Self {
+        Arc::make_mut(&mut self.hashers).unkeyed_fallback = false;
+        self
+    }
+
+    /// Record `last_used_at` and `use_count` on every successful
+    /// [`get_by_access_token`](OAuth2TokenStore::get_by_access_token).
+    ///
+    /// Uses are buffered in memory and written in one batched `UPDATE` every
+    /// `flush_interval` by a background task started with the first recorded use, so hot
+    /// tokens do not cost a write per validation. The task flushes one last time and stops
+    /// once every clone of the store is dropped. Returned rows only reflect usage up to the
+    /// last write; call [`PgTokenStore::flush_usage`] on shutdown to keep the rest. Lookups answered by a cache in front of the store never
+    /// reach it and are not counted.
+    pub fn with_usage_tracking(mut self, flush_interval: Duration) -> Self {
+        self.usage = Some(Arc::new(UsageRecorder::new(flush_interval)));
+        self
+    }
+
+    /// End sessions that go unused for `timeout` (whole seconds).
+    ///
+    /// Tokens issued from now on get an idle deadline that every successful lookup by
+    /// access or refresh token moves forward; once it passes, both tokens are rejected
+    /// as if expired. To save writes, the deadline is only moved once it would advance
+    /// by a tenth of `timeout`, so a session may end up to that much early. Tokens issued
+    /// before keep the timeout they were issued with, if any.
+    pub fn with_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.expiry.idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Slide `expires_at` on use, but never past `max_lifetime` after issuance.
+    ///
+    /// Every access-token lookup moves the token's `expires_at` to its original
+    /// `expires_in` from now, debounced like [`PgTokenStore::with_idle_timeout`]. Only
+    /// applies to tokens issued with an `expires_in`, from now on.
+    pub fn with_sliding_expiration(mut self, max_lifetime: Duration) -> Self {
+        self.expiry.max_lifetime = Some(max_lifetime);
+        self
+    }
+
+    /// Cap the number of active tokens per (user, client) and per client.
+    ///
+    /// [`store_token`](OAuth2TokenStore::store_token) checks the caps in the same
+    /// transaction as the insert, holding a transaction-scoped advisory lock on the
+    /// client (or the user and client, if only that cap is set) so concurrent issuance
+    /// cannot overshoot. Depending on [`LimitPolicy`], a token over the cap fails with
+    /// [`Error::LimitExceeded`] or revokes the oldest active tokens first. Rotation
+    /// replaces a token one for one and is not checked.
+    pub fn with_token_limits(mut self, limits: TokenLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Write buffered usage now. Returns the number of tokens updated.
+    pub async fn flush_usage(&self) -> Result<usize, Error> {
+        let Some(usage) = &self.usage else {
+            return Ok(0);
+        };
+
+        let batch = usage.take();
+        if batch.is_empty() {
+            return Ok(0);
+        }
+
+        let ids: Vec<Uuid> = batch.iter().map(|(id, _)| *id).collect();
+        let uses: Vec<i64> = batch.iter().map(|(_, u)| u.uses).collect();
+        let last_used: Vec<DateTime<Utc>> = batch.iter().map(|(_, u)| u.last_used_at).collect();
+
+        let res = sqlx::query!(
+            r#"
+            UPDATE oauth2_tokens AS t
+            SET use_count = t.use_count + u.uses,
+                last_used_at = GREATEST(t.last_used_at, u.last_used_at)
+            FROM UNNEST($1::uuid[], $2::int8[], $3::timestamptz[]) AS u(id, uses, last_used_at)
+            WHERE t.id = u.id
+            "#,
+            &ids,
+            &uses,
+            &last_used,
+        )
+        .execute(&self.pool)
+        .await;
+
+        match res {
+            Ok(res) => Ok(res.rows_affected() as usize),
+            Err(e) => {
+                usage.restore(batch);
+                Err(e.into())
+            }
+        }
+    }
+
+    /// Hash a token value before storing it, using the current [`TokenHasher`].
     fn hash_token(&self, token: &str) -> Result<String, Error> {
-        use blake3;
-        use hex;
+        self.hashers.current.hash(token)
+    }
 
-        let hash = blake3::hash(token.as_bytes());
-        Ok(hex::encode(hash.as_bytes()))
+    /// Every hash a stored code may have been written under, for lookups.
+    fn lookup_hashes(&self, token: &str) -> Result<Vec<String>, Error> {
+        self.hashers.hashes(token)
     }
-}
 
-#[async_trait]
-impl OAuth2TokenStore for PgTokenStore {
-    async fn store_token(
+    /// Every hash a stored token may have been written under, with the hasher of each,
+    /// for lookups in `oauth2_tokens`.
+    fn lookup_candidates(&self, token: &str) -> Result<Candidates, Error> {
+        self.hashers.candidates(token)
+    }
+
+    /// Insert a token row using the given executor (pool or open transaction).
+    async fn insert_token<'e, E, EF, TT>(
         &self,
-        token: &StandardTokenResponse<EmptyExtraTokenFields, BasicTokenType>,
-        client_id: &str,
-        user_id: Option<Uuid>,
-        scopes: &[Scope],
-    ) -> Result<(), Error> {
+        executor: E,
+        token: &StandardTokenResponse<EF, TT>,
+        row: NewTokenRow<'_>,
+    ) -> Result<StoredToken, Error>
+    where
+        E: PgExecutor<'e>,
+        EF: ExtraTokenFields,
+        TT: TokenType,
+    {
         let access_hash = self.hash_token(token.access_token().secret())?;
 
         let refresh_hash = token
@@ -115,13 +585,18 @@ impl OAuth2TokenStore for PgTokenStore {
             .map(|r: &RefreshToken| self.hash_token(r.secret()))
             .transpose()?;
 
-        let scopes_str: Vec<String> = scopes.iter().map(|s| s.to_string()).collect();
+        let expiry = self.expiry.stamp(Utc::now(), token.expires_in());
 
-        let expires_at = token
-            .expires_in()
+        let refresh_expires_at = refresh_hash
+            .as_ref()
+            .and(row.refresh_expires_in)
             .map(|d| Utc::now() + d);
 
-        sqlx::query!(
+        let extra_fields = serde_json::to_value(token.extra_fields())?;
+        let token_type = token_type_name(token.token_type())?;
+
+        let stored = sqlx::query_as!(
+            StoredToken,
             r#"
             INSERT INTO oauth2_tokens (
                 access_token_hash,
@@ -131,53 +606,320 @@ impl OAuth2TokenStore for PgTokenStore {
                 scopes,
                 issued_at,
                 expires_at,
-                revoked
-            ) VALUES ($1, $2, $3, $4, $5, NOW(), $6, FALSE)
+                revoked,
+                refresh_expires_at,
+                family_id,
+                extra_fields,
+                key_id,
+                hash_algorithm,
+                idle_timeout_secs,
+                idle_expires_at,
+                sliding_window_secs,
+                max_expires_at,
+                token_type
+            ) VALUES (
+                $1, $2, $3, $4, $5, NOW(), $6, FALSE, $7, COALESCE($8, gen_random_uuid()), $9, $10, $11,
+                $12, $13, $14, $15, $16
+            )
+            RETURNING *
             "#,
             access_hash,
             refresh_hash,
-            client_id,
-            user_id,
-            &scopes_str,
-            expires_at,
+            row.client_id,
+            row.user_id,
+            row.scopes,
+            expiry.expires_at,
+            refresh_expires_at,
+            row.family_id,
+            extra_fields,
+            self.hashers.current.key_id(),
+            self.hashers.current.algorithm(),
+            expiry.idle_timeout_secs,
+            expiry.idle_expires_at,
+            expiry.sliding_window_secs,
+            expiry.max_expires_at,
+            token_type,
+        )
+        .fetch_one(executor)
+        .await?;
+
+        Ok(stored)
+    }
+
+    /// Revoke every token in a rotation family.
+    async fn revoke_family<'e, E>(&self, executor: E, family_id: Uuid) -> Result<u64, Error>
+    where
+        E: PgExecutor<'e>,
+    {
+        let res = sqlx::query!(
+            r#"
+            UPDATE oauth2_tokens
+            SET revoked = TRUE
+            WHERE family_id = $1
+            "#,
+            family_id
+        )
+        .execute(executor)
+        .await?;
+
+        Ok(res.rows_affected())
+    }
+
+    /// Make room for one more token of `client_id` / `user_id` under [`TokenLimits`],
+    /// or fail with [`Error::LimitExceeded`]. Must run in the transaction that inserts it.
+    async fn enforce_limits(
+        &self,
+        conn: &mut PgConnection,
+        client_id: &str,
+        user_id: Option<Uuid>,
+    ) -> Result<(), Error> {
+        let limits = self.limits;
+
+        // The client lock covers every user of the client, so one lock is enough.
+        let lock_key = match limits.per_client {
+            Some(_) => format!("oauth2_tokens:{client_id}"),
+            None => format!(
+                "oauth2_tokens:{client_id}:{}",
+                user_id.map(|id| id.to_string()).unwrap_or_default()
+            ),
+        };
+        sqlx::query!("SELECT pg_advisory_xact_lock(hashtextextended($1, 0))", lock_key)
+            .fetch_one(&mut *conn)
+            .await?;
+
+        let caps = [
+            (true, limits.per_user_and_client),
+            (false, limits.per_client),
+        ];
+
+        for (per_user, max) in caps {
+            let Some(max) = max else {
+                continue;
+            };
+
+            let active = sqlx::query_scalar!(
+                r#"
+                SELECT COUNT(*) AS "count!" FROM oauth2_tokens
+                WHERE client_id = $1
+                  AND (NOT $2 OR user_id IS NOT DISTINCT FROM $3)
+                  AND NOT revoked
+                  AND (idle_expires_at IS NULL OR idle_expires_at > NOW())
+                  AND (
+                        expires_at IS NULL OR expires_at > NOW()
+                        OR (
+                            refresh_token_hash IS NOT NULL
+                            AND (refresh_expires_at IS NULL OR refresh_expires_at > NOW())
+                        )
+                  )
+                "#,
+                client_id,
+                per_user,
+                user_id,
+            )
+            .fetch_one(&mut *conn)
+            .await?;
+
+            if active < i64::from(max) {
+                continue;
+            }
+
+            if limits.policy == LimitPolicy::Reject {
+                let scope = if per_user { "this user of client" } else { "client" };
+                return Err(Error::LimitExceeded(format!(
+                    "{max} active tokens for {scope} {client_id}"
+                )));
+            }
+
+            let evicted = sqlx::query!(
+                r#"
+                UPDATE oauth2_tokens
+                SET revoked = TRUE
+                WHERE id IN (
+                    SELECT id FROM oauth2_tokens
+                    WHERE client_id = $1
+                      AND (NOT $2 OR user_id IS NOT DISTINCT FROM $3)
+                      AND NOT revoked
+                      AND (idle_expires_at IS NULL OR idle_expires_at > NOW())
+                      AND (
+                            expires_at IS NULL OR expires_at > NOW()
+                            OR (
+                                refresh_token_hash IS NOT NULL
+                                AND (refresh_expires_at IS NULL OR refresh_expires_at > NOW())
+                            )
+                      )
+                    ORDER BY issued_at, id
+                    LIMIT $4
+                )
+                "#,
+                client_id,
+                per_user,
+                user_id,
+                active - i64::from(max) + 1,
+            )
+            .execute(&mut *conn)
+            .await?
+            .rows_affected();
+
+            tracing::info!(client_id, evicted, "revoked oldest tokens over the active-token limit");
+        }
+
+        Ok(())
+    }
+
+    /// Write deadlines moved by [`expiry::touch`]. Never moves them backwards, in case
+    /// a concurrent lookup wrote later ones.
+    async fn write_deadlines(&self, token: &StoredToken) -> Result<(), Error> {
+        sqlx::query!(
+            r#"
+            UPDATE oauth2_tokens
+            SET idle_expires_at = GREATEST(idle_expires_at, $2),
+                expires_at = GREATEST(expires_at, $3)
+            WHERE id = $1
+            "#,
+            token.id,
+            token.idle_expires_at,
+            token.expires_at,
         )
         .execute(&self.pool)
         .await?;
 
         Ok(())
     }
+}
+
+#[async_trait]
+impl OAuth2TokenStore for PgTokenStore {
+    async fn store_token<EF, TT>(
+        &self,
+        token: &StandardTokenResponse<EF, TT>,
+        client_id: &str,
+        user_id: Option<Uuid>,
+        scopes: &[Scope],
+        refresh_expires_in: Option<Duration>,
+    ) -> Result<(), Error>
+    where
+        EF: ExtraTokenFields + Sync,
+        TT: TokenType + Sync,
+    {
+        let scopes_str: Vec<String> = scopes.iter().map(|s| s.to_string()).collect();
+        let row = NewTokenRow {
+            client_id,
+            user_id,
+            scopes: &scopes_str,
+            refresh_expires_in,
+            family_id: None,
+        };
+
+        if self.limits.per_user_and_client.is_none() && self.limits.per_client.is_none() {
+            self.insert_token(&self.pool, token, row).await?;
+            return Ok(());
+        }
+
+        let mut tx = self.pool.begin().await?;
+        self.enforce_limits(&mut tx, client_id, user_id).await?;
+        self.insert_token(&mut *tx, token, row).await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
 
     async fn get_by_access_token(&self, token: &AccessToken) -> Result<Option<StoredToken>, Error> {
-        let hash = self.hash_token(token.secret())?;
+        let hashes = self.lookup_candidates(token.secret())?;
 
-        let row = sqlx::query_as!(
+        let mut row = sqlx::query_as!(
             StoredToken,
             r#"
             SELECT * FROM oauth2_tokens
-            WHERE access_token_hash = $1
+            WHERE access_token_hash = ANY($1)
+              AND (access_token_hash, hash_algorithm, COALESCE(key_id, ''))
+                  IN (SELECT * FROM UNNEST($1::text[], $2::text[], $3::text[]))
               AND NOT revoked
               AND (expires_at IS NULL OR expires_at > NOW())
+              AND (idle_expires_at IS NULL OR idle_expires_at > NOW())
             "#,
-            hash
+            &hashes.hashes,
+            &hashes.algorithms,
+            &hashes.key_ids
         )
         .fetch_optional(&self.pool)
         .await?;
 
+        if let Some(row) = &mut row
+            && expiry::touch(row, Utc::now(), true)
+        {
+            self.write_deadlines(row).await?;
+        }
+
+        if let (Some(usage), Some(row)) = (&self.usage, &row)
+            && usage.record(row.id)
+        {
+            usage::spawn_flusher(self.clone(), usage, |store| async move { store.flush_usage().await });
+        }
+
         Ok(row)
     }
 
     async fn get_by_refresh_token(&self, token: &RefreshToken) -> Result<Option<StoredToken>, Error> {
-        let hash = self.hash_token(token.secret())?;
+        let Some(mut row) = self.find_by_refresh_token(token).await? else {
+            return Ok(None);
+        };
+
+        if row.rotated_at.is_some() {
+            self.revoke_family(&self.pool, row.family_id).await?;
+            return Err(Error::RefreshTokenReuse(row.family_id));
+        }
+
+        let now = Utc::now();
+        let refresh_expired = row
+            .refresh_expires_at
+            .is_some_and(|t| t <= now);
+
+        if row.revoked || refresh_expired || row.is_idle_expired(now) {
+            return Ok(None);
+        }
+
+        if expiry::touch(&mut row, now, false) {
+            self.write_deadlines(&row).await?;
+        }
+
+        Ok(Some(row))
+    }
+
+    async fn find_by_access_token(&self, token: &AccessToken) -> Result<Option<StoredToken>, Error> {
+        let hashes = self.lookup_candidates(token.secret())?;
 
         let row = sqlx::query_as!(
             StoredToken,
             r#"
             SELECT * FROM oauth2_tokens
-            WHERE refresh_token_hash = $1
-              AND NOT revoked
-              AND (expires_at IS NULL OR expires_at > NOW())
+            WHERE access_token_hash = ANY($1)
+              AND (access_token_hash, hash_algorithm, COALESCE(key_id, ''))
+                  IN (SELECT * FROM UNNEST($1::text[], $2::text[], $3::text[]))
+            "#,
+            &hashes.hashes,
+            &hashes.algorithms,
+            &hashes.key_ids
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row)
+    }
+
+    async fn find_by_refresh_token(&self, token: &RefreshToken) -> Result<Option<StoredToken>, Error> {
+        let hashes = self.lookup_candidates(token.secret())?;
+
+        let row = sqlx::query_as!(
+            StoredToken,
+            r#"
+            SELECT * FROM oauth2_tokens
+            WHERE refresh_token_hash = ANY($1)
+              AND (refresh_token_hash, hash_algorithm, COALESCE(key_id, ''))
+                  IN (SELECT * FROM UNNEST($1::text[], $2::text[], $3::text[]))
             "#,
-            hash
+            &hashes.hashes,
+            &hashes.algorithms,
+            &hashes.key_ids
         )
         .fetch_optional(&self.pool)
         .await?;
@@ -186,15 +928,19 @@ impl OAuth2TokenStore for PgTokenStore {
     }
 
     async fn revoke_by_access_token(&self, token: &AccessToken) -> Result<(), Error> {
-        let hash = self.hash_token(token.secret())?;
+        let hashes = self.lookup_candidates(token.secret())?;
 
         let res = sqlx::query!(
             r#"
             UPDATE oauth2_tokens
             SET revoked = TRUE
-            WHERE access_token_hash = $1
+            WHERE access_token_hash = ANY($1)
+              AND (access_token_hash, hash_algorithm, COALESCE(key_id, ''))
+                  IN (SELECT * FROM UNNEST($1::text[], $2::text[], $3::text[]))
             "#,
-            hash
+            &hashes.hashes,
+            &hashes.algorithms,
+            &hashes.key_ids
         )
         .execute(&self.pool)
         .await?;
@@ -207,15 +953,19 @@ impl OAuth2TokenStore for PgTokenStore {
     }
 
     async fn revoke_by_refresh_token(&self, token: &RefreshToken) -> Result<(), Error> {
-        let hash = self.hash_token(token.secret())?;
+        let hashes = self.lookup_candidates(token.secret())?;
 
         let res = sqlx::query!(
             r#"
             UPDATE oauth2_tokens
             SET revoked = TRUE
-            WHERE refresh_token_hash = $1
+            WHERE refresh_token_hash = ANY($1)
+              AND (refresh_token_hash, hash_algorithm, COALESCE(key_id, ''))
+                  IN (SELECT * FROM UNNEST($1::text[], $2::text[], $3::text[]))
             "#,
-            hash
+            &hashes.hashes,
+            &hashes.algorithms,
+            &hashes.key_ids
         )
         .execute(&self.pool)
         .await?;
@@ -227,17 +977,337 @@ impl OAuth2TokenStore for PgTokenStore {
         Ok(())
     }
 
+    async fn rotate_refresh_token<EF, TT>(
+        &self,
+        old: &RefreshToken,
+        new: &StandardTokenResponse<EF, TT>,
+        refresh_expires_in: Option<Duration>,
+    ) -> Result<StoredToken, Error>
+    where
+        EF: ExtraTokenFields + Sync,
+        TT: TokenType + Sync,
+    {
+        let hashes = self.lookup_candidates(old.secret())?;
+
+        let mut tx = self.pool.begin().await?;
+
+        // Row lock serialises concurrent rotations of the same refresh token:
+        // the loser blocks here and then sees `rotated_at` set.
+        let current = sqlx::query_as!(
+            StoredToken,
+            r#"
+            SELECT * FROM oauth2_tokens
+            WHERE refresh_token_hash = ANY($1)
+              AND (refresh_token_hash, hash_algorithm, COALESCE(key_id, ''))
+                  IN (SELECT * FROM UNNEST($1::text[], $2::text[], $3::text[]))
+            FOR UPDATE
+            "#,
+            &hashes.hashes,
+            &hashes.algorithms,
+            &hashes.key_ids
+        )
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(Error::NotFound)?;
+
+        if current.rotated_at.is_some() {
+            self.revoke_family(&mut *tx, current.family_id).await?;
+            tx.commit().await?;
+            return Err(Error::RefreshTokenReuse(current.family_id));
+        }
+
+        let now = Utc::now();
+        let refresh_expired = current
+            .refresh_expires_at
+            .is_some_and(|t| t <= now);
+
+        if current.revoked || refresh_expired || current.is_idle_expired(now) {
+            return Err(Error::InvalidToken);
+        }
+
+        sqlx::query!(
+            r#"
+            UPDATE oauth2_tokens
+            SET revoked = TRUE, rotated_at = NOW()
+            WHERE id = $1
+            "#,
+            current.id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        let stored = self
+            .insert_token(
+                &mut *tx,
+                new,
+                NewTokenRow {
+                    client_id: &current.client_id,
+                    user_id: current.user_id,
+                    scopes: &current.scopes,
+                    refresh_expires_in,
+                    family_id: Some(current.family_id),
+                },
+            )
+            .await?;
+
+        tx.commit().await?;
+
+        Ok(stored)
+    }
+
+    async fn revoke(
+        &self,
+        token: &str,
+        token_type_hint: Option<TokenTypeHint>,
+        client_id: &str,
+    ) -> Result<(), Error> {
+        let hashes = self.lookup_candidates(token)?;
+
+        for kind in TokenTypeHint::lookup_order(token_type_hint) {
+            let found = match kind {
+                TokenTypeHint::AccessToken => sqlx::query!(
+                    r#"
+                    SELECT id, client_id, family_id FROM oauth2_tokens
+                    WHERE access_token_hash = ANY($1)
+                      AND (access_token_hash, hash_algorithm, COALESCE(key_id, ''))
+                          IN (SELECT * FROM UNNEST($1::text[], $2::text[], $3::text[]))
+                    "#,
+                    &hashes.hashes,
+                    &hashes.algorithms,
+                    &hashes.key_ids
+                )
+                .fetch_optional(&self.pool)
+                .await?
+                .map(|r| (r.id, r.client_id, r.family_id)),
+                TokenTypeHint::RefreshToken => sqlx::query!(
+                    r#"
+                    SELECT id, client_id, family_id FROM oauth2_tokens
+                    WHERE refresh_token_hash = ANY($1)
+                      AND (refresh_token_hash, hash_algorithm, COALESCE(key_id, ''))
+                          IN (SELECT * FROM UNNEST($1::text[], $2::text[], $3::text[]))
+                    "#,
+                    &hashes.hashes,
+                    &hashes.algorithms,
+                    &hashes.key_ids
+                )
+                .fetch_optional(&self.pool)
+                .await?
+                .map(|r| (r.id, r.client_id, r.family_id)),
+            };
+
+            let Some((id, owner, family_id)) = found else {
+                continue;
+            };
+
+            if owner != client_id {
+                return Err(Error::UnauthorizedClient);
+            }
+
+            match kind {
+                TokenTypeHint::AccessToken => {
+                    sqlx::query!(
+                        r#"
+                        UPDATE oauth2_tokens
+                        SET revoked = TRUE
+                        WHERE id = $1
+                        "#,
+                        id
+                    )
+                    .execute(&self.pool)
+                    .await?;
+                }
+                TokenTypeHint::RefreshToken => {
+                    self.revoke_family(&self.pool, family_id).await?;
+                }
+            }
+
+            return Ok(());
+        }
+
+        Ok(())
+    }
+
+    async fn list_tokens_for_user(&self, user_id: Uuid, page: PageRequest) -> Result<TokenPage, Error> {
+        let (after_issued_at, after_id) = match page.after {
+            Some(cursor) => (Some(cursor.issued_at), Some(cursor.id)),
+            None => (None, None),
+        };
+
+        let rows = sqlx::query_as!(
+            StoredToken,
+            r#"
+            SELECT
+                id,
+                '' AS "access_token_hash!",
+                NULL::text AS refresh_token_hash,
+                client_id,
+                user_id,
+                scopes,
+                issued_at,
+                expires_at,
+                revoked,
+                revoked_at,
+                refresh_expires_at,
+                family_id,
+                rotated_at,
+                extra_fields,
+                key_id,
+                hash_algorithm,
+                last_used_at,
+                use_count,
+                idle_timeout_secs,
+                idle_expires_at,
+                sliding_window_secs,
+                max_expires_at,
+                token_type
+            FROM oauth2_tokens
+            WHERE user_id = $1
+              AND NOT revoked
+              AND (idle_expires_at IS NULL OR idle_expires_at > NOW())
+              AND (
+                    expires_at IS NULL OR expires_at > NOW()
+                    OR (
+                        refresh_token_hash IS NOT NULL
+                        AND (refresh_expires_at IS NULL OR refresh_expires_at > NOW())
+                    )
+              )
+              AND ($2::timestamptz IS NULL OR (issued_at, id) < ($2, $3))
+            ORDER BY issued_at DESC, id DESC
+            LIMIT $4
+            "#,
+            user_id,
+            after_issued_at,
+            after_id,
+            i64::from(page.limit) + 1,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(TokenPage::from_rows(rows, page.limit))
+    }
+
+    async fn revoke_by_id(&self, id: Uuid) -> Result<(), Error> {
+        let res = sqlx::query!(
+            r#"
+            UPDATE oauth2_tokens
+            SET revoked = TRUE
+            WHERE id = $1
+            "#,
+            id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        if res.rows_affected() == 0 {
+            return Err(Error::NotFound);
+        }
+
+        Ok(())
+    }
+
+    async fn revoke_all_for_user(&self, user_id: Uuid, except: Option<Uuid>) -> Result<usize, Error> {
+        let res = sqlx::query!(
+            r#"
+            UPDATE oauth2_tokens
+            SET revoked = TRUE
+            WHERE user_id = $1
+              AND NOT revoked
+              AND ($2::uuid IS NULL OR id <> $2)
+            "#,
+            user_id,
+            except
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(res.rows_affected() as usize)
+    }
+
+    async fn revoke_all_for_client(&self, client_id: &str, except: Option<Uuid>) -> Result<usize, Error> {
+        let res = sqlx::query!(
+            r#"
+            UPDATE oauth2_tokens
+            SET revoked = TRUE
+            WHERE client_id = $1
+              AND NOT revoked
+              AND ($2::uuid IS NULL OR id <> $2)
+            "#,
+            client_id,
+            except
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(res.rows_affected() as usize)
+    }
+
+    async fn revoke_all_for_user_and_client(
+        &self,
+        user_id: Uuid,
+        client_id: &str,
+        except: Option<Uuid>,
+    ) -> Result<usize, Error> {
+        let res = sqlx::query!(
+            r#"
+            UPDATE oauth2_tokens
+            SET revoked = TRUE
+            WHERE user_id = $1
+              AND client_id = $2
+              AND NOT revoked
+              AND ($3::uuid IS NULL OR id <> $3)
+            "#,
+            user_id,
+            client_id,
+            except
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(res.rows_affected() as usize)
+    }
+
     async fn cleanup(&self) -> Result<usize, Error> {
         let res = sqlx::query!(
             r#"
             DELETE FROM oauth2_tokens
-            WHERE revoked = TRUE
-               OR (expires_at IS NOT NULL AND expires_at < NOW())
+            WHERE (
+                    revoked = TRUE
+                    -- rotated refresh tokens are kept for reuse detection until they expire, for
+                    -- good if they never do
+                    AND NOT (rotated_at IS NOT NULL AND COALESCE(refresh_expires_at > NOW(), TRUE))
+               )
+               OR (
+                    expires_at IS NOT NULL AND expires_at < NOW()
+                    AND (
+                        refresh_token_hash IS NULL
+                        OR (refresh_expires_at IS NOT NULL AND refresh_expires_at < NOW())
+                    )
+               )
+               -- idled out for good; rotated rows stay for reuse detection as above
+               OR (idle_expires_at IS NOT NULL AND idle_expires_at < NOW() AND rotated_at IS NULL)
             "#
         )
         .execute(&self.pool)
         .await?;
 
-        Ok(res.rows_affected() as usize)
+        let codes = sqlx::query!(
+            r#"
+            DELETE FROM oauth2_authorization_codes
+            WHERE expires_at < NOW()
+            "#
+        )
+        .execute(&self.pool)
+        .await?;
+
+        let device_codes = sqlx::query!(
+            r#"
+            DELETE FROM oauth2_device_codes
+            WHERE expires_at < NOW()
+            "#
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok((res.rows_affected() + codes.rows_affected() + device_codes.rows_affected()) as usize)
     }
 }
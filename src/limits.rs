@@ -0,0 +1,58 @@
+//! Caps on how many active tokens a client, or one user of a client, may hold.
+
+/// What [`PgTokenStore::store_token`](crate::OAuth2TokenStore::store_token) does when a
+/// new token would exceed a [`TokenLimits`] cap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LimitPolicy {
+    /// Fail with [`Error::LimitExceeded`](crate::Error::LimitExceeded) and store nothing.
+    #[default]
+    Reject,
+    /// Revoke the oldest active tokens (by `issued_at`) to make room for the new one.
+    EvictOldest,
+}
+
+/// Maximum number of active tokens per (user, client) pair and per client, set with
+/// [`PgTokenStore::with_token_limits`](crate::PgTokenStore::with_token_limits).
+///
+/// A token counts as active while its access token or refresh token is still usable,
+/// the same rows [`list_tokens_for_user`](crate::OAuth2TokenStore::list_tokens_for_user)
+/// returns. Tokens without a user form their own group under the per-(user, client) cap.
+///
+/// ```
+/// use oauth2_pg_store::{LimitPolicy, TokenLimits};
+///
+/// let limits = TokenLimits::new(LimitPolicy::EvictOldest)
+///     .with_per_user_and_client(20)
+///     .with_per_client(100_000);
+/// ```
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TokenLimits {
+    pub(crate) per_user_and_client: Option<u32>,
+    pub(crate) per_client: Option<u32>,
+    pub(crate) policy: LimitPolicy,
+}
+
+impl TokenLimits {
+    /// No caps yet; `policy` applies to the ones added.
+    pub fn new(policy: LimitPolicy) -> Self {
+        Self {
+            policy,
+            ..Self::default()
+        }
+    }
+
+    /// At most `max` (at least 1) active tokens per user of a client.
+    pub fn with_per_user_and_client(mut self, max: u32) -> Self {
+        self.per_user_and_client = Some(max.max(1));
+        self
+    }
+
+    /// At most `max` (at least 1) active tokens per client, across all of its users.
+    ///
+    /// Under [`LimitPolicy::EvictOldest`] this revokes the client's oldest tokens,
+    /// whichever user holds them.
+    pub fn with_per_client(mut self, max: u32) -> Self {
+        self.per_client = Some(max.max(1));
+        self
+    }
+}
@@ -0,0 +1,444 @@
+//! In-process token store for tests and local development.
+
+use async_trait::async_trait;
+use chrono::Utc;
+use oauth2::{AccessToken, ExtraTokenFields, RefreshToken, Scope, StandardTokenResponse, TokenType};
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::expiry::{self, ExpiryPolicy};
+use crate::hashing::{Candidates, Hashers};
+use crate::usage::{self, UsageRecorder};
+use crate::{
+    Error, NewTokenRow, OAuth2TokenStore, PageRequest, StoredToken, TokenHasher, TokenPage,
+    TokenTypeHint,
+};
+
+/// Mark `token` revoked, recording when like the SQL backends' trigger does.
+fn mark_revoked(token: &mut StoredToken) {
+    if !token.revoked {
+        token.revoked = true;
+        token.revoked_at = Some(Utc::now());
+    }
+}
+
+/// [`OAuth2TokenStore`] kept in memory, with the same hashing, expiry, rotation,
+/// revocation and cleanup behaviour as [`PgTokenStore`](crate::PgTokenStore).
+///
+/// Clones share the same tokens. Nothing is persisted, so this is meant for unit tests
+/// and local development, not production.
+#[derive(Clone, Default)]
+pub struct InMemoryTokenStore {
+    tokens: Arc<Mutex<HashMap<Uuid, StoredToken>>>,
+    hashers: Arc<Hashers>,
+    usage: Option<Arc<UsageRecorder>>,
+    expiry: ExpiryPolicy,
+}
+
+impl InMemoryTokenStore {
+    /// Create an empty store.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Hash new tokens with `hasher` instead of plain BLAKE3.
+    ///
+    /// See [`PgTokenStore::with_hasher`](crate::PgTokenStore::with_hasher).
+    pub fn with_hasher(mut self, hasher: impl TokenHasher) -> Self {
+        Arc::make_mut(&mut self.hashers).current = Arc::new(hasher);
+        self
+    }
+
+    /// Keep accepting tokens hashed by an algorithm or key that is no longer current.
+    pub fn with_legacy_hasher(mut self, hasher: impl TokenHasher) -> Self {
+        Arc::make_mut(&mut self.hashers).legacy.push(Arc::new(hasher));
+        self
+    }
+
+    /// Record `last_used_at` and `use_count` on every successful access-token lookup,
+    /// writing them every `flush_interval`.
+    ///
+    /// See [`PgTokenStore::with_usage_tracking`](crate::PgTokenStore::with_usage_tracking).
+    pub fn with_usage_tracking(mut self, flush_interval: Duration) -> Self {
+        self.usage = Some(Arc::new(UsageRecorder::new(flush_interval)));
+        self
+    }
+
+    /// End sessions that go unused for `timeout`.
+    ///
+    /// See [`PgTokenStore::with_idle_timeout`](crate::PgTokenStore::with_idle_timeout).
+    pub fn with_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.expiry.idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Slide `expires_at` on use, but never past `max_lifetime` after issuance.
+    ///
+    /// See [`PgTokenStore::with_sliding_expiration`](crate::PgTokenStore::with_sliding_expiration).
+    pub fn with_sliding_expiration(mut self, max_lifetime: Duration) -> Self {
+        self.expiry.max_lifetime = Some(max_lifetime);
+        self
+    }
+
+    /// Write buffered usage now. Returns the number of tokens updated.
+    pub async fn flush_usage(&self) -> Result<usize, Error> {
+        let Some(usage) = &self.usage else {
+            return Ok(0);
+        };
+
+        let mut tokens = self.tokens();
+        let mut updated = 0;
+        for (id, use_) in usage.take() {
+            if let Some(token) = tokens.get_mut(&id) {
+                token.use_count += use_.uses;
+                token.last_used_at = token.last_used_at.max(Some(use_.last_used_at));
+                updated += 1;
+            }
+        }
+
+        Ok(updated)
+    }
+
+    fn tokens(&self) -> MutexGuard<'_, HashMap<Uuid, StoredToken>> {
+        // A panic while holding the lock cannot leave a row half-written.
+        self.tokens.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Id of the row whose access or refresh token matches one of the lookup `hashes`.
+    fn find(
+        tokens: &HashMap<Uuid, StoredToken>,
+        kind: TokenTypeHint,
+        hashes: &Candidates,
+    ) -> Option<Uuid> {
+        tokens
+            .values()
+            .find(|t| {
+                let hash = match kind {
+                    TokenTypeHint::AccessToken => Some(&t.access_token_hash),
+                    TokenTypeHint::RefreshToken => t.refresh_token_hash.as_ref(),
+                };
+                hash.is_some_and(|h| hashes.matches(h, &t.hash_algorithm, t.key_id.as_deref()))
+            })
+            .map(|t| t.id)
+    }
+
+    /// Revoke every token in a rotation family.
+    fn revoke_family(tokens: &mut HashMap<Uuid, StoredToken>, family_id: Uuid) {
+        tokens
+            .values_mut()
+            .filter(|t| t.family_id == family_id)
+            .for_each(mark_revoked);
+    }
+
+    /// Revoke every unrevoked row matching `filter` except `except`.
+    fn revoke_where(&self, except: Option<Uuid>, filter: impl Fn(&StoredToken) -> bool) -> usize {
+        let mut tokens = self.tokens();
+        let mut revoked = 0;
+
+        for token in tokens.values_mut() {
+            if !token.revoked && Some(token.id) != except && filter(token) {
+                mark_revoked(token);
+                revoked += 1;
+            }
+        }
+
+        revoked
+    }
+}
+
+#[async_trait]
+impl OAuth2TokenStore for InMemoryTokenStore {
+    async fn store_token<EF, TT>(
+        &self,
+        token: &StandardTokenResponse<EF, TT>,
+        client_id: &str,
+        user_id: Option<Uuid>,
+        scopes: &[Scope],
+        refresh_expires_in: Option<Duration>,
+    ) -> Result<(), Error>
+    where
+        EF: ExtraTokenFields + Sync,
+        TT: TokenType + Sync,
+    {
+        let scopes_str: Vec<String> = scopes.iter().map(|s| s.to_string()).collect();
+
+        let row = NewTokenRow {
+            client_id,
+            user_id,
+            scopes: &scopes_str,
+            refresh_expires_in,
+            family_id: None,
+        }
+        .build(token, &self.hashers, self.expiry, Utc::now())?;
+
+        self.tokens().insert(row.id, row);
+
+        Ok(())
+    }
+
+    async fn get_by_access_token(&self, token: &AccessToken) -> Result<Option<StoredToken>, Error> {
+        let hashes = self.hashers.candidates(token.secret())?;
+        let mut tokens = self.tokens();
+        let now = Utc::now();
+
+        let row = Self::find(&tokens, TokenTypeHint::AccessToken, &hashes)
+            .and_then(|id| tokens.get_mut(&id))
+            .filter(|t| {
+                !t.revoked && t.expires_at.is_none_or(|e| e > now) && !t.is_idle_expired(now)
+            })
+            .map(|t| {
+                expiry::touch(t, now, true);
+                t.clone()
+            });
+        drop(tokens);
+
+        if let (Some(usage), Some(row)) = (&self.usage, &row)
+            && usage.record(row.id)
+        {
+            usage::spawn_flusher(self.clone(), usage, |store| async move { store.flush_usage().await });
+        }
+
+        Ok(row)
+    }
+
+    async fn get_by_refresh_token(&self, token: &RefreshToken) -> Result<Option<StoredToken>, Error> {
+        let hashes = self.hashers.candidates(token.secret())?;
+        let mut tokens = self.tokens();
+
+        let Some(id) = Self::find(&tokens, TokenTypeHint::RefreshToken, &hashes) else {
+            return Ok(None);
+        };
+        let row = tokens[&id].clone();
+
+        if row.rotated_at.is_some() {
+            Self::revoke_family(&mut tokens, row.family_id);
+            return Err(Error::RefreshTokenReuse(row.family_id));
+        }
+
+        let now = Utc::now();
+        let refresh_expired = row
+            .refresh_expires_at
+            .is_some_and(|t| t <= now);
+
+        if row.revoked || refresh_expired || row.is_idle_expired(now) {
+            return Ok(None);
+        }
+
+        let Some(row) = tokens.get_mut(&id) else {
+            return Ok(None);
+        };
+        expiry::touch(row, now, false);
+
+        Ok(Some(row.clone()))
+    }
+
+    async fn find_by_access_token(&self, token: &AccessToken) -> Result<Option<StoredToken>, Error> {
+        let hashes = self.hashers.candidates(token.secret())?;
+        let tokens = self.tokens();
+
+        Ok(Self::find(&tokens, TokenTypeHint::AccessToken, &hashes).map(|id| tokens[&id].clone()))
+    }
+
+    async fn find_by_refresh_token(&self, token: &RefreshToken) -> Result<Option<StoredToken>, Error> {
+        let hashes = self.hashers.candidates(token.secret())?;
+        let tokens = self.tokens();
+
+        Ok(Self::find(&tokens, TokenTypeHint::RefreshToken, &hashes).map(|id| tokens[&id].clone()))
+    }
+
+    async fn revoke_by_access_token(&self, token: &AccessToken) -> Result<(), Error> {
+        let hashes = self.hashers.candidates(token.secret())?;
+        let mut tokens = self.tokens();
+
+        let id = Self::find(&tokens, TokenTypeHint::AccessToken, &hashes).ok_or(Error::NotFound)?;
+        if let Some(t) = tokens.get_mut(&id) {
+            mark_revoked(t);
+        }
+
+        Ok(())
+    }
+
+    async fn revoke_by_refresh_token(&self, token: &RefreshToken) -> Result<(), Error> {
+        let hashes = self.hashers.candidates(token.secret())?;
+        let mut tokens = self.tokens();
+
+        let id = Self::find(&tokens, TokenTypeHint::RefreshToken, &hashes).ok_or(Error::NotFound)?;
+        if let Some(t) = tokens.get_mut(&id) {
+            mark_revoked(t);
+        }
+
+        Ok(())
+    }
+
+    async fn rotate_refresh_token<EF, TT>(
+        &self,
+        old: &RefreshToken,
+        new: &StandardTokenResponse<EF, TT>,
+        refresh_expires_in: Option<Duration>,
+    ) -> Result<StoredToken, Error>
+    where
+        EF: ExtraTokenFields + Sync,
+        TT: TokenType + Sync,
+    {
+        let hashes = self.hashers.candidates(old.secret())?;
+
+        // Holding the lock for the whole exchange serialises concurrent rotations,
+        // like the row lock in `PgTokenStore`.
+        let mut tokens = self.tokens();
+
+        let id = Self::find(&tokens, TokenTypeHint::RefreshToken, &hashes).ok_or(Error::NotFound)?;
+        let current = tokens[&id].clone();
+
+        if current.rotated_at.is_some() {
+            Self::revoke_family(&mut tokens, current.family_id);
+            return Err(Error::RefreshTokenReuse(current.family_id));
+        }
+
+        let now = Utc::now();
+        let refresh_expired = current
+            .refresh_expires_at
+            .is_some_and(|t| t <= now);
+
+        if current.revoked || refresh_expired || current.is_idle_expired(now) {
+            return Err(Error::InvalidToken);
+        }
+
+        let stored = NewTokenRow {
+            client_id: &current.client_id,
+            user_id: current.user_id,
+            scopes: &current.scopes,
+            refresh_expires_in,
+            family_id: Some(current.family_id),
+        }
+        .build(new, &self.hashers, self.expiry, Utc::now())?;
+
+        if let Some(t) = tokens.get_mut(&id) {
+            mark_revoked(t);
+            t.rotated_at = Some(Utc::now());
+        }
+        tokens.insert(stored.id, stored.clone());
+
+        Ok(stored)
+    }
+
+    async fn revoke(
+        &self,
+        token: &str,
+        token_type_hint: Option<TokenTypeHint>,
+        client_id: &str,
+    ) -> Result<(), Error> {
+        let hashes = self.hashers.candidates(token)?;
+        let mut tokens = self.tokens();
+
+        for kind in TokenTypeHint::lookup_order(token_type_hint) {
+            let Some(id) = Self::find(&tokens, kind, &hashes) else {
+                continue;
+            };
+
+            let (owner, family_id) = {
+                let t = &tokens[&id];
+                (t.client_id.clone(), t.family_id)
+            };
+
+            if owner != client_id {
+                return Err(Error::UnauthorizedClient);
+            }
+
+            match kind {
+                TokenTypeHint::AccessToken => {
+                    if let Some(t) = tokens.get_mut(&id) {
+                        mark_revoked(t);
+                    }
+                }
+                TokenTypeHint::RefreshToken => Self::revoke_family(&mut tokens, family_id),
+            }
+
+            return Ok(());
+        }
+
+        Ok(())
+    }
+
+    async fn list_tokens_for_user(&self, user_id: Uuid, page: PageRequest) -> Result<TokenPage, Error> {
+        let now = Utc::now();
+        let tokens = self.tokens();
+
+        let mut rows: Vec<StoredToken> = tokens
+            .values()
+            .filter(|t| t.user_id == Some(user_id) && !t.revoked && !t.is_idle_expired(now))
+            .filter(|t| {
+                t.expires_at.is_none_or(|e| e > now)
+                    || (t.refresh_token_hash.is_some()
+                        && t.refresh_expires_at.is_none_or(|e| e > now))
+            })
+            .filter(|t| {
+                page.after
+                    .is_none_or(|c| (t.issued_at, t.id) < (c.issued_at, c.id))
+            })
+            .cloned()
+            .collect();
+
+        rows.sort_by_key(|t| Reverse((t.issued_at, t.id)));
+        rows.truncate(page.limit as usize + 1);
+
+        for row in &mut rows {
+            row.access_token_hash = String::new();
+            row.refresh_token_hash = None;
+        }
+
+        Ok(TokenPage::from_rows(rows, page.limit))
+    }
+
+    async fn revoke_by_id(&self, id: Uuid) -> Result<(), Error> {
+        let mut tokens = self.tokens();
+        mark_revoked(tokens.get_mut(&id).ok_or(Error::NotFound)?);
+
+        Ok(())
+    }
+
+    async fn revoke_all_for_user(&self, user_id: Uuid, except: Option<Uuid>) -> Result<usize, Error> {
+        Ok(self.revoke_where(except, |t| t.user_id == Some(user_id)))
+    }
+
+    async fn revoke_all_for_client(&self, client_id: &str, except: Option<Uuid>) -> Result<usize, Error> {
+        Ok(self.revoke_where(except, |t| t.client_id == client_id))
+    }
+
+    async fn revoke_all_for_user_and_client(
+        &self,
+        user_id: Uuid,
+        client_id: &str,
+        except: Option<Uuid>,
+    ) -> Result<usize, Error> {
+        Ok(self.revoke_where(except, |t| {
+            t.user_id == Some(user_id) && t.client_id == client_id
+        }))
+    }
+
+    async fn cleanup(&self) -> Result<usize, Error> {
+        let now = Utc::now();
+        let mut tokens = self.tokens();
+        let before = tokens.len();
+
+        tokens.retain(|_, t| {
+            // Rotated refresh tokens are kept for reuse detection until they expire, for
+            // good if they never do.
+            let removable_revoked = t.revoked
+                && !(t.rotated_at.is_some() && t.refresh_expires_at.is_none_or(|e| e > now));
+
+            let fully_expired = t.expires_at.is_some_and(|e| e < now)
+                && (t.refresh_token_hash.is_none()
+                    || t.refresh_expires_at.is_some_and(|e| e < now));
+
+            // Rotated rows stay for reuse detection, as above.
+            let idled_out = t.rotated_at.is_none() && t.idle_expires_at.is_some_and(|e| e < now);
+
+            !(removable_revoked || fully_expired || idled_out)
+        });
+
+        Ok(before - tokens.len())
+    }
+}
@@ -0,0 +1,795 @@
+//! MySQL / MariaDB token store (enabled with the `mysql` feature).
+//!
+//! Uses the schema in `migrations/mysql`. UUIDs are stored as hyphenated `CHAR(36)`,
+//! scopes and extra fields as `JSON`, and timestamps as UTC `DATETIME(6)`. MySQL has
+//! no array parameters or `RETURNING`, so hash lookups expand to `IN (?, ...)` and
+//! inserted rows are built in Rust.
+
+use async_trait::async_trait;
+use chrono::{DateTime, SubsecRound, Utc};
+use oauth2::{AccessToken, ExtraTokenFields, RefreshToken, Scope, StandardTokenResponse, TokenType};
+use sqlx::mysql::MySqlRow;
+use sqlx::types::Json;
+use sqlx::{MySql, MySqlExecutor, MySqlPool, QueryBuilder, Row};
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::expiry::{self, ExpiryPolicy};
+use crate::hashing::{Candidates, Hashers};
+use crate::usage::{self, PendingUse, UsageRecorder};
+use crate::{
+    Error, NewTokenRow, OAuth2TokenStore, PageRequest, StoredToken, TokenHasher, TokenPage,
+    TokenTypeHint,
+};
+
+/// Current time at `DATETIME(6)` precision, so built rows match what is stored.
+fn now() -> DateTime<Utc> {
+    Utc::now().trunc_subsecs(6)
+}
+
+fn decode_uuid(s: &str) -> Result<Uuid, Error> {
+    Uuid::parse_str(s).map_err(|e| Error::Other(Box::new(e)))
+}
+
+/// Decode an `oauth2_tokens` row.
+fn token_from_row(row: &MySqlRow) -> Result<StoredToken, Error> {
+    let Json(scopes) = row.try_get("scopes")?;
+    let Json(extra_fields) = row.try_get("extra_fields")?;
+
+    Ok(StoredToken {
+        id: decode_uuid(row.try_get("id")?)?,
+        access_token_hash: row.try_get("access_token_hash")?,
+        refresh_token_hash: row.try_get("refresh_token_hash")?,
+        client_id: row.try_get("client_id")?,
+        user_id: row
+            .try_get::<Option<&str>, _>("user_id")?
+            .map(decode_uuid)
+            .transpose()?,
+        scopes,
+        issued_at: row.try_get("issued_at")?,
+        expires_at: row.try_get("expires_at")?,
+        revoked: row.try_get("revoked")?,
+        revoked_at: row.try_get("revoked_at")?,
+        refresh_expires_at: row.try_get("refresh_expires_at")?,
+        family_id: decode_uuid(row.try_get("family_id")?)?,
+        rotated_at: row.try_get("rotated_at")?,
+        extra_fields,
+        key_id: row.try_get("key_id")?,
+        hash_algorithm: row.try_get("hash_algorithm")?,
+        last_used_at: row.try_get("last_used_at")?,
+        use_count: row.try_get("use_count")?,
+        idle_timeout_secs: row.try_get("idle_timeout_secs")?,
+        idle_expires_at: row.try_get("idle_expires_at")?,
+        sliding_window_secs: row.try_get("sliding_window_secs")?,
+        max_expires_at: row.try_get("max_expires_at")?,
+        token_type: row.try_get("token_type")?,
+    })
+}
+
+/// Start a query from `sql`, which must end in a `WHERE` clause, and match `<column>`
+/// against the lookup `hashes`, each only on rows written by the hasher that produced it.
+fn match_hashes(sql: &str, column: &str, hashes: &Candidates) -> QueryBuilder<'static, MySql> {
+    let mut query = QueryBuilder::new(sql);
+    query.push(column).push(" IN (");
+
+    let mut list = query.separated(", ");
+    for hash in &hashes.hashes {
+        list.push_bind(hash.clone());
+    }
+    list.push_unseparated(")");
+
+    query
+        .push(" AND (")
+        .push(column)
+        .push(", hash_algorithm, COALESCE(key_id, '')) IN (");
+
+    let mut list = query.separated(", ");
+    for (hash, algorithm, key_id) in hashes.iter() {
+        list.push("(")
+            .push_bind_unseparated(hash.to_string())
+            .push_unseparated(", ")
+            .push_bind_unseparated(algorithm.to_string())
+            .push_unseparated(", ")
+            .push_bind_unseparated(key_id.to_string())
+            .push_unseparated(")");
+    }
+    list.push_unseparated(")");
+
+    query
+}
+
+/// MySQL / MariaDB implementation of [`OAuth2TokenStore`].
+#[derive(Clone)]
+pub struct MySqlTokenStore {
+    pool: MySqlPool,
+    hashers: Arc<Hashers>,
+    usage: Option<Arc<UsageRecorder>>,
+    expiry: ExpiryPolicy,
+}
+
+impl MySqlTokenStore {
+    /// Create a new store on a pool whose database has the `migrations/mysql` schema.
+    pub fn new(pool: MySqlPool) -> Self {
+        Self {
+            pool,
+            hashers: Arc::default(),
+            usage: None,
+            expiry: ExpiryPolicy::default(),
+        }
+    }
+
+    /// Hash new tokens with `hasher` instead of plain BLAKE3.
+    ///
+    /// See [`PgTokenStore::with_hasher`](crate::PgTokenStore::with_hasher).
+    pub fn with_hasher(mut self, hasher: impl TokenHasher) -> Self {
+        Arc::make_mut(&mut self.hashers).current = Arc::new(hasher);
+        self
+    }
+
+    /// Keep accepting tokens hashed by an algorithm or key that is no longer current.
+    pub fn with_legacy_hasher(mut self, hasher: impl TokenHasher) -> Self {
+        Arc::make_mut(&mut self.hashers).legacy.push(Arc::new(hasher));
+        self
+    }
+
+    /// Record `last_used_at` and `use_count` on every successful access-token lookup,
+    /// writing them every `flush_interval`.
+    ///
+    /// See [`PgTokenStore::with_usage_tracking`](crate::PgTokenStore::with_usage_tracking).
+    pub fn with_usage_tracking(mut self, flush_interval: Duration) -> Self {
+        self.usage = Some(Arc::new(UsageRecorder::new(flush_interval)));
+        self
+    }
+
+    /// End sessions that go unused for `timeout` (whole seconds).
+    ///
+    /// See [`PgTokenStore::with_idle_timeout`](crate::PgTokenStore::with_idle_timeout).
+    pub fn with_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.expiry.idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Slide `expires_at` on use, but never past `max_lifetime` after issuance.
+    ///
+    /// See [`PgTokenStore::with_sliding_expiration`](crate::PgTokenStore::with_sliding_expiration).
+    pub fn with_sliding_expiration(mut self, max_lifetime: Duration) -> Self {
+        self.expiry.max_lifetime = Some(max_lifetime);
+        self
+    }
+
+    /// Write buffered usage now. Returns the number of tokens updated.
+    pub async fn flush_usage(&self) -> Result<usize, Error> {
+        let Some(usage) = &self.usage else {
+            return Ok(0);
+        };
+
+        let batch = usage.take();
+        if batch.is_empty() {
+            return Ok(0);
+        }
+
+        match self.write_usage(&batch).await {
+            Ok(updated) => Ok(updated),
+            Err(e) => {
+                usage.restore(batch);
+                Err(e)
+            }
+        }
+    }
+
+    async fn write_usage(&self, batch: &[(Uuid, PendingUse)]) -> Result<usize, Error> {
+        let mut tx = self.pool.begin().await?;
+        let mut updated = 0;
+
+        for (id, use_) in batch {
+            let last_used_at = use_.last_used_at.trunc_subsecs(6);
+            let res = sqlx::query(
+                r#"
+                UPDATE oauth2_tokens
+                SET use_count = use_count + ?,
+                    last_used_at = GREATEST(COALESCE(last_used_at, ?), ?)
+                WHERE id = ?
+                "#,
+            )
+            .bind(use_.uses)
+            .bind(last_used_at)
+            .bind(last_used_at)
+            .bind(id.to_string())
+            .execute(&mut *tx)
+            .await?;
+
+            updated += res.rows_affected() as usize;
+        }
+
+        tx.commit().await?;
+        Ok(updated)
+    }
+
+    /// Write deadlines moved by [`expiry::touch`], never moving them backwards.
+    async fn write_deadlines(&self, token: &StoredToken) -> Result<(), Error> {
+        let idle_expires_at = token.idle_expires_at.map(|t| t.trunc_subsecs(6));
+        let expires_at = token.expires_at.map(|t| t.trunc_subsecs(6));
+
+        sqlx::query(
+            r#"
+            UPDATE oauth2_tokens
+            SET idle_expires_at = GREATEST(COALESCE(idle_expires_at, ?), ?),
+                expires_at = GREATEST(COALESCE(expires_at, ?), ?)
+            WHERE id = ?
+            "#,
+        )
+        .bind(idle_expires_at)
+        .bind(idle_expires_at)
+        .bind(expires_at)
+        .bind(expires_at)
+        .bind(token.id.to_string())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Insert a token row using the given executor (pool or open transaction).
+    async fn insert_token<'e, E, EF, TT>(
+        &self,
+        executor: E,
+        token: &StandardTokenResponse<EF, TT>,
+        row: NewTokenRow<'_>,
+    ) -> Result<StoredToken, Error>
+    where
+        E: MySqlExecutor<'e>,
+        EF: ExtraTokenFields,
+        TT: TokenType,
+    {
+        let stored = row.build(token, &self.hashers, self.expiry, now())?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO oauth2_tokens (
+                id,
+                access_token_hash,
+                refresh_token_hash,
+                client_id,
+                user_id,
+                scopes,
+                issued_at,
+                expires_at,
+                revoked,
+                refresh_expires_at,
+                family_id,
+                extra_fields,
+                key_id,
+                hash_algorithm,
+                idle_timeout_secs,
+                idle_expires_at,
+                sliding_window_secs,
+                max_expires_at,
+                token_type
+            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, FALSE, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(stored.id.to_string())
+        .bind(&stored.access_token_hash)
+        .bind(&stored.refresh_token_hash)
+        .bind(&stored.client_id)
+        .bind(stored.user_id.map(|id| id.to_string()))
+        .bind(Json(&stored.scopes))
+        .bind(stored.issued_at)
+        .bind(stored.expires_at)
+        .bind(stored.refresh_expires_at)
+        .bind(stored.family_id.to_string())
+        .bind(Json(&stored.extra_fields))
+        .bind(&stored.key_id)
+        .bind(&stored.hash_algorithm)
+        .bind(stored.idle_timeout_secs)
+        .bind(stored.idle_expires_at)
+        .bind(stored.sliding_window_secs)
+        .bind(stored.max_expires_at)
+        .bind(&stored.token_type)
+        .execute(executor)
+        .await?;
+
+        Ok(stored)
+    }
+
+    /// Revoke every token in a rotation family.
+    async fn revoke_family<'e, E>(&self, executor: E, family_id: Uuid) -> Result<u64, Error>
+    where
+        E: MySqlExecutor<'e>,
+    {
+        let res = sqlx::query(
+            r#"
+            UPDATE oauth2_tokens
+            SET revoked = TRUE
+            WHERE family_id = ?
+            "#,
+        )
+        .bind(family_id.to_string())
+        .execute(executor)
+        .await?;
+
+        Ok(res.rows_affected())
+    }
+}
+
+#[async_trait]
+impl OAuth2TokenStore for MySqlTokenStore {
+    async fn store_token<EF, TT>(
+        &self,
+        token: &StandardTokenResponse<EF, TT>,
+        client_id: &str,
+        user_id: Option<Uuid>,
+        scopes: &[Scope],
+        refresh_expires_in: Option<Duration>,
+    ) -> Result<(), Error>
+    where
+        EF: ExtraTokenFields + Sync,
+        TT: TokenType + Sync,
+    {
+        let scopes_str: Vec<String> = scopes.iter().map(|s| s.to_string()).collect();
+
+        self.insert_token(
+            &self.pool,
+            token,
+            NewTokenRow {
+                client_id,
+                user_id,
+                scopes: &scopes_str,
+                refresh_expires_in,
+                family_id: None,
+            },
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_by_access_token(&self, token: &AccessToken) -> Result<Option<StoredToken>, Error> {
+        let hashes = self.hashers.candidates(token.secret())?;
+
+        let mut query = match_hashes(
+            "SELECT * FROM oauth2_tokens WHERE ",
+            "access_token_hash",
+            &hashes,
+        );
+        let now = now();
+        query
+            .push(" AND NOT revoked AND (expires_at IS NULL OR expires_at > ")
+            .push_bind(now)
+            .push(") AND (idle_expires_at IS NULL OR idle_expires_at > ")
+            .push_bind(now)
+            .push(")");
+
+        let row = query.build().fetch_optional(&self.pool).await?;
+        let mut row = row.as_ref().map(token_from_row).transpose()?;
+
+        if let Some(row) = &mut row
+            && expiry::touch(row, now, true)
+        {
+            self.write_deadlines(row).await?;
+        }
+
+        if let (Some(usage), Some(row)) = (&self.usage, &row)
+            && usage.record(row.id)
+        {
+            usage::spawn_flusher(self.clone(), usage, |store| async move { store.flush_usage().await });
+        }
+
+        Ok(row)
+    }
+
+    async fn get_by_refresh_token(&self, token: &RefreshToken) -> Result<Option<StoredToken>, Error> {
+        let Some(mut row) = self.find_by_refresh_token(token).await? else {
+            return Ok(None);
+        };
+
+        if row.rotated_at.is_some() {
+            self.revoke_family(&self.pool, row.family_id).await?;
+            return Err(Error::RefreshTokenReuse(row.family_id));
+        }
+
+        let now = now();
+        let refresh_expired = row
+            .refresh_expires_at
+            .is_some_and(|t| t <= now);
+
+        if row.revoked || refresh_expired || row.is_idle_expired(now) {
+            return Ok(None);
+        }
+
+        if expiry::touch(&mut row, now, false) {
+            self.write_deadlines(&row).await?;
+        }
+
+        Ok(Some(row))
+    }
+
+    async fn find_by_access_token(&self, token: &AccessToken) -> Result<Option<StoredToken>, Error> {
+        let hashes = self.hashers.candidates(token.secret())?;
+
+        let row = match_hashes(
+            "SELECT * FROM oauth2_tokens WHERE ",
+            "access_token_hash",
+            &hashes,
+        )
+        .build()
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.as_ref().map(token_from_row).transpose()
+    }
+
+    async fn find_by_refresh_token(&self, token: &RefreshToken) -> Result<Option<StoredToken>, Error> {
+        let hashes = self.hashers.candidates(token.secret())?;
+
+        let row = match_hashes(
+            "SELECT * FROM oauth2_tokens WHERE ",
+            "refresh_token_hash",
+            &hashes,
+        )
+        .build()
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.as_ref().map(token_from_row).transpose()
+    }
+
+    async fn revoke_by_access_token(&self, token: &AccessToken) -> Result<(), Error> {
+        let hashes = self.hashers.candidates(token.secret())?;
+
+        let res = match_hashes(
+            "UPDATE oauth2_tokens SET revoked = TRUE WHERE ",
+            "access_token_hash",
+            &hashes,
+        )
+        .build()
+        .execute(&self.pool)
+        .await?;
+
+        if res.rows_affected() == 0 {
+            return Err(Error::NotFound);
+        }
+
+        Ok(())
+    }
+
+    async fn revoke_by_refresh_token(&self, token: &RefreshToken) -> Result<(), Error> {
+        let hashes = self.hashers.candidates(token.secret())?;
+
+        let res = match_hashes(
+            "UPDATE oauth2_tokens SET revoked = TRUE WHERE ",
+            "refresh_token_hash",
+            &hashes,
+        )
+        .build()
+        .execute(&self.pool)
+        .await?;
+
+        if res.rows_affected() == 0 {
+            return Err(Error::NotFound);
+        }
+
+        Ok(())
+    }
+
+    async fn rotate_refresh_token<EF, TT>(
+        &self,
+        old: &RefreshToken,
+        new: &StandardTokenResponse<EF, TT>,
+        refresh_expires_in: Option<Duration>,
+    ) -> Result<StoredToken, Error>
+    where
+        EF: ExtraTokenFields + Sync,
+        TT: TokenType + Sync,
+    {
+        let hashes = self.hashers.candidates(old.secret())?;
+
+        let mut tx = self.pool.begin().await?;
+
+        // Row lock serialises concurrent rotations of the same refresh token:
+        // the loser blocks here and then sees `rotated_at` set.
+        let mut query = match_hashes(
+            "SELECT * FROM oauth2_tokens WHERE ",
+            "refresh_token_hash",
+            &hashes,
+        );
+        query.push(" FOR UPDATE");
+
+        let current = query
+            .build()
+            .fetch_optional(&mut *tx)
+            .await?
+            .as_ref()
+            .map(token_from_row)
+            .transpose()?
+            .ok_or(Error::NotFound)?;
+
+        if current.rotated_at.is_some() {
+            self.revoke_family(&mut *tx, current.family_id).await?;
+            tx.commit().await?;
+            return Err(Error::RefreshTokenReuse(current.family_id));
+        }
+
+        let refresh_expired = current
+            .refresh_expires_at
+            .is_some_and(|t| t <= Utc::now());
+
+        if current.revoked || refresh_expired || current.is_idle_expired(Utc::now()) {
+            return Err(Error::InvalidToken);
+        }
+
+        sqlx::query(
+            r#"
+            UPDATE oauth2_tokens
+            SET revoked = TRUE, rotated_at = ?
+            WHERE id = ?
+            "#,
+        )
+        .bind(now())
+        .bind(current.id.to_string())
+        .execute(&mut *tx)
+        .await?;
+
+        let stored = self
+            .insert_token(
+                &mut *tx,
+                new,
+                NewTokenRow {
+                    client_id: &current.client_id,
+                    user_id: current.user_id,
+                    scopes: &current.scopes,
+                    refresh_expires_in,
+                    family_id: Some(current.family_id),
+                },
+            )
+            .await?;
+
+        tx.commit().await?;
+
+        Ok(stored)
+    }
+
+    async fn revoke(
+        &self,
+        token: &str,
+        token_type_hint: Option<TokenTypeHint>,
+        client_id: &str,
+    ) -> Result<(), Error> {
+        let hashes = self.hashers.candidates(token)?;
+
+        for kind in TokenTypeHint::lookup_order(token_type_hint) {
+            let column = match kind {
+                TokenTypeHint::AccessToken => "access_token_hash",
+                TokenTypeHint::RefreshToken => "refresh_token_hash",
+            };
+
+            let Some(found) = match_hashes(
+                "SELECT id, client_id, family_id FROM oauth2_tokens WHERE ",
+                column,
+                &hashes,
+            )
+            .build()
+            .fetch_optional(&self.pool)
+            .await?
+            else {
+                continue;
+            };
+
+            let owner: &str = found.try_get("client_id")?;
+            if owner != client_id {
+                return Err(Error::UnauthorizedClient);
+            }
+
+            match kind {
+                TokenTypeHint::AccessToken => {
+                    sqlx::query(
+                        r#"
+                        UPDATE oauth2_tokens
+                        SET revoked = TRUE
+                        WHERE id = ?
+                        "#,
+                    )
+                    .bind(found.try_get::<&str, _>("id")?)
+                    .execute(&self.pool)
+                    .await?;
+                }
+                TokenTypeHint::RefreshToken => {
+                    let family_id = decode_uuid(found.try_get("family_id")?)?;
+                    self.revoke_family(&self.pool, family_id).await?;
+                }
+            }
+
+            return Ok(());
+        }
+
+        Ok(())
+    }
+
+    async fn list_tokens_for_user(&self, user_id: Uuid, page: PageRequest) -> Result<TokenPage, Error> {
+        let (after_issued_at, after_id) = match page.after {
+            Some(cursor) => (Some(cursor.issued_at), Some(cursor.id.to_string())),
+            None => (None, None),
+        };
+        let now = now();
+
+        let rows = sqlx::query(
+            r#"
+            SELECT
+                id,
+                '' AS access_token_hash,
+                CAST(NULL AS CHAR) AS refresh_token_hash,
+                client_id,
+                user_id,
+                scopes,
+                issued_at,
+                expires_at,
+                revoked,
+                revoked_at,
+                refresh_expires_at,
+                family_id,
+                rotated_at,
+                extra_fields,
+                key_id,
+                hash_algorithm,
+                last_used_at,
+                use_count,
+                idle_timeout_secs,
+                idle_expires_at,
+                sliding_window_secs,
+                max_expires_at,
+                token_type
+            FROM oauth2_tokens
+            WHERE user_id = ?
+              AND NOT revoked
+              AND (idle_expires_at IS NULL OR idle_expires_at > ?)
+              AND (
+                    expires_at IS NULL OR expires_at > ?
+                    OR (
+                        refresh_token_hash IS NOT NULL
+                        AND (refresh_expires_at IS NULL OR refresh_expires_at > ?)
+                    )
+              )
+              AND (? IS NULL OR (issued_at, id) < (?, ?))
+            ORDER BY issued_at DESC, id DESC
+            LIMIT ?
+            "#,
+        )
+        .bind(user_id.to_string())
+        .bind(now)
+        .bind(now)
+        .bind(now)
+        .bind(after_issued_at)
+        .bind(after_issued_at)
+        .bind(after_id)
+        .bind(i64::from(page.limit) + 1)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let rows = rows.iter().map(token_from_row).collect::<Result<_, _>>()?;
+
+        Ok(TokenPage::from_rows(rows, page.limit))
+    }
+
+    async fn revoke_by_id(&self, id: Uuid) -> Result<(), Error> {
+        let res = sqlx::query(
+            r#"
+            UPDATE oauth2_tokens
+            SET revoked = TRUE
+            WHERE id = ?
+            "#,
+        )
+        .bind(id.to_string())
+        .execute(&self.pool)
+        .await?;
+
+        if res.rows_affected() == 0 {
+            return Err(Error::NotFound);
+        }
+
+        Ok(())
+    }
+
+    async fn revoke_all_for_user(&self, user_id: Uuid, except: Option<Uuid>) -> Result<usize, Error> {
+        let except = except.map(|id| id.to_string());
+
+        let res = sqlx::query(
+            r#"
+            UPDATE oauth2_tokens
+            SET revoked = TRUE
+            WHERE user_id = ?
+              AND NOT revoked
+              AND (? IS NULL OR id <> ?)
+            "#,
+        )
+        .bind(user_id.to_string())
+        .bind(&except)
+        .bind(&except)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(res.rows_affected() as usize)
+    }
+
+    async fn revoke_all_for_client(&self, client_id: &str, except: Option<Uuid>) -> Result<usize, Error> {
+        let except = except.map(|id| id.to_string());
+
+        let res = sqlx::query(
+            r#"
+            UPDATE oauth2_tokens
+            SET revoked = TRUE
+            WHERE client_id = ?
+              AND NOT revoked
+              AND (? IS NULL OR id <> ?)
+            "#,
+        )
+        .bind(client_id)
+        .bind(&except)
+        .bind(&except)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(res.rows_affected() as usize)
+    }
+
+    async fn revoke_all_for_user_and_client(
+        &self,
+        user_id: Uuid,
+        client_id: &str,
+        except: Option<Uuid>,
+    ) -> Result<usize, Error> {
+        let except = except.map(|id| id.to_string());
+
+        let res = sqlx::query(
+            r#"
+            UPDATE oauth2_tokens
+            SET revoked = TRUE
+            WHERE user_id = ?
+              AND client_id = ?
+              AND NOT revoked
+              AND (? IS NULL OR id <> ?)
+            "#,
+        )
+        .bind(user_id.to_string())
+        .bind(client_id)
+        .bind(&except)
+        .bind(&except)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(res.rows_affected() as usize)
+    }
+
+    async fn cleanup(&self) -> Result<usize, Error> {
+        let now = now();
+
+        let res = sqlx::query(
+            r#"
+            DELETE FROM oauth2_tokens
+            WHERE (
+                    revoked
+                    -- rotated refresh tokens are kept for reuse detection until they expire, for
+                    -- good if they never do
+                    AND NOT (rotated_at IS NOT NULL AND COALESCE(refresh_expires_at > ?, TRUE))
+               )
+               OR (
+                    expires_at IS NOT NULL AND expires_at < ?
+                    AND (
+                        refresh_token_hash IS NULL
+                        OR (refresh_expires_at IS NOT NULL AND refresh_expires_at < ?)
+                    )
+               )
+               -- idled out for good; rotated rows stay for reuse detection as above
+               OR (idle_expires_at IS NOT NULL AND idle_expires_at < ? AND rotated_at IS NULL)
+            "#,
+        )
+        .bind(now)
+        .bind(now)
+        .bind(now)
+        .bind(now)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(res.rows_affected() as usize)
+    }
+}
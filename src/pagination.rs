@@ -0,0 +1,61 @@
+//! Keyset pagination for token listings.
+
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use crate::StoredToken;
+
+/// Position in a listing ordered by `issued_at DESC, id DESC`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PageCursor {
+    pub issued_at: DateTime<Utc>,
+    pub id: Uuid,
+}
+
+impl From<&StoredToken> for PageCursor {
+    fn from(token: &StoredToken) -> Self {
+        Self {
+            issued_at: token.issued_at,
+            id: token.id,
+        }
+    }
+}
+
+/// Which page of a listing to fetch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PageRequest {
+    /// Maximum number of rows to return.
+    pub limit: u32,
+    /// Return rows after this cursor; `None` starts from the newest row.
+    pub after: Option<PageCursor>,
+}
+
+impl PageRequest {
+    /// The first page, holding at most `limit` rows.
+    pub fn first(limit: u32) -> Self {
+        Self { limit, after: None }
+    }
+}
+
+/// One page of a listing.
+#[derive(Debug, Clone)]
+pub struct TokenPage {
+    pub tokens: Vec<StoredToken>,
+    /// Cursor for the following page; `None` on the last page.
+    pub next: Option<PageCursor>,
+}
+
+impl TokenPage {
+    /// Build a page from up to `limit + 1` fetched rows; the extra row only signals
+    /// that another page exists.
+    pub(crate) fn from_rows(mut tokens: Vec<StoredToken>, limit: u32) -> Self {
+        let next = if tokens.len() > limit as usize {
+            tokens.truncate(limit as usize);
+            tokens.last().map(PageCursor::from)
+        } else {
+            None
+        };
+
+        Self { tokens, next }
+    }
+}
@@ -0,0 +1,563 @@
+//! Read-through cache shared between instances through Redis.
+
+use async_trait::async_trait;
+use chrono::Utc;
+use oauth2::{
+    AccessToken, ExtraTokenFields, RefreshToken, Scope, StandardTokenResponse, TokenResponse,
+    TokenType,
+};
+use redis::aio::{ConnectionManager, ConnectionManagerConfig};
+use redis::{cmd, pipe, Client, RedisError};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{Mutex, OnceCell};
+use tokio::time::Instant;
+use uuid::Uuid;
+
+use crate::cache::cache_key;
+use crate::{Error, OAuth2TokenStore, PageRequest, StoredToken, TokenPage, TokenTypeHint};
+
+const DEFAULT_KEY_PREFIX: &str = "oauth2:";
+const DEFAULT_TTL: Duration = Duration::from_secs(60);
+const DEFAULT_CONNECTION_TIMEOUT: Duration = Duration::from_secs(1);
+const DEFAULT_RESPONSE_TIMEOUT: Duration = Duration::from_millis(500);
+const MIN_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+impl From<RedisError> for Error {
+    fn from(error: RedisError) -> Self {
+        Error::Cache(error.to_string())
+    }
+}
+
+/// Decorator caching [`OAuth2TokenStore::get_by_access_token`] results of another store
+/// in Redis, so every instance pointing at the same Redis shares one cache.
+///
+/// Tokens are cached as JSON under a hash of the access token, for the TTL or until
+/// the token's `expires_at` or idle deadline, whichever comes first. As with
+/// [`CachedTokenStore`](crate::CachedTokenStore), cache hits do not count as activity
+/// for idle timeouts or sliding expiration. Revoking through any instance
+/// deletes the cached entries and records the tokens on a revocation list that every
+/// lookup checks first, so a revoked token is rejected without reaching the inner store
+/// even if a concurrent lookup re-cached it.
+///
+/// Bulk revocations and revocations by id find their entries through per-user,
+/// per-client and per-family index sets written alongside each cached token.
+/// Revocations by refresh token find theirs through a mapping written when the token is
+/// stored or rotated through a `RedisCachedTokenStore`; tokens issued elsewhere are only
+/// dropped once their entry's TTL runs out.
+///
+/// If Redis is unreachable or slower than the response timeout, lookups fall through to
+/// the inner store. After a failed connect, Redis is skipped for an exponentially
+/// growing window of up to 30 seconds rather than every lookup waiting out the
+/// connection timeout again. A revocation that succeeded in the inner store but could not be
+/// applied to Redis fails with [`Error::Cache`].
+///
+/// Clones share the same connection, a [`ConnectionManager`] that multiplexes requests
+/// and reconnects on its own.
+pub struct RedisCachedTokenStore<S> {
+    inner: Arc<S>,
+    client: Client,
+    connection: Arc<OnceCell<ConnectionManager>>,
+    reconnect: Arc<Mutex<Reconnect>>,
+    prefix: String,
+    ttl: Duration,
+    connection_timeout: Duration,
+    response_timeout: Duration,
+}
+
+/// Backoff between failed attempts to connect to Redis.
+struct Reconnect {
+    backoff: Duration,
+    retry_at: Option<Instant>,
+}
+
+impl Default for Reconnect {
+    fn default() -> Self {
+        Self {
+            backoff: MIN_BACKOFF,
+            retry_at: None,
+        }
+    }
+}
+
+impl<S> Clone for RedisCachedTokenStore<S> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: Arc::clone(&self.inner),
+            client: self.client.clone(),
+            connection: Arc::clone(&self.connection),
+            reconnect: Arc::clone(&self.reconnect),
+            prefix: self.prefix.clone(),
+            ttl: self.ttl,
+            connection_timeout: self.connection_timeout,
+            response_timeout: self.response_timeout,
+        }
+    }
+}
+
+impl<S: OAuth2TokenStore> RedisCachedTokenStore<S> {
+    /// Wrap `inner` with a cache in the Redis server at `url`
+    /// (`redis://[[user]:password@]host[:port][/db]`, or `rediss://` for TLS), with a
+    /// 60 second TTL, keys prefixed with `oauth2:`, a 1 second connection timeout and a
+    /// 500 millisecond response timeout.
+    ///
+    /// Connects lazily, on first use.
+    pub fn new(inner: S, url: &str) -> Result<Self, Error> {
+        let client = Client::open(url).map_err(|e| Error::Cache(format!("invalid redis url: {e}")))?;
+
+        Ok(Self {
+            inner: Arc::new(inner),
+            client,
+            connection: Arc::new(OnceCell::new()),
+            reconnect: Arc::new(Mutex::new(Reconnect::default())),
+            prefix: DEFAULT_KEY_PREFIX.to_string(),
+            ttl: DEFAULT_TTL,
+            connection_timeout: DEFAULT_CONNECTION_TIMEOUT,
+            response_timeout: DEFAULT_RESPONSE_TIMEOUT,
+        })
+    }
+
+    /// Prefix every key with `prefix`, e.g. to share a Redis database between services.
+    pub fn with_key_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// How long a found token is served from the cache. Revoked tokens stay on the
+    /// revocation list for twice as long, outliving any entry a concurrent lookup may have
+    /// re-cached. Must be at least a millisecond.
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl.max(Duration::from_millis(1));
+        self
+    }
+
+    /// Give up on connecting to Redis after `timeout`.
+    pub fn with_connection_timeout(mut self, timeout: Duration) -> Self {
+        self.connection_timeout = timeout;
+        self
+    }
+
+    /// Give up on a Redis reply after `timeout`; lookups then read through to the inner
+    /// store.
+    pub fn with_response_timeout(mut self, timeout: Duration) -> Self {
+        self.response_timeout = timeout;
+        self
+    }
+
+    /// The wrapped store, e.g. for its [`AuthorizationCodeStore`](crate::AuthorizationCodeStore)
+    /// implementation.
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    fn key(&self, kind: &str, id: impl std::fmt::Display) -> String {
+        format!("{}{kind}:{id}", self.prefix)
+    }
+
+    fn ttl_millis(&self) -> u64 {
+        millis(self.ttl)
+    }
+
+    /// The shared connection, established on first use. Replies are matched to their
+    /// requests, so a lookup dropped mid-request leaves nothing behind for the next one.
+    ///
+    /// Fails without trying while backing off from a failed connect.
+    async fn connection(&self) -> Result<ConnectionManager, Error> {
+        if let Some(connection) = self.connection.get() {
+            return Ok(connection.clone());
+        }
+
+        // Held across the attempt, so concurrent callers wait for its outcome rather
+        // than each starting their own.
+        let mut reconnect = self.reconnect.lock().await;
+        if let Some(connection) = self.connection.get() {
+            return Ok(connection.clone());
+        }
+        if reconnect.retry_at.is_some_and(|retry_at| Instant::now() < retry_at) {
+            return Err(Error::Cache("redis unreachable; backing off".to_string()));
+        }
+
+        // Fail fast rather than retry: a lookup waiting on Redis is better off reading
+        // through.
+        let config = ConnectionManagerConfig::new()
+            .set_connection_timeout(self.connection_timeout)
+            .set_response_timeout(self.response_timeout)
+            .set_number_of_retries(0);
+        match ConnectionManager::new_with_config(self.client.clone(), config).await {
+            Ok(connection) => {
+                let _ = self.connection.set(connection.clone());
+                *reconnect = Reconnect::default();
+                Ok(connection)
+            }
+            Err(error) => {
+                tracing::warn!(%error, backoff = ?reconnect.backoff, "could not connect to redis");
+                reconnect.retry_at = Some(Instant::now() + reconnect.backoff);
+                reconnect.backoff = (reconnect.backoff * 2).min(MAX_BACKOFF);
+                Err(error.into())
+            }
+        }
+    }
+
+    /// Cached lookup result for `key`: `Some(None)` if the token is on the revocation
+    /// list, `None` if the inner store has to be asked.
+    async fn cached(&self, key: &str) -> Result<Option<Option<StoredToken>>, Error> {
+        let (revoked, json): (bool, Option<Vec<u8>>) = pipe()
+            .cmd("EXISTS")
+            .arg(self.key("revoked", key))
+            .cmd("GET")
+            .arg(self.key("token", key))
+            .query_async(&mut self.connection().await?)
+            .await?;
+
+        if revoked {
+            return Ok(Some(None));
+        }
+
+        let Some(json) = json else {
+            return Ok(None);
+        };
+        let token: StoredToken = serde_json::from_slice(&json)?;
+
+        if token.usable_until().is_some_and(|e| e <= Utc::now()) {
+            return Ok(None);
+        }
+
+        Ok(Some(Some(token)))
+    }
+
+    /// Cache `token` under `key` and add it to the index sets.
+    async fn store(&self, key: &str, token: &StoredToken) -> Result<(), Error> {
+        let mut ttl = self.ttl;
+        if let Some(usable_until) = token.usable_until() {
+            match (usable_until - Utc::now()).to_std() {
+                Ok(remaining) => ttl = ttl.min(remaining),
+                Err(_) => return Ok(()),
+            }
+        }
+        let px = millis(ttl);
+        if px == 0 {
+            return Ok(());
+        }
+
+        let member = format!("{}:{key}", token.id);
+        let mut pipeline = pipe();
+        pipeline
+            .cmd("SET")
+            .arg(self.key("token", key))
+            .arg(serde_json::to_vec(token)?)
+            .arg("PX")
+            .arg(px)
+            .cmd("SET")
+            .arg(self.key("id", token.id))
+            .arg(key)
+            .arg("PX")
+            .arg(px);
+
+        let mut sets = vec![
+            self.key("client", &token.client_id),
+            self.key("family", token.family_id),
+        ];
+        if let Some(user_id) = token.user_id {
+            sets.push(self.key("user", user_id));
+        }
+        for set in sets {
+            // Index sets outlive every entry they point to.
+            pipeline
+                .cmd("SADD")
+                .arg(&set)
+                .arg(&member)
+                .cmd("PEXPIRE")
+                .arg(&set)
+                .arg(self.ttl_millis());
+        }
+
+        pipeline
+            .query_async::<()>(&mut self.connection().await?)
+            .await?;
+        Ok(())
+    }
+
+    /// Remember which access token `refresh` belongs to, for revocations by refresh token.
+    async fn map_refresh_token<EF, TT>(&self, token: &StandardTokenResponse<EF, TT>) -> Result<(), Error>
+    where
+        EF: ExtraTokenFields,
+        TT: TokenType,
+    {
+        let Some(refresh) = token.refresh_token() else {
+            return Ok(());
+        };
+
+        // Entries are never cached past the access token's expiry.
+        let mut command = cmd("SET");
+        command
+            .arg(self.key("refresh", cache_key(refresh.secret())))
+            .arg(cache_key(token.access_token().secret()));
+        if let Some(expires_in) = token.expires_in() {
+            command.arg("PX").arg(millis(expires_in).max(1));
+        }
+
+        command
+            .query_async::<()>(&mut self.connection().await?)
+            .await?;
+        Ok(())
+    }
+
+    /// Drop the cached entries for `keys` and put them on the revocation list.
+    async fn invalidate(&self, keys: &[String]) -> Result<(), Error> {
+        if keys.is_empty() {
+            return Ok(());
+        }
+
+        let revoked_for = millis(self.ttl * 2);
+        let mut pipeline = pipe();
+        for key in keys {
+            pipeline
+                .cmd("DEL")
+                .arg(self.key("token", key))
+                .cmd("SET")
+                .arg(self.key("revoked", key))
+                .arg(1)
+                .arg("PX")
+                .arg(revoked_for);
+        }
+
+        pipeline
+            .query_async::<()>(&mut self.connection().await?)
+            .await?;
+        Ok(())
+    }
+
+    /// Invalidate the entry of the access token issued alongside `refresh`, if known.
+    async fn invalidate_refresh_token(&self, refresh: &str) -> Result<(), Error> {
+        let mapped = self.get(self.key("refresh", cache_key(refresh))).await?;
+
+        match mapped {
+            Some(key) => self.invalidate(&[key]).await,
+            None => Ok(()),
+        }
+    }
+
+    /// Invalidate every entry in the intersection of the index `sets`, except `except`.
+    async fn invalidate_indexed(&self, sets: &[String], except: Option<Uuid>) -> Result<(), Error> {
+        let members: Vec<String> = cmd("SINTER")
+            .arg(sets)
+            .query_async(&mut self.connection().await?)
+            .await?;
+
+        let mut keys = Vec::new();
+        let mut removed = Vec::new();
+        for member in members {
+            let Some((id, key)) = member.split_once(':') else {
+                continue;
+            };
+            if except.is_some_and(|e| e.to_string() == id) {
+                continue;
+            }
+            keys.push(key.to_string());
+            removed.push(member);
+        }
+
+        self.invalidate(&keys).await?;
+
+        if !removed.is_empty() {
+            let mut pipeline = pipe();
+            for set in sets {
+                pipeline.cmd("SREM").arg(set).arg(&removed);
+            }
+            pipeline
+                .query_async::<()>(&mut self.connection().await?)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// The string at `key`, if any.
+    async fn get(&self, key: String) -> Result<Option<String>, Error> {
+        let value = cmd("GET")
+            .arg(key)
+            .query_async(&mut self.connection().await?)
+            .await?;
+        Ok(value)
+    }
+
+    /// Forget a family after reuse detection revoked it. The reuse error is what the
+    /// caller needs to see, so a Redis failure is only logged.
+    async fn invalidate_on_reuse<T>(&self, result: &Result<T, Error>) {
+        if let Err(Error::RefreshTokenReuse(family_id)) = result {
+            let family = self.key("family", family_id);
+            if let Err(error) = self.invalidate_indexed(&[family], None).await {
+                tracing::warn!(%error, %family_id, "could not drop revoked token family from redis cache");
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl<S: OAuth2TokenStore> OAuth2TokenStore for RedisCachedTokenStore<S> {
+    async fn store_token<EF, TT>(
+        &self,
+        token: &StandardTokenResponse<EF, TT>,
+        client_id: &str,
+        user_id: Option<Uuid>,
+        scopes: &[Scope],
+        refresh_expires_in: Option<Duration>,
+    ) -> Result<(), Error>
+    where
+        EF: ExtraTokenFields + Sync,
+        TT: TokenType + Sync,
+    {
+        self.inner
+            .store_token(token, client_id, user_id, scopes, refresh_expires_in)
+            .await?;
+
+        if let Err(error) = self.map_refresh_token(token).await {
+            tracing::warn!(%error, "could not index refresh token in redis cache");
+        }
+
+        Ok(())
+    }
+
+    async fn get_by_access_token(&self, token: &AccessToken) -> Result<Option<StoredToken>, Error> {
+        let key = cache_key(token.secret());
+
+        match self.cached(&key).await {
+            Ok(Some(cached)) => return Ok(cached),
+            Ok(None) => {}
+            Err(error) => tracing::warn!(%error, "redis cache lookup failed; reading through"),
+        }
+
+        let found = self.inner.get_by_access_token(token).await?;
+
+        if let Some(stored) = &found
+            && let Err(error) = self.store(&key, stored).await
+        {
+            tracing::warn!(%error, "could not write token to redis cache");
+        }
+
+        Ok(found)
+    }
+
+    async fn get_by_refresh_token(&self, token: &RefreshToken) -> Result<Option<StoredToken>, Error> {
+        let result = self.inner.get_by_refresh_token(token).await;
+        self.invalidate_on_reuse(&result).await;
+        result
+    }
+
+    async fn find_by_access_token(&self, token: &AccessToken) -> Result<Option<StoredToken>, Error> {
+        self.inner.find_by_access_token(token).await
+    }
+
+    async fn find_by_refresh_token(&self, token: &RefreshToken) -> Result<Option<StoredToken>, Error> {
+        self.inner.find_by_refresh_token(token).await
+    }
+
+    async fn revoke_by_access_token(&self, token: &AccessToken) -> Result<(), Error> {
+        self.inner.revoke_by_access_token(token).await?;
+        self.invalidate(&[cache_key(token.secret())]).await
+    }
+
+    async fn revoke_by_refresh_token(&self, token: &RefreshToken) -> Result<(), Error> {
+        self.inner.revoke_by_refresh_token(token).await?;
+        self.invalidate_refresh_token(token.secret()).await
+    }
+
+    async fn rotate_refresh_token<EF, TT>(
+        &self,
+        old: &RefreshToken,
+        new: &StandardTokenResponse<EF, TT>,
+        refresh_expires_in: Option<Duration>,
+    ) -> Result<StoredToken, Error>
+    where
+        EF: ExtraTokenFields + Sync,
+        TT: TokenType + Sync,
+    {
+        let result = self
+            .inner
+            .rotate_refresh_token(old, new, refresh_expires_in)
+            .await;
+        self.invalidate_on_reuse(&result).await;
+
+        let stored = result?;
+
+        // The rotation is committed, so failing here would only lose the new token pair.
+        let cache_update = async {
+            self.invalidate_refresh_token(old.secret()).await?;
+            self.map_refresh_token(new).await?;
+            self.store(&cache_key(new.access_token().secret()), &stored).await
+        };
+        if let Err(error) = cache_update.await {
+            tracing::warn!(%error, "could not update redis cache after rotation");
+        }
+
+        Ok(stored)
+    }
+
+    async fn revoke(
+        &self,
+        token: &str,
+        token_type_hint: Option<TokenTypeHint>,
+        client_id: &str,
+    ) -> Result<(), Error> {
+        self.inner.revoke(token, token_type_hint, client_id).await?;
+
+        // The token may have been either kind; listing a refresh token is harmless.
+        self.invalidate(&[cache_key(token)]).await?;
+        self.invalidate_refresh_token(token).await
+    }
+
+    async fn list_tokens_for_user(&self, user_id: Uuid, page: PageRequest) -> Result<TokenPage, Error> {
+        self.inner.list_tokens_for_user(user_id, page).await
+    }
+
+    async fn revoke_by_id(&self, id: Uuid) -> Result<(), Error> {
+        self.inner.revoke_by_id(id).await?;
+
+        match self.get(self.key("id", id)).await? {
+            Some(key) => self.invalidate(&[key]).await,
+            None => Ok(()),
+        }
+    }
+
+    async fn revoke_all_for_user(&self, user_id: Uuid, except: Option<Uuid>) -> Result<usize, Error> {
+        let revoked = self.inner.revoke_all_for_user(user_id, except).await?;
+        self.invalidate_indexed(&[self.key("user", user_id)], except)
+            .await?;
+        Ok(revoked)
+    }
+
+    async fn revoke_all_for_client(&self, client_id: &str, except: Option<Uuid>) -> Result<usize, Error> {
+        let revoked = self.inner.revoke_all_for_client(client_id, except).await?;
+        self.invalidate_indexed(&[self.key("client", client_id)], except)
+            .await?;
+        Ok(revoked)
+    }
+
+    async fn revoke_all_for_user_and_client(
+        &self,
+        user_id: Uuid,
+        client_id: &str,
+        except: Option<Uuid>,
+    ) -> Result<usize, Error> {
+        let revoked = self
+            .inner
+            .revoke_all_for_user_and_client(user_id, client_id, except)
+            .await?;
+        self.invalidate_indexed(
+            &[self.key("user", user_id), self.key("client", client_id)],
+            except,
+        )
+        .await?;
+        Ok(revoked)
+    }
+
+    async fn cleanup(&self) -> Result<usize, Error> {
+        self.inner.cleanup().await
+    }
+}
+
+/// Whole milliseconds of `d`, as Redis expects for `PX` and `PEXPIRE`.
+fn millis(d: Duration) -> u64 {
+    u64::try_from(d.as_millis()).unwrap_or(u64::MAX)
+}
@@ -0,0 +1,158 @@
+//! Scope checks on stored tokens.
+
+use std::collections::HashMap;
+
+use crate::StoredToken;
+
+/// How a granted scope satisfies a required one.
+///
+/// The default ([`ScopeRules::exact`]) only accepts identical scopes. On top of that,
+/// rules can make a scope imply its children (`admin` grants `admin:read`), treat `*`
+/// segments as wildcards (`orders:*` grants `orders:read`), or list explicit
+/// implications (`admin` grants `orders:write`):
+///
+/// ```
+/// use oauth2_pg_store::ScopeRules;
+///
+/// let rules = ScopeRules::hierarchical(':')
+///     .with_wildcards()
+///     .with_implied("admin", ["orders:write"]);
+///
+/// assert!(rules.grants("admin", "admin:read"));
+/// assert!(rules.grants("orders:*", "orders:read"));
+/// assert!(rules.grants("admin", "orders:write"));
+/// assert!(!rules.grants("orders:read", "orders:write"));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ScopeRules {
+    separator: Option<char>,
+    wildcards: bool,
+    implied: HashMap<String, Vec<String>>,
+}
+
+impl ScopeRules {
+    /// Only identical scopes match.
+    pub fn exact() -> Self {
+        Self::default()
+    }
+
+    /// A scope also grants every scope below it, segments being split on `separator`:
+    /// with `':'`, `admin` grants `admin:read` and `admin:read:logs`, but not `administer`.
+    pub fn hierarchical(separator: char) -> Self {
+        Self {
+            separator: Some(separator),
+            ..Self::default()
+        }
+    }
+
+    /// A `*` segment in a granted scope matches any single segment, so `orders:*` grants
+    /// `orders:read`. Segments are split on the hierarchy separator, or `:` for exact rules.
+    pub fn with_wildcards(mut self) -> Self {
+        self.wildcards = true;
+        self
+    }
+
+    /// Holding `scope` also grants each of `implies` (and whatever those grant in turn).
+    pub fn with_implied<I, T>(mut self, scope: impl Into<String>, implies: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<String>,
+    {
+        self.implied
+            .entry(scope.into())
+            .or_default()
+            .extend(implies.into_iter().map(Into::into));
+        self
+    }
+
+    /// Whether holding `granted` satisfies `required`.
+    pub fn grants(&self, granted: &str, required: &str) -> bool {
+        let mut seen = Vec::new();
+        self.grants_inner(granted, required, &mut seen)
+    }
+
+    /// Whether any of `granted` satisfies `required`.
+    pub fn satisfies<S: AsRef<str>>(&self, granted: &[S], required: &str) -> bool {
+        granted.iter().any(|g| self.grants(g.as_ref(), required))
+    }
+
+    fn grants_inner<'a>(&'a self, granted: &'a str, required: &str, seen: &mut Vec<&'a str>) -> bool {
+        if self.matches(granted, required) {
+            return true;
+        }
+
+        // Implications may form cycles; each scope is expanded once.
+        if seen.contains(&granted) {
+            return false;
+        }
+        seen.push(granted);
+
+        self.implied.get(granted).is_some_and(|implied| {
+            implied
+                .iter()
+                .any(|scope| self.grants_inner(scope, required, seen))
+        })
+    }
+
+    fn matches(&self, granted: &str, required: &str) -> bool {
+        if granted == required {
+            return true;
+        }
+
+        let separator = self.separator.unwrap_or(':');
+        let mut granted_segments = granted.split(separator);
+        let mut required_segments = required.split(separator);
+
+        loop {
+            match (granted_segments.next(), required_segments.next()) {
+                (None, None) => return true,
+                // `required` lies below `granted`.
+                (None, Some(_)) => return self.separator.is_some(),
+                (Some(_), None) => return false,
+                (Some(g), Some(r)) => {
+                    if g != r && !(self.wildcards && g == "*") {
+                        return false;
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl StoredToken {
+    /// Whether the token was granted exactly `scope`.
+    ///
+    /// The `_with` variants match under [`ScopeRules`] instead, e.g. hierarchically.
+    pub fn has_scope(&self, scope: &str) -> bool {
+        self.has_scope_with(&ScopeRules::exact(), scope)
+    }
+
+    /// Whether the token was granted every one of `scopes`.
+    pub fn has_all<S: AsRef<str>>(&self, scopes: &[S]) -> bool {
+        self.has_all_with(&ScopeRules::exact(), scopes)
+    }
+
+    /// Whether the token was granted at least one of `scopes`.
+    pub fn has_any<S: AsRef<str>>(&self, scopes: &[S]) -> bool {
+        self.has_any_with(&ScopeRules::exact(), scopes)
+    }
+
+    /// [`StoredToken::has_scope`] under `rules`.
+    pub fn has_scope_with(&self, rules: &ScopeRules, scope: &str) -> bool {
+        rules.satisfies(&self.scopes, scope)
+    }
+
+    /// [`StoredToken::has_all`] under `rules`.
+    pub fn has_all_with<S: AsRef<str>>(&self, rules: &ScopeRules, scopes: &[S]) -> bool {
+        scopes
+            .iter()
+            .all(|required| self.has_scope_with(rules, required.as_ref()))
+    }
+
+    /// [`StoredToken::has_any`] under `rules`.
+    pub fn has_any_with<S: AsRef<str>>(&self, rules: &ScopeRules, scopes: &[S]) -> bool {
+        scopes
+            .iter()
+            .any(|required| self.has_scope_with(rules, required.as_ref()))
+    }
+}
@@ -0,0 +1,802 @@
+//! SQLite token store (enabled with the `sqlite` feature).
+//!
+//! Uses the schema in `migrations/sqlite`. SQLite has no array, UUID or timestamptz
+//! types, so scopes and extra fields are stored as JSON text, UUIDs as hyphenated text
+//! and timestamps as fixed-width RFC 3339 UTC text, which orders correctly as a string.
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use oauth2::{
+    AccessToken, ExtraTokenFields, RefreshToken, Scope, StandardTokenResponse, TokenResponse,
+    TokenType,
+};
+use sqlx::sqlite::SqliteRow;
+use sqlx::{Row, SqliteExecutor, SqlitePool};
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::expiry::{self, ExpiryPolicy};
+use crate::hashing::Hashers;
+use crate::usage::{self, PendingUse, UsageRecorder};
+use crate::{
+    token_type_name, Error, NewTokenRow, OAuth2TokenStore, PageRequest, StoredToken,
+    TokenHasher, TokenPage, TokenTypeHint,
+};
+
+/// Timestamp text format: microsecond precision (like Postgres) and a fixed width.
+const TIMESTAMP_FORMAT: &str = "%Y-%m-%dT%H:%M:%S%.6fZ";
+
+fn encode_time(t: DateTime<Utc>) -> String {
+    t.format(TIMESTAMP_FORMAT).to_string()
+}
+
+fn decode_time(s: &str) -> Result<DateTime<Utc>, Error> {
+    DateTime::parse_from_rfc3339(s)
+        .map(|t| t.with_timezone(&Utc))
+        .map_err(|e| Error::Other(Box::new(e)))
+}
+
+fn decode_uuid(s: &str) -> Result<Uuid, Error> {
+    Uuid::parse_str(s).map_err(|e| Error::Other(Box::new(e)))
+}
+
+/// Decode an `oauth2_tokens` row.
+fn token_from_row(row: &SqliteRow) -> Result<StoredToken, Error> {
+    let optional_time = |column: &str| -> Result<Option<DateTime<Utc>>, Error> {
+        row.try_get::<Option<String>, _>(column)?
+            .as_deref()
+            .map(decode_time)
+            .transpose()
+    };
+
+    Ok(StoredToken {
+        id: decode_uuid(row.try_get("id")?)?,
+        access_token_hash: row.try_get("access_token_hash")?,
+        refresh_token_hash: row.try_get("refresh_token_hash")?,
+        client_id: row.try_get("client_id")?,
+        user_id: row
+            .try_get::<Option<&str>, _>("user_id")?
+            .map(decode_uuid)
+            .transpose()?,
+        scopes: serde_json::from_str(row.try_get("scopes")?)?,
+        issued_at: decode_time(row.try_get("issued_at")?)?,
+        expires_at: optional_time("expires_at")?,
+        revoked: row.try_get("revoked")?,
+        revoked_at: optional_time("revoked_at")?,
+        refresh_expires_at: optional_time("refresh_expires_at")?,
+        family_id: decode_uuid(row.try_get("family_id")?)?,
+        rotated_at: optional_time("rotated_at")?,
+        extra_fields: serde_json::from_str(row.try_get("extra_fields")?)?,
+        key_id: row.try_get("key_id")?,
+        hash_algorithm: row.try_get("hash_algorithm")?,
+        last_used_at: optional_time("last_used_at")?,
+        use_count: row.try_get("use_count")?,
+        idle_timeout_secs: row.try_get("idle_timeout_secs")?,
+        idle_expires_at: optional_time("idle_expires_at")?,
+        sliding_window_secs: row.try_get("sliding_window_secs")?,
+        max_expires_at: optional_time("max_expires_at")?,
+        token_type: row.try_get("token_type")?,
+    })
+}
+
+/// SQLite implementation of [`OAuth2TokenStore`], for small deployments and local
+/// development.
+#[derive(Clone)]
+pub struct SqliteTokenStore {
+    pool: SqlitePool,
+    hashers: Arc<Hashers>,
+    usage: Option<Arc<UsageRecorder>>,
+    expiry: ExpiryPolicy,
+}
+
+impl SqliteTokenStore {
+    /// Create a new store on a pool whose database has the `migrations/sqlite` schema.
+    pub fn new(pool: SqlitePool) -> Self {
+        Self {
+            pool,
+            hashers: Arc::default(),
+            usage: None,
+            expiry: ExpiryPolicy::default(),
+        }
+    }
+
+    /// Hash new tokens with `hasher` instead of plain BLAKE3.
+    ///
+    /// See [`PgTokenStore::with_hasher`](crate::PgTokenStore::with_hasher).
+    pub fn with_hasher(mut self, hasher: impl TokenHasher) -> Self {
+        Arc::make_mut(&mut self.hashers).current = Arc::new(hasher);
+        self
+    }
+
+    /// Keep accepting tokens hashed by an algorithm or key that is no longer current.
+    pub fn with_legacy_hasher(mut self, hasher: impl TokenHasher) -> Self {
+        Arc::make_mut(&mut self.hashers).legacy.push(Arc::new(hasher));
+        self
+    }
+
+    /// Record `last_used_at` and `use_count` on every successful access-token lookup,
+    /// writing them every `flush_interval`.
+    ///
+    /// See [`PgTokenStore::with_usage_tracking`](crate::PgTokenStore::with_usage_tracking).
+    pub fn with_usage_tracking(mut self, flush_interval: Duration) -> Self {
+        self.usage = Some(Arc::new(UsageRecorder::new(flush_interval)));
+        self
+    }
+
+    /// End sessions that go unused for `timeout` (whole seconds).
+    ///
+    /// See [`PgTokenStore::with_idle_timeout`](crate::PgTokenStore::with_idle_timeout).
+    pub fn with_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.expiry.idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Slide `expires_at` on use, but never past `max_lifetime` after issuance.
+    ///
+    /// See [`PgTokenStore::with_sliding_expiration`](crate::PgTokenStore::with_sliding_expiration).
+    pub fn with_sliding_expiration(mut self, max_lifetime: Duration) -> Self {
+        self.expiry.max_lifetime = Some(max_lifetime);
+        self
+    }
+
+    /// Write buffered usage now. Returns the number of tokens updated.
+    pub async fn flush_usage(&self) -> Result<usize, Error> {
+        let Some(usage) = &self.usage else {
+            return Ok(0);
+        };
+
+        let batch = usage.take();
+        if batch.is_empty() {
+            return Ok(0);
+        }
+
+        match self.write_usage(&batch).await {
+            Ok(updated) => Ok(updated),
+            Err(e) => {
+                usage.restore(batch);
+                Err(e)
+            }
+        }
+    }
+
+    async fn write_usage(&self, batch: &[(Uuid, PendingUse)]) -> Result<usize, Error> {
+        let mut tx = self.pool.begin().await?;
+        let mut updated = 0;
+
+        for (id, use_) in batch {
+            // Fixed-width timestamps compare correctly as text.
+            let res = sqlx::query(
+                r#"
+                UPDATE oauth2_tokens
+                SET use_count = use_count + ?2,
+                    last_used_at = max(coalesce(last_used_at, ''), ?3)
+                WHERE id = ?1
+                "#,
+            )
+            .bind(id.to_string())
+            .bind(use_.uses)
+            .bind(encode_time(use_.last_used_at))
+            .execute(&mut *tx)
+            .await?;
+
+            updated += res.rows_affected() as usize;
+        }
+
+        tx.commit().await?;
+        Ok(updated)
+    }
+
+    /// Write deadlines moved by [`expiry::touch`], never moving them backwards.
+    async fn write_deadlines(&self, token: &StoredToken) -> Result<(), Error> {
+        sqlx::query(
+            r#"
+            UPDATE oauth2_tokens
+            SET idle_expires_at = max(idle_expires_at, ?2),
+                expires_at = max(expires_at, ?3)
+            WHERE id = ?1
+            "#,
+        )
+        .bind(token.id.to_string())
+        .bind(token.idle_expires_at.map(encode_time))
+        .bind(token.expires_at.map(encode_time))
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Every hash a stored token may have been written under, as a JSON array of
+    /// `[hash, hash_algorithm, key_id]` triples for `json_each(..)`.
+    fn lookup_hashes(&self, token: &str) -> Result<String, Error> {
+        let candidates = self.hashers.candidates(token)?;
+        Ok(serde_json::to_string(&candidates.iter().collect::<Vec<_>>())?)
+    }
+
+    /// Insert a token row using the given executor (pool or open transaction).
+    async fn insert_token<'e, E, EF, TT>(
+        &self,
+        executor: E,
+        token: &StandardTokenResponse<EF, TT>,
+        row: NewTokenRow<'_>,
+    ) -> Result<StoredToken, Error>
+    where
+        E: SqliteExecutor<'e>,
+        EF: ExtraTokenFields,
+        TT: TokenType,
+    {
+        let now = Utc::now();
+
+        let access_hash = self.hashers.current.hash(token.access_token().secret())?;
+
+        let refresh_hash = token
+            .refresh_token()
+            .map(|r: &RefreshToken| self.hashers.current.hash(r.secret()))
+            .transpose()?;
+
+        let expiry = self.expiry.stamp(now, token.expires_in());
+
+        let refresh_expires_at = refresh_hash
+            .as_ref()
+            .and(row.refresh_expires_in)
+            .map(|d| now + d);
+
+        let stored = sqlx::query(
+            r#"
+            INSERT INTO oauth2_tokens (
+                id,
+                access_token_hash,
+                refresh_token_hash,
+                client_id,
+                user_id,
+                scopes,
+                issued_at,
+                expires_at,
+                revoked,
+                refresh_expires_at,
+                family_id,
+                extra_fields,
+                key_id,
+                hash_algorithm,
+                idle_timeout_secs,
+                idle_expires_at,
+                sliding_window_secs,
+                max_expires_at,
+                token_type
+            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, 0, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18)
+            RETURNING *
+            "#,
+        )
+        .bind(Uuid::new_v4().to_string())
+        .bind(access_hash)
+        .bind(refresh_hash)
+        .bind(row.client_id)
+        .bind(row.user_id.map(|id| id.to_string()))
+        .bind(serde_json::to_string(row.scopes)?)
+        .bind(encode_time(now))
+        .bind(expiry.expires_at.map(encode_time))
+        .bind(refresh_expires_at.map(encode_time))
+        .bind(row.family_id.unwrap_or_else(Uuid::new_v4).to_string())
+        .bind(serde_json::to_string(&token.extra_fields())?)
+        .bind(self.hashers.current.key_id())
+        .bind(self.hashers.current.algorithm())
+        .bind(expiry.idle_timeout_secs)
+        .bind(expiry.idle_expires_at.map(encode_time))
+        .bind(expiry.sliding_window_secs)
+        .bind(expiry.max_expires_at.map(encode_time))
+        .bind(token_type_name(token.token_type())?)
+        .fetch_one(executor)
+        .await?;
+
+        token_from_row(&stored)
+    }
+
+    /// Revoke every token in a rotation family.
+    async fn revoke_family<'e, E>(&self, executor: E, family_id: Uuid) -> Result<u64, Error>
+    where
+        E: SqliteExecutor<'e>,
+    {
+        let res = sqlx::query(
+            r#"
+            UPDATE oauth2_tokens
+            SET revoked = 1
+            WHERE family_id = ?1
+            "#,
+        )
+        .bind(family_id.to_string())
+        .execute(executor)
+        .await?;
+
+        Ok(res.rows_affected())
+    }
+}
+
+#[async_trait]
+impl OAuth2TokenStore for SqliteTokenStore {
+    async fn store_token<EF, TT>(
+        &self,
+        token: &StandardTokenResponse<EF, TT>,
+        client_id: &str,
+        user_id: Option<Uuid>,
+        scopes: &[Scope],
+        refresh_expires_in: Option<Duration>,
+    ) -> Result<(), Error>
+    where
+        EF: ExtraTokenFields + Sync,
+        TT: TokenType + Sync,
+    {
+        let scopes_str: Vec<String> = scopes.iter().map(|s| s.to_string()).collect();
+
+        self.insert_token(
+            &self.pool,
+            token,
+            NewTokenRow {
+                client_id,
+                user_id,
+                scopes: &scopes_str,
+                refresh_expires_in,
+                family_id: None,
+            },
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_by_access_token(&self, token: &AccessToken) -> Result<Option<StoredToken>, Error> {
+        let hashes = self.lookup_hashes(token.secret())?;
+
+        let row = sqlx::query(
+            r#"
+            SELECT * FROM oauth2_tokens
+            WHERE access_token_hash IN (SELECT value ->> 0 FROM json_each(?1))
+              AND (access_token_hash, hash_algorithm, COALESCE(key_id, ''))
+                  IN (SELECT value ->> 0, value ->> 1, value ->> 2 FROM json_each(?1))
+              AND NOT revoked
+              AND (expires_at IS NULL OR expires_at > ?2)
+              AND (idle_expires_at IS NULL OR idle_expires_at > ?2)
+            "#,
+        )
+        .bind(hashes)
+        .bind(encode_time(Utc::now()))
+        .fetch_optional(&self.pool)
+        .await?;
+
+        let mut row = row.as_ref().map(token_from_row).transpose()?;
+
+        if let Some(row) = &mut row
+            && expiry::touch(row, Utc::now(), true)
+        {
+            self.write_deadlines(row).await?;
+        }
+
+        if let (Some(usage), Some(row)) = (&self.usage, &row)
+            && usage.record(row.id)
+        {
+            usage::spawn_flusher(self.clone(), usage, |store| async move { store.flush_usage().await });
+        }
+
+        Ok(row)
+    }
+
+    async fn get_by_refresh_token(&self, token: &RefreshToken) -> Result<Option<StoredToken>, Error> {
+        let Some(mut row) = self.find_by_refresh_token(token).await? else {
+            return Ok(None);
+        };
+
+        if row.rotated_at.is_some() {
+            self.revoke_family(&self.pool, row.family_id).await?;
+            return Err(Error::RefreshTokenReuse(row.family_id));
+        }
+
+        let now = Utc::now();
+        let refresh_expired = row
+            .refresh_expires_at
+            .is_some_and(|t| t <= now);
+
+        if row.revoked || refresh_expired || row.is_idle_expired(now) {
+            return Ok(None);
+        }
+
+        if expiry::touch(&mut row, now, false) {
+            self.write_deadlines(&row).await?;
+        }
+
+        Ok(Some(row))
+    }
+
+    async fn find_by_access_token(&self, token: &AccessToken) -> Result<Option<StoredToken>, Error> {
+        let hashes = self.lookup_hashes(token.secret())?;
+
+        let row = sqlx::query(
+            r#"
+            SELECT * FROM oauth2_tokens
+            WHERE access_token_hash IN (SELECT value ->> 0 FROM json_each(?1))
+              AND (access_token_hash, hash_algorithm, COALESCE(key_id, ''))
+                  IN (SELECT value ->> 0, value ->> 1, value ->> 2 FROM json_each(?1))
+            "#,
+        )
+        .bind(hashes)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.as_ref().map(token_from_row).transpose()
+    }
+
+    async fn find_by_refresh_token(&self, token: &RefreshToken) -> Result<Option<StoredToken>, Error> {
+        let hashes = self.lookup_hashes(token.secret())?;
+
+        let row = sqlx::query(
+            r#"
+            SELECT * FROM oauth2_tokens
+            WHERE refresh_token_hash IN (SELECT value ->> 0 FROM json_each(?1))
+              AND (refresh_token_hash, hash_algorithm, COALESCE(key_id, ''))
+                  IN (SELECT value ->> 0, value ->> 1, value ->> 2 FROM json_each(?1))
+            "#,
+        )
+        .bind(hashes)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.as_ref().map(token_from_row).transpose()
+    }
+
+    async fn revoke_by_access_token(&self, token: &AccessToken) -> Result<(), Error> {
+        let hashes = self.lookup_hashes(token.secret())?;
+
+        let res = sqlx::query(
+            r#"
+            UPDATE oauth2_tokens
+            SET revoked = 1
+            WHERE access_token_hash IN (SELECT value ->> 0 FROM json_each(?1))
+              AND (access_token_hash, hash_algorithm, COALESCE(key_id, ''))
+                  IN (SELECT value ->> 0, value ->> 1, value ->> 2 FROM json_each(?1))
+            "#,
+        )
+        .bind(hashes)
+        .execute(&self.pool)
+        .await?;
+
+        if res.rows_affected() == 0 {
+            return Err(Error::NotFound);
+        }
+
+        Ok(())
+    }
+
+    async fn revoke_by_refresh_token(&self, token: &RefreshToken) -> Result<(), Error> {
+        let hashes = self.lookup_hashes(token.secret())?;
+
+        let res = sqlx::query(
+            r#"
+            UPDATE oauth2_tokens
+            SET revoked = 1
+            WHERE refresh_token_hash IN (SELECT value ->> 0 FROM json_each(?1))
+              AND (refresh_token_hash, hash_algorithm, COALESCE(key_id, ''))
+                  IN (SELECT value ->> 0, value ->> 1, value ->> 2 FROM json_each(?1))
+            "#,
+        )
+        .bind(hashes)
+        .execute(&self.pool)
+        .await?;
+
+        if res.rows_affected() == 0 {
+            return Err(Error::NotFound);
+        }
+
+        Ok(())
+    }
+
+    async fn rotate_refresh_token<EF, TT>(
+        &self,
+        old: &RefreshToken,
+        new: &StandardTokenResponse<EF, TT>,
+        refresh_expires_in: Option<Duration>,
+    ) -> Result<StoredToken, Error>
+    where
+        EF: ExtraTokenFields + Sync,
+        TT: TokenType + Sync,
+    {
+        let hashes = self.lookup_hashes(old.secret())?;
+
+        // IMMEDIATE takes the write lock up front, serialising concurrent rotations the
+        // way `SELECT ... FOR UPDATE` does on Postgres.
+        let mut tx = self.pool.begin_with("BEGIN IMMEDIATE").await?;
+
+        let current = sqlx::query(
+            r#"
+            SELECT * FROM oauth2_tokens
+            WHERE refresh_token_hash IN (SELECT value ->> 0 FROM json_each(?1))
+              AND (refresh_token_hash, hash_algorithm, COALESCE(key_id, ''))
+                  IN (SELECT value ->> 0, value ->> 1, value ->> 2 FROM json_each(?1))
+            "#,
+        )
+        .bind(hashes)
+        .fetch_optional(&mut *tx)
+        .await?
+        .as_ref()
+        .map(token_from_row)
+        .transpose()?
+        .ok_or(Error::NotFound)?;
+
+        if current.rotated_at.is_some() {
+            self.revoke_family(&mut *tx, current.family_id).await?;
+            tx.commit().await?;
+            return Err(Error::RefreshTokenReuse(current.family_id));
+        }
+
+        let now = Utc::now();
+        let refresh_expired = current
+            .refresh_expires_at
+            .is_some_and(|t| t <= now);
+
+        if current.revoked || refresh_expired || current.is_idle_expired(now) {
+            return Err(Error::InvalidToken);
+        }
+
+        sqlx::query(
+            r#"
+            UPDATE oauth2_tokens
+            SET revoked = 1, rotated_at = ?2
+            WHERE id = ?1
+            "#,
+        )
+        .bind(current.id.to_string())
+        .bind(encode_time(Utc::now()))
+        .execute(&mut *tx)
+        .await?;
+
+        let stored = self
+            .insert_token(
+                &mut *tx,
+                new,
+                NewTokenRow {
+                    client_id: &current.client_id,
+                    user_id: current.user_id,
+                    scopes: &current.scopes,
+                    refresh_expires_in,
+                    family_id: Some(current.family_id),
+                },
+            )
+            .await?;
+
+        tx.commit().await?;
+
+        Ok(stored)
+    }
+
+    async fn revoke(
+        &self,
+        token: &str,
+        token_type_hint: Option<TokenTypeHint>,
+        client_id: &str,
+    ) -> Result<(), Error> {
+        let hashes = self.lookup_hashes(token)?;
+
+        for kind in TokenTypeHint::lookup_order(token_type_hint) {
+            let query = match kind {
+                TokenTypeHint::AccessToken => {
+                    r#"
+                    SELECT id, client_id, family_id FROM oauth2_tokens
+                    WHERE access_token_hash IN (SELECT value ->> 0 FROM json_each(?1))
+                      AND (access_token_hash, hash_algorithm, COALESCE(key_id, ''))
+                          IN (SELECT value ->> 0, value ->> 1, value ->> 2 FROM json_each(?1))
+                    "#
+                }
+                TokenTypeHint::RefreshToken => {
+                    r#"
+                    SELECT id, client_id, family_id FROM oauth2_tokens
+                    WHERE refresh_token_hash IN (SELECT value ->> 0 FROM json_each(?1))
+                      AND (refresh_token_hash, hash_algorithm, COALESCE(key_id, ''))
+                          IN (SELECT value ->> 0, value ->> 1, value ->> 2 FROM json_each(?1))
+                    "#
+                }
+            };
+
+            let Some(found) = sqlx::query(query)
+                .bind(&hashes)
+                .fetch_optional(&self.pool)
+                .await?
+            else {
+                continue;
+            };
+
+            let owner: &str = found.try_get("client_id")?;
+            if owner != client_id {
+                return Err(Error::UnauthorizedClient);
+            }
+
+            match kind {
+                TokenTypeHint::AccessToken => {
+                    sqlx::query(
+                        r#"
+                        UPDATE oauth2_tokens
+                        SET revoked = 1
+                        WHERE id = ?1
+                        "#,
+                    )
+                    .bind(found.try_get::<&str, _>("id")?)
+                    .execute(&self.pool)
+                    .await?;
+                }
+                TokenTypeHint::RefreshToken => {
+                    let family_id = decode_uuid(found.try_get("family_id")?)?;
+                    self.revoke_family(&self.pool, family_id).await?;
+                }
+            }
+
+            return Ok(());
+        }
+
+        Ok(())
+    }
+
+    async fn list_tokens_for_user(&self, user_id: Uuid, page: PageRequest) -> Result<TokenPage, Error> {
+        let (after_issued_at, after_id) = match page.after {
+            Some(cursor) => (Some(encode_time(cursor.issued_at)), Some(cursor.id.to_string())),
+            None => (None, None),
+        };
+
+        let rows = sqlx::query(
+            r#"
+            SELECT
+                id,
+                '' AS access_token_hash,
+                NULL AS refresh_token_hash,
+                client_id,
+                user_id,
+                scopes,
+                issued_at,
+                expires_at,
+                revoked,
+                revoked_at,
+                refresh_expires_at,
+                family_id,
+                rotated_at,
+                extra_fields,
+                key_id,
+                hash_algorithm,
+                last_used_at,
+                use_count,
+                idle_timeout_secs,
+                idle_expires_at,
+                sliding_window_secs,
+                max_expires_at,
+                token_type
+            FROM oauth2_tokens
+            WHERE user_id = ?1
+              AND NOT revoked
+              AND (idle_expires_at IS NULL OR idle_expires_at > ?5)
+              AND (
+                    expires_at IS NULL OR expires_at > ?5
+                    OR (
+                        refresh_token_hash IS NOT NULL
+                        AND (refresh_expires_at IS NULL OR refresh_expires_at > ?5)
+                    )
+              )
+              AND (?2 IS NULL OR (issued_at, id) < (?2, ?3))
+            ORDER BY issued_at DESC, id DESC
+            LIMIT ?4
+            "#,
+        )
+        .bind(user_id.to_string())
+        .bind(after_issued_at)
+        .bind(after_id)
+        .bind(i64::from(page.limit) + 1)
+        .bind(encode_time(Utc::now()))
+        .fetch_all(&self.pool)
+        .await?;
+
+        let rows = rows.iter().map(token_from_row).collect::<Result<_, _>>()?;
+
+        Ok(TokenPage::from_rows(rows, page.limit))
+    }
+
+    async fn revoke_by_id(&self, id: Uuid) -> Result<(), Error> {
+        let res = sqlx::query(
+            r#"
+            UPDATE oauth2_tokens
+            SET revoked = 1
+            WHERE id = ?1
+            "#,
+        )
+        .bind(id.to_string())
+        .execute(&self.pool)
+        .await?;
+
+        if res.rows_affected() == 0 {
+            return Err(Error::NotFound);
+        }
+
+        Ok(())
+    }
+
+    async fn revoke_all_for_user(&self, user_id: Uuid, except: Option<Uuid>) -> Result<usize, Error> {
+        let res = sqlx::query(
+            r#"
+            UPDATE oauth2_tokens
+            SET revoked = 1
+            WHERE user_id = ?1
+              AND NOT revoked
+              AND (?2 IS NULL OR id <> ?2)
+            "#,
+        )
+        .bind(user_id.to_string())
+        .bind(except.map(|id| id.to_string()))
+        .execute(&self.pool)
+        .await?;
+
+        Ok(res.rows_affected() as usize)
+    }
+
+    async fn revoke_all_for_client(&self, client_id: &str, except: Option<Uuid>) -> Result<usize, Error> {
+        let res = sqlx::query(
+            r#"
+            UPDATE oauth2_tokens
+            SET revoked = 1
+            WHERE client_id = ?1
+              AND NOT revoked
+              AND (?2 IS NULL OR id <> ?2)
+            "#,
+        )
+        .bind(client_id)
+        .bind(except.map(|id| id.to_string()))
+        .execute(&self.pool)
+        .await?;
+
+        Ok(res.rows_affected() as usize)
+    }
+
+    async fn revoke_all_for_user_and_client(
+        &self,
+        user_id: Uuid,
+        client_id: &str,
+        except: Option<Uuid>,
+    ) -> Result<usize, Error> {
+        let res = sqlx::query(
+            r#"
+            UPDATE oauth2_tokens
+            SET revoked = 1
+            WHERE user_id = ?1
+              AND client_id = ?2
+              AND NOT revoked
+              AND (?3 IS NULL OR id <> ?3)
+            "#,
+        )
+        .bind(user_id.to_string())
+        .bind(client_id)
+        .bind(except.map(|id| id.to_string()))
+        .execute(&self.pool)
+        .await?;
+
+        Ok(res.rows_affected() as usize)
+    }
+
+    async fn cleanup(&self) -> Result<usize, Error> {
+        let res = sqlx::query(
+            r#"
+            DELETE FROM oauth2_tokens
+            WHERE (
+                    revoked
+                    -- rotated refresh tokens are kept for reuse detection until they expire, for
+                    -- good if they never do
+                    AND NOT (rotated_at IS NOT NULL AND COALESCE(refresh_expires_at > ?1, TRUE))
+               )
+               OR (
+                    expires_at IS NOT NULL AND expires_at < ?1
+                    AND (
+                        refresh_token_hash IS NULL
+                        OR (refresh_expires_at IS NOT NULL AND refresh_expires_at < ?1)
+                    )
+               )
+               -- idled out for good; rotated rows stay for reuse detection as above
+               OR (idle_expires_at IS NOT NULL AND idle_expires_at < ?1 AND rotated_at IS NULL)
+            "#,
+        )
+        .bind(encode_time(Utc::now()))
+        .execute(&self.pool)
+        .await?;
+
+        Ok(res.rows_affected() as usize)
+    }
+}
@@ -0,0 +1,125 @@
+//! Debounced usage tracking (`last_used_at` / `use_count`).
+//!
+//! Stores with usage tracking enabled record each successful access-token lookup here
+//! instead of writing it to the database. Uses of the same token are merged, and a
+//! background task started with the first recorded use writes the whole batch once per
+//! flush interval, whether or not lookups keep coming.
+
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+use tokio::time::{interval_at, Instant, MissedTickBehavior};
+use uuid::Uuid;
+
+use crate::Error;
+
+/// Uses of one token not yet written to the store.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct PendingUse {
+    pub(crate) uses: i64,
+    pub(crate) last_used_at: DateTime<Utc>,
+}
+
+impl PendingUse {
+    fn merge(&mut self, other: PendingUse) {
+        self.uses += other.uses;
+        self.last_used_at = self.last_used_at.max(other.last_used_at);
+    }
+}
+
+struct Pending {
+    uses: HashMap<Uuid, PendingUse>,
+    flusher_started: bool,
+}
+
+pub(crate) struct UsageRecorder {
+    pending: Mutex<Pending>,
+    flush_interval: Duration,
+}
+
+impl UsageRecorder {
+    pub(crate) fn new(flush_interval: Duration) -> Self {
+        Self {
+            pending: Mutex::new(Pending {
+                uses: HashMap::new(),
+                flusher_started: false,
+            }),
+            flush_interval,
+        }
+    }
+
+    fn pending(&self) -> MutexGuard<'_, Pending> {
+        // Plain data, consistent between statements, so a poisoned lock is safe to reuse.
+        self.pending.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Record one use of token `id`. Returns `true` for the first use recorded, when the
+    /// caller should start the flusher with [`spawn_flusher`].
+    pub(crate) fn record(&self, id: Uuid) -> bool {
+        let use_ = PendingUse {
+            uses: 1,
+            last_used_at: Utc::now(),
+        };
+
+        let mut pending = self.pending();
+        pending
+            .uses
+            .entry(id)
+            .and_modify(|p| p.merge(use_))
+            .or_insert(use_);
+
+        !std::mem::replace(&mut pending.flusher_started, true)
+    }
+
+    /// Take every pending use for writing.
+    pub(crate) fn take(&self) -> Vec<(Uuid, PendingUse)> {
+        self.pending().uses.drain().collect()
+    }
+
+    /// Put back a batch that could not be written, so it goes out with the next flush.
+    pub(crate) fn restore(&self, batch: Vec<(Uuid, PendingUse)>) {
+        let mut pending = self.pending();
+        for (id, use_) in batch {
+            pending
+                .uses
+                .entry(id)
+                .and_modify(|p| p.merge(use_))
+                .or_insert(use_);
+        }
+    }
+}
+
+/// Run `flush(store)` every flush interval of `usage`, logging failures; a failed batch
+/// is restored and retried with the next flush.
+///
+/// `store` must be a clone of the store owning `usage`. Once every other clone is
+/// dropped, the task flushes one last time and stops.
+pub(crate) fn spawn_flusher<S, F, Fut>(store: S, usage: &Arc<UsageRecorder>, flush: F)
+where
+    S: Clone + Send + 'static,
+    F: Fn(S) -> Fut + Send + 'static,
+    Fut: Future<Output = Result<usize, Error>> + Send,
+{
+    let period = usage.flush_interval.max(Duration::from_millis(1));
+    let usage = Arc::downgrade(usage);
+
+    tokio::spawn(async move {
+        let mut interval = interval_at(Instant::now() + period, period);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            interval.tick().await;
+
+            // Only `store` is left holding the recorder.
+            let last = usage.strong_count() <= 1;
+            if let Err(error) = flush(store.clone()).await {
+                tracing::warn!(%error, "could not write token usage");
+            }
+            if last {
+                break;
+            }
+        }
+    });
+}
@@ -0,0 +1,113 @@
+//! Containers backing the integration and conformance tests.
+//!
+//! Each test binary starts one Postgres and one Redis container, on first use, and
+//! shares them between its tests. Every test gets a database of its own.
+
+use sqlx::{Connection, Executor, PgConnection, PgPool};
+use std::time::Duration;
+use testcontainers::core::{ImageExt, IntoContainerPort};
+use testcontainers::runners::AsyncRunner;
+use testcontainers::{ContainerAsync, GenericImage};
+use tokio::net::TcpStream;
+use tokio::sync::OnceCell;
+use uuid::Uuid;
+
+/// The shared Postgres container and the URL of its server, without a database.
+static POSTGRES: OnceCell<(ContainerAsync<GenericImage>, String)> = OnceCell::const_new();
+
+#[cfg(feature = "redis")]
+static REDIS: OnceCell<(ContainerAsync<GenericImage>, String)> = OnceCell::const_new();
+
+/// A freshly created, migrated database in the shared Postgres container.
+pub async fn setup_test_db() -> PgPool {
+    let (_, server_url) = POSTGRES.get_or_init(start_postgres).await;
+
+    let database = format!("test_{}", Uuid::new_v4().simple());
+    let mut admin = PgConnection::connect(&format!("{server_url}/postgres?sslmode=disable"))
+        .await
+        .expect("Failed to connect to Postgres");
+    admin
+        .execute(format!("CREATE DATABASE {database}").as_str())
+        .await
+        .expect("Failed to create test database");
+    admin.close().await.expect("Failed to disconnect from Postgres");
+
+    let pool = PgPool::connect(&format!("{server_url}/{database}?sslmode=disable"))
+        .await
+        .expect("Failed to connect to test database");
+
+    sqlx::migrate!("./migrations")
+        .run(&pool)
+        .await
+        .expect("Migrations failed");
+
+    pool
+}
+
+async fn start_postgres() -> (ContainerAsync<GenericImage>, String) {
+    let container: ContainerAsync<GenericImage> = GenericImage::new("postgres", "16-alpine")
+        .with_exposed_port(5432.tcp())
+        .with_env_var("POSTGRES_PASSWORD", "postgres")
+        .with_env_var("POSTGRES_DB", "testdb")
+        .start()
+        .await
+        .expect("Failed to start Postgres container");
+
+    let host = "localhost".to_string();
+    let port = container.get_host_port_ipv4(5432).await.expect("No port mapping");
+
+    // Wait for TCP port open
+    let deadline = std::time::Instant::now() + Duration::from_secs(60);
+    while TcpStream::connect(format!("{host}:{port}")).await.is_err() {
+        if std::time::Instant::now() > deadline {
+            panic!("TCP port never opened after 60s");
+        }
+        tokio::time::sleep(Duration::from_millis(500)).await;
+    }
+
+    // The entrypoint restarts Postgres once after initialising; give it time to.
+    tokio::time::sleep(Duration::from_secs(15)).await;
+
+    // Retry sqlx connect
+    let server_url = format!("postgres://postgres:postgres@{host}:{port}");
+    for _ in 1..=20 {
+        match PgConnection::connect(&format!("{server_url}/testdb?sslmode=disable")).await {
+            Ok(connection) => {
+                connection.close().await.expect("Failed to disconnect from Postgres");
+                return (container, server_url);
+            }
+            Err(_) => tokio::time::sleep(Duration::from_secs(2)).await,
+        }
+    }
+
+    panic!("Failed to connect to Postgres after 20 attempts");
+}
+
+/// URL of the shared Redis server. Tests share its keyspace, so give each store a
+/// key prefix of its own.
+#[cfg(feature = "redis")]
+pub async fn setup_redis() -> String {
+    let (_, url) = REDIS.get_or_init(start_redis).await;
+    url.clone()
+}
+
+#[cfg(feature = "redis")]
+async fn start_redis() -> (ContainerAsync<GenericImage>, String) {
+    let container: ContainerAsync<GenericImage> = GenericImage::new("redis", "7-alpine")
+        .with_exposed_port(6379.tcp())
+        .start()
+        .await
+        .expect("Failed to start Redis container");
+
+    let port = container.get_host_port_ipv4(6379).await.expect("No port mapping");
+
+    let deadline = std::time::Instant::now() + Duration::from_secs(60);
+    while TcpStream::connect(format!("127.0.0.1:{port}")).await.is_err() {
+        if std::time::Instant::now() > deadline {
+            panic!("TCP port never opened after 60s");
+        }
+        tokio::time::sleep(Duration::from_millis(500)).await;
+    }
+
+    (container, format!("redis://127.0.0.1:{port}"))
+}
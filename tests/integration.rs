@@ -1,84 +1,37 @@
+mod common;
+
 #[cfg(test)]
 mod tests {
-    use oauth2_pg_store::{OAuth2TokenStore, PgTokenStore};
+    #[cfg(feature = "redis")]
+    use crate::common::setup_redis;
+    use crate::common::setup_test_db;
+    use oauth2_pg_store::{
+        generate_user_code, AuthorizationCodeGrant, AuthorizationCodeStore,
+        CachedTokenStore, CleanupOptions, CleanupTable, CleanupTask,
+        DeviceCodeStore, DevicePoll, Error, HashingKey, HmacSha256Hasher, InMemoryTokenStore,
+        LimitPolicy, OAuth2TokenStore, PageRequest, PgTokenStore, ScopeRules,
+        Sha256Hasher, StoredToken, TokenLimits, TokenPage, TokenTypeHint,
+    };
     use oauth2::{
         AccessToken,
+        AuthorizationCode,
         basic::BasicTokenType,
+        DeviceCode,
         EmptyExtraTokenFields,
+        ExtraTokenFields,
+        PkceCodeChallenge,
+        PkceCodeVerifier,
         RefreshToken,
         Scope,
         StandardTokenResponse,
     };
-    use sqlx::PgPool;
-    use testcontainers::core::{ImageExt, IntoContainerPort};
-    use testcontainers::{ContainerAsync, GenericImage};
-    use testcontainers::runners::AsyncRunner;
     use uuid::Uuid;
+    use std::sync::Arc;
     use std::time::Duration;
-    use tokio::net::TcpStream;
-
-    async fn setup_test_db() -> (PgPool, ContainerAsync<GenericImage>) {
-        let container: ContainerAsync<GenericImage> = GenericImage::new("postgres", "16-alpine")
-            .with_exposed_port(5432.tcp())
-            .with_env_var("POSTGRES_PASSWORD", "postgres")
-            .with_env_var("POSTGRES_DB", "testdb")
-            .start()
-            .await
-            .expect("Failed to start Postgres container");
-
-        let host = "localhost".to_string();
-        let port = container.get_host_port_ipv4(5432).await.expect("No port mapping");
-
-        // Wait for TCP port open
-        let deadline = std::time::Instant::now() + Duration::from_secs(60);
-        loop {
-            if TcpStream::connect(format!("{host}:{port}")).await.is_ok() {
-                println!("TCP port {port} is open");
-                break;
-            }
-            if std::time::Instant::now() > deadline {
-                panic!("TCP port never opened after 60s");
-            }
-            tokio::time::sleep(Duration::from_millis(500)).await;
-        }
-
-        // Fixed delay for Postgres startup
-        println!("Port open — waiting 15s for Postgres to be ready...");
-        tokio::time::sleep(Duration::from_secs(15)).await;
-
-        // Retry sqlx connect
-        let database_url = format!("postgres://postgres:postgres@{host}:{port}?sslmode=disable");
-        let mut pool: Option<PgPool> = None;
-        for attempt in 1..=20 {
-            match PgPool::connect(&database_url).await {
-                Ok(p) => {
-                    pool = Some(p);
-                    println!("sqlx connected after attempt {}", attempt);
-                    break;
-                }
-                Err(e) => {
-                    println!("sqlx connect attempt {} failed: {}", attempt, e);
-                    tokio::time::sleep(Duration::from_secs(2)).await;
-                }
-            }
-        }
-
-        let pool = pool.expect("Failed to connect sqlx pool after 20 attempts");
-
-        println!("Running migrations...");
-        sqlx::migrate!("./migrations")
-            .run(&pool)
-            .await
-            .expect("Migrations failed");
-
-        println!("Setup complete — running test");
-
-        (pool, container)
-    }
 
     #[tokio::test]
     async fn test_store_and_retrieve_token() -> Result<(), Box<dyn std::error::Error>> {
-        let (pool, _container) = setup_test_db().await;
+        let pool = setup_test_db().await;
         let store = PgTokenStore::new(pool);
 
         let access_token_str = Uuid::new_v4().to_string();
@@ -101,7 +54,7 @@ mod tests {
         let user_id = Uuid::new_v4();
 
         store
-            .store_token(&token_response, "test-app", Some(user_id), &scopes)
+            .store_token(&token_response, "test-app", Some(user_id), &scopes, None)
             .await?;
 
         let found = store
@@ -120,7 +73,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_revoke_by_access_token() -> Result<(), Box<dyn std::error::Error>> {
-        let (pool, _container) = setup_test_db().await;
+        let pool = setup_test_db().await;
         let store = PgTokenStore::new(pool);
 
         let access_token_str = Uuid::new_v4().to_string();
@@ -134,7 +87,7 @@ mod tests {
         token_response.set_expires_in(Some(&Duration::from_secs(3600)));
 
         store
-            .store_token(&token_response, "revoke-test", None, &[])
+            .store_token(&token_response, "revoke-test", None, &[], None)
             .await?;
 
         store.revoke_by_access_token(&token).await?;
@@ -147,7 +100,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_cleanup_removes_expired_tokens() -> Result<(), Box<dyn std::error::Error>> {
-        let (pool, _container) = setup_test_db().await;
+        let pool = setup_test_db().await;
         let store = PgTokenStore::new(pool);
 
         let access_token_str = Uuid::new_v4().to_string();
@@ -162,7 +115,7 @@ mod tests {
         token_response.set_expires_in(Some(&Duration::from_millis(300)));
 
         store
-            .store_token(&token_response, "cleanup-test", None, &[])
+            .store_token(&token_response, "cleanup-test", None, &[], None)
             .await?;
 
         tokio::time::sleep(Duration::from_millis(1500)).await;
@@ -176,9 +129,166 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_cleanup_batched() -> Result<(), Box<dyn std::error::Error>> {
+        let pool = setup_test_db().await;
+        let store = PgTokenStore::new(pool.clone());
+
+        let mut live = Vec::new();
+        for i in 0..7 {
+            let access = AccessToken::new(Uuid::new_v4().to_string());
+            let mut token_response = StandardTokenResponse::new(
+                access.clone(),
+                BasicTokenType::Bearer,
+                EmptyExtraTokenFields {},
+            );
+            let expires_in = if i < 5 { Duration::ZERO } else { Duration::from_secs(3600) };
+            token_response.set_expires_in(Some(&expires_in));
+            store
+                .store_token(&token_response, "cleanup-batched", None, &[], None)
+                .await?;
+            if i >= 5 {
+                live.push(access);
+            }
+        }
+        store.revoke_by_access_token(&live[0]).await?;
+
+        // Refresh tokens expiring shortly: one left alone, one rotated; rotated rows whose
+        // refresh token never expires are kept.
+        for (access_expires_in, refresh_expires_in) in [
+            (Duration::ZERO, Some(Duration::from_millis(500))),
+            (Duration::from_secs(3600), Some(Duration::from_millis(500))),
+            (Duration::from_secs(3600), None),
+        ] {
+            let refresh = RefreshToken::new(Uuid::new_v4().to_string());
+            let mut token_response = StandardTokenResponse::new(
+                AccessToken::new(Uuid::new_v4().to_string()),
+                BasicTokenType::Bearer,
+                EmptyExtraTokenFields {},
+            );
+            token_response.set_expires_in(Some(&access_expires_in));
+            token_response.set_refresh_token(Some(refresh.clone()));
+            store
+                .store_token(&token_response, "cleanup-batched", None, &[], refresh_expires_in)
+                .await?;
+
+            if access_expires_in > Duration::ZERO {
+                let mut rotated = StandardTokenResponse::new(
+                    AccessToken::new(Uuid::new_v4().to_string()),
+                    BasicTokenType::Bearer,
+                    EmptyExtraTokenFields {},
+                );
+                rotated.set_expires_in(Some(&Duration::from_secs(3600)));
+                store.rotate_refresh_token(&refresh, &rotated, None).await?;
+            }
+        }
+
+        let revoked_at: Option<chrono::DateTime<chrono::Utc>> = sqlx::query_scalar(
+            "SELECT revoked_at FROM oauth2_tokens WHERE revoked AND rotated_at IS NULL",
+        )
+        .fetch_one(&pool)
+        .await?;
+        assert!(revoked_at.is_some(), "revocation time not recorded");
+
+        tokio::time::sleep(Duration::from_millis(1100)).await;
+
+        // Within the retention period nothing is deleted.
+        let retained = store
+            .cleanup_batched(&CleanupOptions::new().with_retention(Duration::from_secs(3600)), |_| {})
+            .await?;
+        assert_eq!(retained.total(), 0);
+
+        let mut progress = Vec::new();
+        let options = CleanupOptions::new()
+            .with_batch_size(2)
+            .with_pause(Duration::from_millis(10));
+        let report = store
+            .cleanup_batched(&options, |p| progress.push(*p))
+            .await?;
+
+        assert_eq!(report.tokens, 8);
+        assert_eq!(report.total(), 8);
+
+        // 2 + 2 + 2 + 2 + 0 token rows, then one empty batch per code table.
+        let token_batches: Vec<u64> = progress
+            .iter()
+            .filter(|p| p.table == CleanupTable::Tokens)
+            .map(|p| p.deleted)
+            .collect();
+        assert_eq!(token_batches, [2, 2, 2, 2, 0]);
+        assert_eq!(report.batches as usize, progress.len());
+        assert_eq!(progress.last().unwrap().batch, report.batches);
+        assert_eq!(progress[3].table_deleted, 8);
+
+        let rotated_left: i64 =
+            sqlx::query_scalar("SELECT COUNT(*) FROM oauth2_tokens WHERE rotated_at IS NOT NULL")
+                .fetch_one(&pool)
+                .await?;
+        assert_eq!(rotated_left, 1);
+
+        assert!(store.get_by_access_token(&live[1]).await?.is_some());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_cleanup_task() -> Result<(), Box<dyn std::error::Error>> {
+        let pool = setup_test_db().await;
+        let store = PgTokenStore::new(pool.clone());
+
+        let expired = AccessToken::new(Uuid::new_v4().to_string());
+        let mut token_response = StandardTokenResponse::new(
+            expired.clone(),
+            BasicTokenType::Bearer,
+            EmptyExtraTokenFields {},
+        );
+        token_response.set_expires_in(Some(&Duration::ZERO));
+        store
+            .store_token(&token_response, "cleanup-task", None, &[], None)
+            .await?;
+        let count = || async {
+            sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM oauth2_tokens")
+                .fetch_one(&pool)
+                .await
+        };
+
+        // Another replica holding the lock makes this one skip its run.
+        let task = CleanupTask::new(store.clone(), Duration::from_millis(100)).with_lock_key(42);
+        let mut other = pool.acquire().await?;
+        sqlx::query("SELECT pg_advisory_lock(42)").execute(&mut *other).await?;
+        assert!(task.run_once().await?.is_none());
+        assert_eq!(count().await?, 1);
+        sqlx::query("SELECT pg_advisory_unlock(42)").execute(&mut *other).await?;
+        drop(other);
+
+        // Spawned, it cleans on its interval until shut down.
+        let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+        let handle = task.spawn(async move {
+            let _ = stopped.await;
+        });
+
+        let deadline = std::time::Instant::now() + Duration::from_secs(10);
+        while count().await? > 0 {
+            assert!(std::time::Instant::now() < deadline, "cleanup task never ran");
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+
+        stop.send(()).ok();
+        tokio::time::timeout(Duration::from_secs(5), handle).await??;
+
+        // Nothing holds the lock after shutdown.
+        let report = CleanupTask::new(store, Duration::from_secs(60))
+            .with_lock_key(42)
+            .run_once()
+            .await?;
+        assert_eq!(report.map(|r| r.total()), Some(0));
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_revoke_by_refresh_token() -> Result<(), Box<dyn std::error::Error>> {
-        let (pool, _container) = setup_test_db().await;
+        let pool = setup_test_db().await;
         let store = PgTokenStore::new(pool);
 
         let refresh_token_str = Uuid::new_v4().to_string();
@@ -194,7 +304,7 @@ mod tests {
         token_response.set_refresh_token(Some(refresh.clone()));
 
         store
-            .store_token(&token_response, "refresh-test", None, &[])
+            .store_token(&token_response, "refresh-test", None, &[], None)
             .await?;
 
         store.revoke_by_refresh_token(&refresh).await?;
@@ -207,9 +317,1874 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_refresh_token_outlives_access_token() -> Result<(), Box<dyn std::error::Error>> {
+        let pool = setup_test_db().await;
+        let store = PgTokenStore::new(pool);
+
+        let access = AccessToken::new(Uuid::new_v4().to_string());
+        let refresh = RefreshToken::new(Uuid::new_v4().to_string());
+
+        let mut token_response = StandardTokenResponse::new(
+            access.clone(),
+            BasicTokenType::Bearer,
+            EmptyExtraTokenFields {},
+        );
+        token_response.set_expires_in(Some(&Duration::from_millis(300)));
+        token_response.set_refresh_token(Some(refresh.clone()));
+
+        store
+            .store_token(
+                &token_response,
+                "refresh-expiry-test",
+                None,
+                &[],
+                Some(Duration::from_secs(3600)),
+            )
+            .await?;
+
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+
+        let found = store.get_by_access_token(&access).await?;
+        assert!(found.is_none(), "Access token should have expired");
+
+        let found = store
+            .get_by_refresh_token(&refresh)
+            .await?
+            .expect("Refresh token should still be valid");
+        assert!(found.refresh_expires_at.is_some());
+
+        let removed = store.cleanup().await?;
+        assert_eq!(removed, 0, "Row with a live refresh token must survive cleanup");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_rotate_refresh_token() -> Result<(), Box<dyn std::error::Error>> {
+        let pool = setup_test_db().await;
+        let store = PgTokenStore::new(pool);
+
+        let old_access = AccessToken::new(Uuid::new_v4().to_string());
+        let old_refresh = RefreshToken::new(Uuid::new_v4().to_string());
+
+        let mut old_response = StandardTokenResponse::new(
+            old_access.clone(),
+            BasicTokenType::Bearer,
+            EmptyExtraTokenFields {},
+        );
+        old_response.set_expires_in(Some(&Duration::from_secs(3600)));
+        old_response.set_refresh_token(Some(old_refresh.clone()));
+
+        let user_id = Uuid::new_v4();
+        let scopes = vec![Scope::new("read".to_string())];
+
+        store
+            .store_token(&old_response, "rotate-test", Some(user_id), &scopes, None)
+            .await?;
+
+        let new_access = AccessToken::new(Uuid::new_v4().to_string());
+        let new_refresh = RefreshToken::new(Uuid::new_v4().to_string());
+
+        let mut new_response = StandardTokenResponse::new(
+            new_access.clone(),
+            BasicTokenType::Bearer,
+            EmptyExtraTokenFields {},
+        );
+        new_response.set_expires_in(Some(&Duration::from_secs(3600)));
+        new_response.set_refresh_token(Some(new_refresh.clone()));
+
+        let rotated = store
+            .rotate_refresh_token(&old_refresh, &new_response, None)
+            .await?;
+
+        assert_eq!(rotated.client_id, "rotate-test");
+        assert_eq!(rotated.user_id, Some(user_id));
+        assert_eq!(rotated.scopes, vec!["read".to_string()]);
+
+        assert!(store.get_by_access_token(&old_access).await?.is_none());
+
+        let current = store
+            .get_by_refresh_token(&new_refresh)
+            .await?
+            .expect("New refresh token should be valid");
+        assert_eq!(current.id, rotated.id);
+        assert!(current.rotated_at.is_none());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_rotation_succeeds_once() -> Result<(), Box<dyn std::error::Error>> {
+        let pool = setup_test_db().await;
+        let store = PgTokenStore::new(pool);
+
+        let old_refresh = RefreshToken::new(Uuid::new_v4().to_string());
+
+        let mut old_response = StandardTokenResponse::new(
+            AccessToken::new(Uuid::new_v4().to_string()),
+            BasicTokenType::Bearer,
+            EmptyExtraTokenFields {},
+        );
+        old_response.set_refresh_token(Some(old_refresh.clone()));
+
+        store
+            .store_token(&old_response, "race-test", None, &[], None)
+            .await?;
+
+        let attempts = (0..5).map(|_| {
+            let store = store.clone();
+            let old_refresh = old_refresh.clone();
+            let mut new_response = StandardTokenResponse::new(
+                AccessToken::new(Uuid::new_v4().to_string()),
+                BasicTokenType::Bearer,
+                EmptyExtraTokenFields {},
+            );
+            new_response.set_refresh_token(Some(RefreshToken::new(Uuid::new_v4().to_string())));
+
+            tokio::spawn(async move {
+                store
+                    .rotate_refresh_token(&old_refresh, &new_response, None)
+                    .await
+            })
+        });
+
+        let mut succeeded = 0;
+        for attempt in attempts.collect::<Vec<_>>() {
+            match attempt.await? {
+                Ok(_) => succeeded += 1,
+                Err(Error::RefreshTokenReuse(_)) => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        assert_eq!(succeeded, 1, "Exactly one rotation should win");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_refresh_token_reuse_revokes_family() -> Result<(), Box<dyn std::error::Error>> {
+        let pool = setup_test_db().await;
+        let store = PgTokenStore::new(pool);
+
+        let first_refresh = RefreshToken::new(Uuid::new_v4().to_string());
+
+        let mut first_response = StandardTokenResponse::new(
+            AccessToken::new(Uuid::new_v4().to_string()),
+            BasicTokenType::Bearer,
+            EmptyExtraTokenFields {},
+        );
+        first_response.set_refresh_token(Some(first_refresh.clone()));
+
+        store
+            .store_token(&first_response, "reuse-test", None, &[], None)
+            .await?;
+
+        let second_access = AccessToken::new(Uuid::new_v4().to_string());
+        let second_refresh = RefreshToken::new(Uuid::new_v4().to_string());
+
+        let mut second_response = StandardTokenResponse::new(
+            second_access.clone(),
+            BasicTokenType::Bearer,
+            EmptyExtraTokenFields {},
+        );
+        second_response.set_refresh_token(Some(second_refresh.clone()));
+
+        let rotated = store
+            .rotate_refresh_token(&first_refresh, &second_response, None)
+            .await?;
+
+        // The attacker replays the first refresh token.
+        let reuse = store.get_by_refresh_token(&first_refresh).await;
+        assert!(matches!(reuse, Err(Error::RefreshTokenReuse(id)) if id == rotated.family_id));
+
+        assert!(store.get_by_access_token(&second_access).await?.is_none());
+        assert!(store.get_by_refresh_token(&second_refresh).await?.is_none());
+
+        let again = store
+            .rotate_refresh_token(&first_refresh, &second_response, None)
+            .await;
+        assert!(matches!(again, Err(Error::RefreshTokenReuse(_))));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_introspect_access_and_refresh() -> Result<(), Box<dyn std::error::Error>> {
+        let pool = setup_test_db().await;
+        let store = PgTokenStore::new(pool);
+
+        let access_token_str = Uuid::new_v4().to_string();
+        let refresh_token_str = Uuid::new_v4().to_string();
+
+        let mut token_response = StandardTokenResponse::new(
+            AccessToken::new(access_token_str.clone()),
+            BasicTokenType::Bearer,
+            EmptyExtraTokenFields {},
+        );
+        token_response.set_expires_in(Some(&Duration::from_secs(3600)));
+        token_response.set_refresh_token(Some(RefreshToken::new(refresh_token_str.clone())));
+
+        let user_id = Uuid::new_v4();
+        let scopes = vec![
+            Scope::new("read".to_string()),
+            Scope::new("write".to_string()),
+        ];
+
+        store
+            .store_token(&token_response, "introspect-test", Some(user_id), &scopes, None)
+            .await?;
+
+        let access = store.introspect(&access_token_str, None).await?;
+        assert!(access.active);
+        assert_eq!(access.scope.as_deref(), Some("read write"));
+        assert_eq!(access.client_id.as_deref(), Some("introspect-test"));
+        assert_eq!(access.sub, Some(user_id.to_string()));
+        assert_eq!(access.token_type.as_deref(), Some("bearer"));
+        assert!(access.exp.is_some());
+
+        // A wrong hint still finds the token.
+        let refresh = store
+            .introspect(&refresh_token_str, Some(TokenTypeHint::AccessToken))
+            .await?;
+        assert!(refresh.active);
+        assert_eq!(refresh.exp, None, "Refresh token was stored without expiry");
+
+        let unknown = store.introspect("not-a-token", None).await?;
+        assert_eq!(serde_json::to_value(&unknown)?, serde_json::json!({ "active": false }));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_introspection_is_read_only() -> Result<(), Box<dyn std::error::Error>> {
+        let pool = setup_test_db().await;
+        let store = PgTokenStore::new(pool)
+            .with_usage_tracking(Duration::from_secs(3600))
+            .with_idle_timeout(Duration::from_secs(10))
+            .with_sliding_expiration(Duration::from_secs(86400));
+
+        let access = AccessToken::new(Uuid::new_v4().to_string());
+        let refresh = RefreshToken::new(Uuid::new_v4().to_string());
+        let mut token_response = StandardTokenResponse::new(
+            access.clone(),
+            BasicTokenType::Bearer,
+            EmptyExtraTokenFields {},
+        );
+        token_response.set_expires_in(Some(&Duration::from_secs(10)));
+        token_response.set_refresh_token(Some(refresh.clone()));
+        store
+            .store_token(&token_response, "introspect-test", None, &[], None)
+            .await?;
+        let before = store.find_by_access_token(&access).await?.unwrap();
+
+        // Long enough for a lookup to move both deadlines.
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+
+        assert!(store.introspect(access.secret(), None).await?.active);
+        assert!(
+            store
+                .introspect(refresh.secret(), Some(TokenTypeHint::RefreshToken))
+                .await?
+                .active
+        );
+
+        let after = store.find_by_access_token(&access).await?.unwrap();
+        assert_eq!(after.idle_expires_at, before.idle_expires_at);
+        assert_eq!(after.expires_at, before.expires_at);
+        assert_eq!(store.flush_usage().await?, 0, "introspection counted as a use");
+
+        // Introspecting a rotated refresh token reports it inactive without revoking the family.
+        let new_access = AccessToken::new(Uuid::new_v4().to_string());
+        let rotated = StandardTokenResponse::new(
+            new_access.clone(),
+            BasicTokenType::Bearer,
+            EmptyExtraTokenFields {},
+        );
+        store.rotate_refresh_token(&refresh, &rotated, None).await?;
+
+        let old = store.introspect(refresh.secret(), None).await?;
+        assert_eq!(serde_json::to_value(&old)?, serde_json::json!({ "active": false }));
+        assert!(store.get_by_access_token(&new_access).await?.is_some());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_revoke_is_idempotent_and_checks_client() -> Result<(), Box<dyn std::error::Error>> {
+        let pool = setup_test_db().await;
+        let store = PgTokenStore::new(pool);
+
+        let access_token_str = Uuid::new_v4().to_string();
+        let refresh_token_str = Uuid::new_v4().to_string();
+
+        let mut token_response = StandardTokenResponse::new(
+            AccessToken::new(access_token_str.clone()),
+            BasicTokenType::Bearer,
+            EmptyExtraTokenFields {},
+        );
+        token_response.set_refresh_token(Some(RefreshToken::new(refresh_token_str.clone())));
+
+        store
+            .store_token(&token_response, "owner-app", None, &[], None)
+            .await?;
+
+        let foreign = store.revoke(&refresh_token_str, None, "other-app").await;
+        assert!(matches!(foreign, Err(Error::UnauthorizedClient)));
+        assert!(store
+            .get_by_access_token(&AccessToken::new(access_token_str.clone()))
+            .await?
+            .is_some());
+
+        store
+            .revoke(&refresh_token_str, Some(TokenTypeHint::RefreshToken), "owner-app")
+            .await?;
+        assert!(store
+            .get_by_access_token(&AccessToken::new(access_token_str.clone()))
+            .await?
+            .is_none());
+
+        // Revoking again, or revoking an unknown token, is not an error.
+        store.revoke(&refresh_token_str, None, "owner-app").await?;
+        store.revoke("never-issued", None, "owner-app").await?;
+
+        Ok(())
+    }
+
+    #[cfg(feature = "axum")]
+    #[tokio::test]
+    async fn test_introspection_endpoint() -> Result<(), Box<dyn std::error::Error>> {
+        use async_trait::async_trait;
+        use axum::body::{to_bytes, Body};
+        use axum::http::{header, Request, StatusCode};
+        use oauth2_pg_store::endpoints::{introspection_router, ClientAuthenticator};
+        use tower::ServiceExt;
+
+        struct StaticClient;
+
+        #[async_trait]
+        impl ClientAuthenticator for StaticClient {
+            async fn authenticate(
+                &self,
+                client_id: &str,
+                client_secret: Option<&str>,
+            ) -> Result<bool, Error> {
+                Ok(client_id == "rs" && client_secret == Some("secret"))
+            }
+        }
+
+        let pool = setup_test_db().await;
+        let store = Arc::new(PgTokenStore::new(pool));
+
+        let access_token_str = Uuid::new_v4().to_string();
+        let token_response = StandardTokenResponse::new(
+            AccessToken::new(access_token_str.clone()),
+            BasicTokenType::Bearer,
+            EmptyExtraTokenFields {},
+        );
+        store
+            .store_token(&token_response, "endpoint-test", None, &[], None)
+            .await?;
+
+        let app = introspection_router(store, Arc::new(StaticClient));
+
+        let request = |auth: &'static str| {
+            Request::post("/introspect")
+                .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+                .header(header::AUTHORIZATION, auth)
+                .body(Body::from(format!("token={access_token_str}")))
+                .unwrap()
+        };
+
+        // base64("rs:wrong")
+        let response = app.clone().oneshot(request("Basic cnM6d3Jvbmc=")).await?;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        // base64("rs:secret")
+        let response = app.clone().oneshot(request("Basic cnM6c2VjcmV0")).await?;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = to_bytes(response.into_body(), usize::MAX).await?;
+        let json: serde_json::Value = serde_json::from_slice(&body)?;
+        assert_eq!(json["active"], true);
+        assert_eq!(json["client_id"], "endpoint-test");
+
+        // A non-Basic Authorization header leaves the form credentials to be checked.
+        let response = app
+            .oneshot(
+                Request::post("/introspect")
+                    .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+                    .header(header::AUTHORIZATION, "Bearer some-access-token")
+                    .body(Body::from(format!(
+                        "token={access_token_str}&client_id=rs&client_secret=secret"
+                    )))
+                    .unwrap(),
+            )
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = to_bytes(response.into_body(), usize::MAX).await?;
+        let json: serde_json::Value = serde_json::from_slice(&body)?;
+        assert_eq!(json["active"], true);
+
+        Ok(())
+    }
+
+    #[cfg(feature = "axum")]
+    #[tokio::test]
+    async fn test_bearer_auth_layer() -> Result<(), Box<dyn std::error::Error>> {
+        use axum::body::{to_bytes, Body};
+        use axum::http::{header, Request, StatusCode};
+        use axum::routing::get;
+        use axum::Router;
+        use oauth2_pg_store::bearer::{AuthenticatedToken, BearerAuthLayer, BearerError};
+        use tower::ServiceExt;
+
+        let store = Arc::new(InMemoryTokenStore::new());
+
+        let access = AccessToken::new(Uuid::new_v4().to_string());
+        let token_response = StandardTokenResponse::new(
+            access.clone(),
+            BasicTokenType::Bearer,
+            EmptyExtraTokenFields {},
+        );
+        store
+            .store_token(&token_response, "bearer-test", None, &[Scope::new("orders:read".into())], None)
+            .await?;
+
+        let app = Router::new()
+            .route(
+                "/orders",
+                get(|AuthenticatedToken(token): AuthenticatedToken| async move { token.client_id }),
+            )
+            .route(
+                "/admin",
+                get(|| async { BearerError::InsufficientScope(vec!["admin".to_string()]) }),
+            )
+            .layer(BearerAuthLayer::new(store.clone()));
+
+        let request = |path: &str, auth: Option<String>| {
+            let mut request = Request::get(path);
+            if let Some(auth) = auth {
+                request = request.header(header::AUTHORIZATION, auth);
+            }
+            request.body(Body::empty()).unwrap()
+        };
+        let challenge = |response: &axum::response::Response| {
+            response.headers()[header::WWW_AUTHENTICATE].to_str().unwrap().to_string()
+        };
+
+        // No credentials: a bare challenge.
+        let response = app.clone().oneshot(request("/orders", None)).await?;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(challenge(&response), r#"Bearer realm="oauth2""#);
+
+        let response = app.clone().oneshot(request("/orders", Some("Bearer".into()))).await?;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(challenge(&response), r#"Bearer realm="oauth2", error="invalid_request""#);
+
+        let response = app
+            .clone()
+            .oneshot(request("/orders", Some(format!("Bearer {}", Uuid::new_v4()))))
+            .await?;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(challenge(&response), r#"Bearer realm="oauth2", error="invalid_token""#);
+
+        // The scheme is case-insensitive.
+        let response = app
+            .clone()
+            .oneshot(request("/orders", Some(format!("bearer {}", access.secret()))))
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(to_bytes(response.into_body(), usize::MAX).await?, "bearer-test");
+
+        let response = app
+            .clone()
+            .oneshot(request("/admin", Some(format!("Bearer {}", access.secret()))))
+            .await?;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert_eq!(
+            challenge(&response),
+            r#"Bearer realm="oauth2", error="insufficient_scope", scope="admin""#
+        );
+
+        store.revoke_by_access_token(&access).await?;
+        let response = app
+            .oneshot(request("/orders", Some(format!("Bearer {}", access.secret()))))
+            .await?;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(challenge(&response), r#"Bearer realm="oauth2", error="invalid_token""#);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_scope_rules() -> Result<(), Box<dyn std::error::Error>> {
+        let store = InMemoryTokenStore::new();
+        let access = AccessToken::new(Uuid::new_v4().to_string());
+        let token_response = StandardTokenResponse::new(
+            access.clone(),
+            BasicTokenType::Bearer,
+            EmptyExtraTokenFields {},
+        );
+        let scopes = [Scope::new("admin".into()), Scope::new("orders:*".into())];
+        store
+            .store_token(&token_response, "scope-test", None, &scopes, None)
+            .await?;
+        let token = store.get_by_access_token(&access).await?.unwrap();
+
+        assert!(token.has_scope("admin"));
+        assert!(!token.has_scope("admin:read"));
+        assert!(token.has_all(&["admin", "orders:*"]));
+        assert!(!token.has_all(&["admin", "billing"]));
+        assert!(token.has_any(&["billing", "admin"]));
+        assert!(!token.has_any::<&str>(&[]));
+
+        let rules = ScopeRules::hierarchical(':')
+            .with_wildcards()
+            .with_implied("admin", ["billing"])
+            .with_implied("billing", ["invoices:read", "admin"]);
+        assert!(token.has_scope_with(&rules, "admin:read:logs"));
+        assert!(!token.has_scope_with(&rules, "administer"));
+        assert!(token.has_all_with(&rules, &["orders:write", "orders:read:all", "billing:export"]));
+        // Implications are transitive, and cycles are harmless.
+        assert!(token.has_scope_with(&rules, "invoices:read"));
+        assert!(!token.has_any_with(&rules, &["invoices:write", "users"]));
+
+        // Wildcards only apply when enabled.
+        assert!(!ScopeRules::hierarchical(':').grants("orders:*", "orders:read"));
+        assert!(ScopeRules::exact().with_wildcards().grants("orders:*", "orders:read"));
+        assert!(!ScopeRules::exact().with_wildcards().grants("orders:*", "orders:read:all"));
+
+        Ok(())
+    }
+
+    #[cfg(feature = "axum")]
+    #[tokio::test]
+    async fn test_require_scope_layer() -> Result<(), Box<dyn std::error::Error>> {
+        use axum::body::Body;
+        use axum::http::{header, Request, StatusCode};
+        use axum::routing::{get, post};
+        use axum::Router;
+        use oauth2_pg_store::bearer::{BearerAuthLayer, RequireScopeLayer};
+        use tower::ServiceExt;
+
+        let store = Arc::new(InMemoryTokenStore::new());
+        let access = AccessToken::new(Uuid::new_v4().to_string());
+        let token_response = StandardTokenResponse::new(
+            access.clone(),
+            BasicTokenType::Bearer,
+            EmptyExtraTokenFields {},
+        );
+        store
+            .store_token(&token_response, "scope-test", None, &[Scope::new("orders".into())], None)
+            .await?;
+
+        let app = Router::new()
+            .route(
+                "/orders",
+                get(|| async { "listed" })
+                    .merge(post(|| async { "created" }).layer(RequireScopeLayer::all(["orders:write"]))),
+            )
+            .route(
+                "/orders/export",
+                get(|| async { "exported" }).layer(
+                    RequireScopeLayer::any(["orders:export", "admin"])
+                        .with_rules(ScopeRules::hierarchical(':')),
+                ),
+            )
+            .route(
+                "/admin",
+                get(|| async { "admin" }).layer(RequireScopeLayer::any(["admin", "root"])),
+            )
+            .layer(BearerAuthLayer::new(store.clone()));
+
+        let request = |method: &str, path: &str| {
+            Request::builder()
+                .method(method)
+                .uri(path)
+                .header(header::AUTHORIZATION, format!("Bearer {}", access.secret()))
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        // Exact matching by default: `orders` does not grant `orders:write`.
+        let response = app.clone().oneshot(request("GET", "/orders")).await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = app.clone().oneshot(request("POST", "/orders")).await?;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert_eq!(
+            response.headers()[header::WWW_AUTHENTICATE],
+            r#"Bearer realm="oauth2", error="insufficient_scope", scope="orders:write""#
+        );
+
+        let response = app.clone().oneshot(request("GET", "/orders/export")).await?;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = app.clone().oneshot(request("GET", "/admin")).await?;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert_eq!(
+            response.headers()[header::WWW_AUTHENTICATE],
+            r#"Bearer realm="oauth2", error="insufficient_scope", scope="admin root""#
+        );
+
+        // The bearer check still runs first.
+        let response = app
+            .oneshot(Request::post("/orders").body(Body::empty()).unwrap())
+            .await?;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        Ok(())
+    }
+
+    #[cfg(feature = "axum")]
+    #[tokio::test]
+    async fn test_revocation_endpoint() -> Result<(), Box<dyn std::error::Error>> {
+        use async_trait::async_trait;
+        use axum::body::Body;
+        use axum::http::{header, Request, StatusCode};
+        use oauth2_pg_store::endpoints::{revocation_router, ClientAuthenticator};
+        use tower::ServiceExt;
+
+        struct AnyClient;
+
+        #[async_trait]
+        impl ClientAuthenticator for AnyClient {
+            async fn authenticate(&self, _: &str, _: Option<&str>) -> Result<bool, Error> {
+                Ok(true)
+            }
+        }
+
+        let pool = setup_test_db().await;
+        let store = Arc::new(PgTokenStore::new(pool));
+
+        let access_token_str = Uuid::new_v4().to_string();
+        let token_response = StandardTokenResponse::new(
+            AccessToken::new(access_token_str.clone()),
+            BasicTokenType::Bearer,
+            EmptyExtraTokenFields {},
+        );
+        store
+            .store_token(&token_response, "revoke-endpoint-test", None, &[], None)
+            .await?;
+
+        let app = revocation_router(Arc::clone(&store), Arc::new(AnyClient));
+
+        let request = |client_id: &str, token: &str| {
+            Request::post("/revoke")
+                .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+                .body(Body::from(format!("token={token}&client_id={client_id}")))
+                .unwrap()
+        };
+
+        let response = app
+            .clone()
+            .oneshot(request("someone-else", &access_token_str))
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let still_valid = store
+            .get_by_access_token(&AccessToken::new(access_token_str.clone()))
+            .await?;
+        assert!(still_valid.is_some(), "another client's token must not be revoked");
+
+        let response = app
+            .clone()
+            .oneshot(request("revoke-endpoint-test", &access_token_str))
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(store
+            .get_by_access_token(&AccessToken::new(access_token_str))
+            .await?
+            .is_none());
+
+        let response = app
+            .oneshot(request("revoke-endpoint-test", "unknown-token"))
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        Ok(())
+    }
+
+    fn code_grant(challenge: &PkceCodeChallenge) -> AuthorizationCodeGrant {
+        AuthorizationCodeGrant {
+            client_id: "code-app".to_string(),
+            user_id: Uuid::new_v4(),
+            redirect_uri: Some("https://app.example/callback".to_string()),
+            scopes: vec!["openid".to_string()],
+            code_challenge: Some(challenge.as_str().to_string()),
+            code_challenge_method: Some(challenge.method().to_string()),
+            nonce: Some("n-0S6_WzA2Mj".to_string()),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_authorization_code_is_single_use() -> Result<(), Box<dyn std::error::Error>> {
+        let pool = setup_test_db().await;
+        let store = PgTokenStore::new(pool);
+
+        let (challenge, verifier) = PkceCodeChallenge::new_random_sha256();
+        let code = AuthorizationCode::new(Uuid::new_v4().to_string());
+        let grant = code_grant(&challenge);
+
+        store
+            .store_code(&code, &grant, Duration::from_secs(60))
+            .await?;
+
+        let redeemed = store
+            .consume_code(&code, "code-app", Some("https://app.example/callback"), Some(&verifier))
+            .await?;
+        assert_eq!(redeemed.user_id, grant.user_id);
+        assert_eq!(redeemed.nonce, grant.nonce);
+
+        let replay = store
+            .consume_code(&code, "code-app", Some("https://app.example/callback"), Some(&verifier))
+            .await;
+        assert!(matches!(replay, Err(Error::NotFound)));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_authorization_code_rejects_wrong_verifier() -> Result<(), Box<dyn std::error::Error>> {
+        let pool = setup_test_db().await;
+        let store = PgTokenStore::new(pool);
+
+        let (challenge, _verifier) = PkceCodeChallenge::new_random_sha256();
+        let code = AuthorizationCode::new(Uuid::new_v4().to_string());
+
+        store
+            .store_code(&code, &code_grant(&challenge), Duration::from_secs(60))
+            .await?;
+
+        let wrong = PkceCodeVerifier::new("x".repeat(43));
+        let result = store
+            .consume_code(&code, "code-app", Some("https://app.example/callback"), Some(&wrong))
+            .await;
+        assert!(matches!(result, Err(Error::InvalidGrant(_))));
+
+        // The failed attempt burned the code.
+        let (_, verifier) = PkceCodeChallenge::new_random_sha256();
+        let result = store
+            .consume_code(&code, "code-app", Some("https://app.example/callback"), Some(&verifier))
+            .await;
+        assert!(matches!(result, Err(Error::NotFound)));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_device_code_flow() -> Result<(), Box<dyn std::error::Error>> {
+        let pool = setup_test_db().await;
+        let store = PgTokenStore::new(pool);
+
+        let device_code = DeviceCode::new(Uuid::new_v4().to_string());
+        let user_code = generate_user_code();
+
+        store
+            .store_device_code(
+                &device_code,
+                &user_code,
+                "cli-app",
+                &[Scope::new("read".to_string())],
+                Duration::from_secs(600),
+                Duration::from_secs(5),
+            )
+            .await?;
+
+        let poll = store.poll(&device_code, "cli-app").await?;
+        assert!(matches!(poll, DevicePoll::Pending));
+
+        // Polling again immediately violates the 5s interval.
+        let poll = store.poll(&device_code, "cli-app").await?;
+        assert!(matches!(poll, DevicePoll::SlowDown { interval } if interval == Duration::from_secs(10)));
+
+        // Users type codes loosely.
+        let typed = user_code.secret().to_lowercase().replace('-', " ");
+        assert!(store.find_by_user_code(&typed).await?.is_some());
+
+        let user_id = Uuid::new_v4();
+        store.approve(&typed, user_id).await?;
+        assert!(matches!(store.deny(&typed, user_id).await, Err(Error::NotFound)));
+
+        match store.poll(&device_code, "cli-app").await? {
+            DevicePoll::Approved(grant) => {
+                assert_eq!(grant.user_id, Some(user_id));
+                assert_eq!(grant.scopes, vec!["read".to_string()]);
+            }
+            other => panic!("Expected approval, got {other:?}"),
+        }
+
+        let consumed = store.poll(&device_code, "cli-app").await;
+        assert!(matches!(consumed, Err(Error::NotFound)));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_device_user_code_collisions() -> Result<(), Box<dyn std::error::Error>> {
+        let pool = setup_test_db().await;
+        let store = PgTokenStore::new(pool);
+
+        let user_code = generate_user_code();
+        let store_request = |expires_in: Duration| {
+            let store = &store;
+            let user_code = &user_code;
+            async move {
+                let device_code = DeviceCode::new(Uuid::new_v4().to_string());
+                store
+                    .store_device_code(&device_code, user_code, "cli-app", &[], expires_in, Duration::from_secs(5))
+                    .await
+                    .map(|()| device_code)
+            }
+        };
+
+        // A request that expired without ever being polled gives up its user code.
+        let expired = store_request(Duration::ZERO).await?;
+        let live = store_request(Duration::from_secs(600)).await?;
+        assert!(matches!(store.poll(&expired, "cli-app").await, Err(Error::NotFound)));
+        assert!(matches!(store.poll(&live, "cli-app").await?, DevicePoll::Pending));
+
+        // An unexpired one keeps it.
+        assert!(matches!(
+            store_request(Duration::from_secs(600)).await,
+            Err(Error::UserCodeTaken)
+        ));
+
+        let device_code = DeviceCode::new(Uuid::new_v4().to_string());
+        let issued = store
+            .issue_device_code(&device_code, "cli-app", &[], Duration::from_secs(600), Duration::from_secs(5))
+            .await?;
+        let found = store.find_by_user_code(issued.secret()).await?.expect("issued code not stored");
+        assert_eq!(found.client_id, "cli-app");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_store_extra_token_fields() -> Result<(), Box<dyn std::error::Error>> {
+        #[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+        struct IdTokenFields {
+            id_token: String,
+        }
+
+        impl ExtraTokenFields for IdTokenFields {}
+
+        let pool = setup_test_db().await;
+        let store = PgTokenStore::new(pool);
+
+        let access = AccessToken::new(Uuid::new_v4().to_string());
+        let fields = IdTokenFields {
+            id_token: "eyJhbGciOiJSUzI1NiJ9.payload.signature".to_string(),
+        };
+
+        let token_response = StandardTokenResponse::new(
+            access.clone(),
+            BasicTokenType::Bearer,
+            fields.clone(),
+        );
+
+        store
+            .store_token(&token_response, "oidc-app", None, &[], None)
+            .await?;
+
+        let found = store
+            .get_by_access_token(&access)
+            .await?
+            .expect("Token should be found");
+
+        assert_eq!(found.typed_extra_fields::<IdTokenFields>()?, fields);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_bulk_revocation() -> Result<(), Box<dyn std::error::Error>> {
+        let pool = setup_test_db().await;
+        let store = PgTokenStore::new(pool);
+
+        let user_id = Uuid::new_v4();
+        let mut issued = Vec::new();
+
+        for client_id in ["web", "web", "mobile"] {
+            let access = AccessToken::new(Uuid::new_v4().to_string());
+            let token_response = StandardTokenResponse::new(
+                access.clone(),
+                BasicTokenType::Bearer,
+                EmptyExtraTokenFields {},
+            );
+            store
+                .store_token(&token_response, client_id, Some(user_id), &[], None)
+                .await?;
+            issued.push(access);
+        }
+
+        let other_user = AccessToken::new(Uuid::new_v4().to_string());
+        let token_response = StandardTokenResponse::new(
+            other_user.clone(),
+            BasicTokenType::Bearer,
+            EmptyExtraTokenFields {},
+        );
+        store
+            .store_token(&token_response, "web", Some(Uuid::new_v4()), &[], None)
+            .await?;
+
+        let current = store
+            .get_by_access_token(&issued[0])
+            .await?
+            .expect("Token should be found");
+
+        let revoked = store
+            .revoke_all_for_user_and_client(user_id, "web", Some(current.id))
+            .await?;
+        assert_eq!(revoked, 1);
+        assert!(store.get_by_access_token(&issued[0]).await?.is_some());
+        assert!(store.get_by_access_token(&issued[1]).await?.is_none());
+
+        let revoked = store.revoke_all_for_user(user_id, None).await?;
+        assert_eq!(revoked, 2, "Already-revoked tokens are not counted");
+        assert!(store.get_by_access_token(&other_user).await?.is_some());
+
+        let revoked = store.revoke_all_for_client("web", None).await?;
+        assert_eq!(revoked, 1);
+        assert!(store.get_by_access_token(&other_user).await?.is_none());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_list_tokens_for_user_paginates() -> Result<(), Box<dyn std::error::Error>> {
+        let pool = setup_test_db().await;
+        let store = PgTokenStore::new(pool);
+
+        let user_id = Uuid::new_v4();
+
+        for client_id in ["laptop", "phone", "tablet"] {
+            let token_response = StandardTokenResponse::new(
+                AccessToken::new(Uuid::new_v4().to_string()),
+                BasicTokenType::Bearer,
+                EmptyExtraTokenFields {},
+            );
+            store
+                .store_token(&token_response, client_id, Some(user_id), &[], None)
+                .await?;
+        }
+
+        let first = store
+            .list_tokens_for_user(user_id, PageRequest::first(2))
+            .await?;
+        assert_eq!(first.tokens.len(), 2);
+        assert!(first.tokens.iter().all(|t| t.access_token_hash.is_empty()));
+        assert!(first.tokens[0].issued_at >= first.tokens[1].issued_at);
+
+        let second = store
+            .list_tokens_for_user(
+                user_id,
+                PageRequest {
+                    limit: 2,
+                    after: first.next,
+                },
+            )
+            .await?;
+        assert_eq!(second.tokens.len(), 1);
+        assert!(second.next.is_none());
+
+        store.revoke_by_id(second.tokens[0].id).await?;
+
+        let remaining = store
+            .list_tokens_for_user(user_id, PageRequest::first(10))
+            .await?;
+        assert_eq!(remaining.tokens.len(), 2);
+        assert!(remaining.next.is_none());
+
+        assert!(matches!(store.revoke_by_id(Uuid::new_v4()).await, Err(Error::NotFound)));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_pepper_rotation_keeps_tokens_valid() -> Result<(), Box<dyn std::error::Error>> {
+        let pool = setup_test_db().await;
+
+        let unkeyed = PgTokenStore::new(pool.clone());
+        let v1 = PgTokenStore::new(pool.clone()).with_hashing_key(HashingKey::new("v1", b"pepper-one"));
+
+        let store_with = |store: PgTokenStore| async move {
+            let access = AccessToken::new(Uuid::new_v4().to_string());
+            let token_response = StandardTokenResponse::new(
+                access.clone(),
+                BasicTokenType::Bearer,
+                EmptyExtraTokenFields {},
+            );
+            store
+                .store_token(&token_response, "pepper-test", None, &[], None)
+                .await
+                .map(|_| access)
+        };
+
+        let legacy_token = store_with(unkeyed).await?;
+        let v1_token = store_with(v1.clone()).await?;
+
+        let found = v1
+            .get_by_access_token(&v1_token)
+            .await?
+            .expect("Token should be found");
+        assert_eq!(found.key_id.as_deref(), Some("v1"));
+        assert_eq!(found.hash_algorithm, "blake3-keyed");
+        assert_ne!(
+            found.access_token_hash,
+            blake3::hash(v1_token.secret().as_bytes()).to_hex().to_string(),
+            "Keyed hash must differ from the unkeyed hash"
+        );
+
+        let v2 = PgTokenStore::new(pool.clone())
+            .with_hashing_key(HashingKey::new("v2", b"pepper-two"))
+            .with_retired_hashing_key(HashingKey::new("v1", b"pepper-one"));
+
+        assert!(v2.get_by_access_token(&v1_token).await?.is_some());
+        assert!(v2.get_by_access_token(&legacy_token).await?.is_some());
+
+        let v2_token = store_with(v2.clone()).await?;
+        let found = v2
+            .get_by_access_token(&v2_token)
+            .await?
+            .expect("Token should be found");
+        assert_eq!(found.key_id.as_deref(), Some("v2"));
+
+        // Once v1 is dropped, its tokens no longer validate; unkeyed ones still do
+        // until the fallback is turned off.
+        let v2_only = PgTokenStore::new(pool.clone()).with_hashing_key(HashingKey::new("v2", b"pepper-two"));
+        assert!(v2_only.get_by_access_token(&v1_token).await?.is_none());
+        assert!(v2_only.get_by_access_token(&legacy_token).await?.is_some());
+
+        let keyed_only = PgTokenStore::new(pool)
+            .with_hashing_key(HashingKey::new("v2", b"pepper-two"))
+            .without_unkeyed_fallback();
+        assert!(keyed_only.get_by_access_token(&legacy_token).await?.is_none());
+        assert!(keyed_only.get_by_access_token(&v2_token).await?.is_some());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_hash_algorithm_migration() -> Result<(), Box<dyn std::error::Error>> {
+        let pool = setup_test_db().await;
+
+        let sha = PgTokenStore::new(pool.clone()).with_hasher(Sha256Hasher);
+        let access = AccessToken::new(Uuid::new_v4().to_string());
+        let refresh = RefreshToken::new(Uuid::new_v4().to_string());
+        let mut token_response = StandardTokenResponse::new(
+            access.clone(),
+            BasicTokenType::Bearer,
+            EmptyExtraTokenFields {},
+        );
+        token_response.set_refresh_token(Some(refresh.clone()));
+        sha.store_token(&token_response, "hash-test", None, &[], None)
+            .await?;
+
+        let found = sha
+            .get_by_access_token(&access)
+            .await?
+            .expect("Token should be found");
+        assert_eq!(found.hash_algorithm, "sha256");
+        assert_eq!(found.key_id, None);
+
+        // Move to HMAC-SHA256 while still accepting the SHA-256 rows.
+        let hmac = PgTokenStore::new(pool.clone())
+            .with_hasher(HmacSha256Hasher::new("k1", b"hmac-secret"))
+            .with_legacy_hasher(Sha256Hasher);
+        assert!(hmac.get_by_access_token(&access).await?.is_some());
+
+        let new_access = AccessToken::new(Uuid::new_v4().to_string());
+        let new_refresh = RefreshToken::new(Uuid::new_v4().to_string());
+        let mut rotated = StandardTokenResponse::new(
+            new_access.clone(),
+            BasicTokenType::Bearer,
+            EmptyExtraTokenFields {},
+        );
+        rotated.set_refresh_token(Some(new_refresh));
+        let stored = hmac.rotate_refresh_token(&refresh, &rotated, None).await?;
+        assert_eq!(stored.hash_algorithm, "hmac-sha256");
+        assert_eq!(stored.key_id.as_deref(), Some("k1"));
+
+        // The SHA-256-only store cannot see rows written with HMAC.
+        assert!(sha.get_by_access_token(&new_access).await?.is_none());
+
+        // Rows are only checked against the hasher recorded on them: the same secret
+        // under another key id does not match.
+        let other_key = PgTokenStore::new(pool).with_hasher(HmacSha256Hasher::new("k2", b"hmac-secret"));
+        assert!(other_key.get_by_access_token(&new_access).await?.is_none());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_cached_store_serves_hits_and_evicts_on_revoke() -> Result<(), Box<dyn std::error::Error>> {
+        let backend = InMemoryTokenStore::new();
+        let cached = CachedTokenStore::new(backend.clone()).with_ttl(Duration::from_secs(60));
+
+        let access = AccessToken::new(Uuid::new_v4().to_string());
+        let mut token_response = StandardTokenResponse::new(
+            access.clone(),
+            BasicTokenType::Bearer,
+            EmptyExtraTokenFields {},
+        );
+        token_response.set_expires_in(Some(&Duration::from_secs(3600)));
+        cached
+            .store_token(&token_response, "cache-test", None, &[], None)
+            .await?;
+
+        assert!(cached.get_by_access_token(&access).await?.is_some());
+
+        // Revoked behind the cache's back: the cached entry is still served.
+        backend.revoke_by_access_token(&access).await?;
+        assert!(cached.get_by_access_token(&access).await?.is_some());
+        cached.clear();
+        assert!(cached.get_by_access_token(&access).await?.is_none());
+
+        // Revoked through the cache: evicted immediately.
+        let access = AccessToken::new(Uuid::new_v4().to_string());
+        let token_response = StandardTokenResponse::new(
+            access.clone(),
+            BasicTokenType::Bearer,
+            EmptyExtraTokenFields {},
+        );
+        let user_id = Uuid::new_v4();
+        cached
+            .store_token(&token_response, "cache-test", Some(user_id), &[], None)
+            .await?;
+        assert!(cached.get_by_access_token(&access).await?.is_some());
+        cached.revoke_all_for_user(user_id, None).await?;
+        assert!(cached.get_by_access_token(&access).await?.is_none());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_cached_store_evicts_only_affected_entries() -> Result<(), Box<dyn std::error::Error>> {
+        let backend = InMemoryTokenStore::new();
+        let cached = CachedTokenStore::new(backend.clone()).with_ttl(Duration::from_secs(60));
+
+        let mut issued = Vec::new();
+        for _ in 0..2 {
+            let access = AccessToken::new(Uuid::new_v4().to_string());
+            let refresh = RefreshToken::new(Uuid::new_v4().to_string());
+            let mut token_response = StandardTokenResponse::new(
+                access.clone(),
+                BasicTokenType::Bearer,
+                EmptyExtraTokenFields {},
+            );
+            token_response.set_refresh_token(Some(refresh.clone()));
+            cached
+                .store_token(&token_response, "cache-test", None, &[], None)
+                .await?;
+            assert!(cached.get_by_access_token(&access).await?.is_some());
+            issued.push((access, refresh));
+        }
+        let [(first_access, first_refresh), (second_access, second_refresh)] = &issued[..] else {
+            unreachable!()
+        };
+
+        // Revoked behind the cache's back, so a cache hit is the only way to still see it.
+        backend.revoke_by_access_token(second_access).await?;
+
+        cached.revoke_by_refresh_token(first_refresh).await?;
+        assert!(cached.get_by_access_token(first_access).await?.is_none());
+        assert!(cached.get_by_access_token(second_access).await?.is_some());
+
+        cached
+            .revoke(second_refresh.secret(), Some(TokenTypeHint::RefreshToken), "cache-test")
+            .await?;
+        assert!(cached.get_by_access_token(second_access).await?.is_none());
+
+        Ok(())
+    }
+
+    /// Store whose next access-token lookup stops after reading the row, until resumed.
+    #[derive(Clone, Default)]
+    struct PausingStore {
+        inner: InMemoryTokenStore,
+        pause: Arc<std::sync::atomic::AtomicBool>,
+        paused: Arc<tokio::sync::Notify>,
+        resume: Arc<tokio::sync::Notify>,
+    }
+
+    #[async_trait::async_trait]
+    impl OAuth2TokenStore for PausingStore {
+        async fn store_token<EF, TT>(
+            &self,
+            token: &StandardTokenResponse<EF, TT>,
+            client_id: &str,
+            user_id: Option<Uuid>,
+            scopes: &[Scope],
+            refresh_expires_in: Option<Duration>,
+        ) -> Result<(), Error>
+        where
+            EF: ExtraTokenFields + Sync,
+            TT: oauth2::TokenType + Sync,
+        {
+            self.inner
+                .store_token(token, client_id, user_id, scopes, refresh_expires_in)
+                .await
+        }
+
+        async fn get_by_access_token(&self, token: &AccessToken) -> Result<Option<StoredToken>, Error> {
+            let found = self.inner.get_by_access_token(token).await;
+            if self.pause.swap(false, std::sync::atomic::Ordering::SeqCst) {
+                self.paused.notify_one();
+                self.resume.notified().await;
+            }
+            found
+        }
+
+        async fn get_by_refresh_token(&self, token: &RefreshToken) -> Result<Option<StoredToken>, Error> {
+            self.inner.get_by_refresh_token(token).await
+        }
+
+        async fn find_by_access_token(&self, token: &AccessToken) -> Result<Option<StoredToken>, Error> {
+            self.inner.find_by_access_token(token).await
+        }
+
+        async fn find_by_refresh_token(&self, token: &RefreshToken) -> Result<Option<StoredToken>, Error> {
+            self.inner.find_by_refresh_token(token).await
+        }
+
+        async fn revoke_by_access_token(&self, token: &AccessToken) -> Result<(), Error> {
+            self.inner.revoke_by_access_token(token).await
+        }
+
+        async fn revoke_by_refresh_token(&self, token: &RefreshToken) -> Result<(), Error> {
+            self.inner.revoke_by_refresh_token(token).await
+        }
+
+        async fn rotate_refresh_token<EF, TT>(
+            &self,
+            old: &RefreshToken,
+            new: &StandardTokenResponse<EF, TT>,
+            refresh_expires_in: Option<Duration>,
+        ) -> Result<StoredToken, Error>
+        where
+            EF: ExtraTokenFields + Sync,
+            TT: oauth2::TokenType + Sync,
+        {
+            self.inner.rotate_refresh_token(old, new, refresh_expires_in).await
+        }
+
+        async fn revoke(
+            &self,
+            token: &str,
+            token_type_hint: Option<TokenTypeHint>,
+            client_id: &str,
+        ) -> Result<(), Error> {
+            self.inner.revoke(token, token_type_hint, client_id).await
+        }
+
+        async fn list_tokens_for_user(&self, user_id: Uuid, page: PageRequest) -> Result<TokenPage, Error> {
+            self.inner.list_tokens_for_user(user_id, page).await
+        }
+
+        async fn revoke_by_id(&self, id: Uuid) -> Result<(), Error> {
+            self.inner.revoke_by_id(id).await
+        }
+
+        async fn revoke_all_for_user(&self, user_id: Uuid, except: Option<Uuid>) -> Result<usize, Error> {
+            self.inner.revoke_all_for_user(user_id, except).await
+        }
+
+        async fn revoke_all_for_client(&self, client_id: &str, except: Option<Uuid>) -> Result<usize, Error> {
+            self.inner.revoke_all_for_client(client_id, except).await
+        }
+
+        async fn revoke_all_for_user_and_client(
+            &self,
+            user_id: Uuid,
+            client_id: &str,
+            except: Option<Uuid>,
+        ) -> Result<usize, Error> {
+            self.inner
+                .revoke_all_for_user_and_client(user_id, client_id, except)
+                .await
+        }
+
+        async fn cleanup(&self) -> Result<usize, Error> {
+            self.inner.cleanup().await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cached_store_does_not_recache_tokens_revoked_mid_lookup() -> Result<(), Box<dyn std::error::Error>> {
+        let backend = PausingStore::default();
+        let cached = CachedTokenStore::new(backend.clone()).with_ttl(Duration::from_secs(60));
+
+        let user_id = Uuid::new_v4();
+        let mut accesses = Vec::new();
+        for _ in 0..2 {
+            let access = AccessToken::new(Uuid::new_v4().to_string());
+            let token_response = StandardTokenResponse::new(
+                access.clone(),
+                BasicTokenType::Bearer,
+                EmptyExtraTokenFields {},
+            );
+            cached
+                .store_token(&token_response, "cache-test", Some(user_id), &[], None)
+                .await?;
+            accesses.push(access);
+        }
+
+        // Revoke by value, and in bulk, while a lookup holds the row it read before.
+        for (i, access) in accesses.iter().enumerate() {
+            backend.pause.store(true, std::sync::atomic::Ordering::SeqCst);
+            let lookup = tokio::spawn({
+                let cached = cached.clone();
+                let access = access.clone();
+                async move { cached.get_by_access_token(&access).await }
+            });
+            backend.paused.notified().await;
+
+            if i == 0 {
+                cached.revoke_by_access_token(access).await?;
+            } else {
+                cached.revoke_all_for_user(user_id, None).await?;
+            }
+
+            backend.resume.notify_one();
+            assert!(lookup.await??.is_some(), "the lookup read the row before revocation");
+            assert!(cached.get_by_access_token(access).await?.is_none());
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_cached_store_expiry_and_negative_ttl() -> Result<(), Box<dyn std::error::Error>> {
+        let backend = InMemoryTokenStore::new();
+        let cached = CachedTokenStore::new(backend.clone())
+            .with_ttl(Duration::from_secs(60))
+            .with_negative_ttl(Duration::from_millis(300));
+
+        // Never served past expires_at, even within the TTL.
+        let access = AccessToken::new(Uuid::new_v4().to_string());
+        let mut token_response = StandardTokenResponse::new(
+            access.clone(),
+            BasicTokenType::Bearer,
+            EmptyExtraTokenFields {},
+        );
+        token_response.set_expires_in(Some(&Duration::from_secs(1)));
+        cached
+            .store_token(&token_response, "cache-test", None, &[], None)
+            .await?;
+        assert!(cached.get_by_access_token(&access).await?.is_some());
+
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+        assert!(cached.get_by_access_token(&access).await?.is_none());
+
+        // A miss is cached until the negative TTL runs out.
+        let access = AccessToken::new(Uuid::new_v4().to_string());
+        let token_response = StandardTokenResponse::new(
+            access.clone(),
+            BasicTokenType::Bearer,
+            EmptyExtraTokenFields {},
+        );
+        assert!(cached.get_by_access_token(&access).await?.is_none());
+        backend
+            .store_token(&token_response, "cache-test", None, &[], None)
+            .await?;
+        assert!(cached.get_by_access_token(&access).await?.is_none());
+
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        assert!(cached.get_by_access_token(&access).await?.is_some());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_revocation_notifications_purge_other_caches() -> Result<(), Box<dyn std::error::Error>> {
+        let pool = setup_test_db().await;
+
+        // Two instances sharing one database, each with its own cache.
+        let pod_a = CachedTokenStore::new(PgTokenStore::new(pool.clone()));
+        let pod_b = CachedTokenStore::new(PgTokenStore::new(pool.clone()));
+        let listener = pod_b.listen_for_revocations(pool.clone());
+        tokio::time::sleep(Duration::from_millis(500)).await;
+
+        // More tokens than fit in one notification.
+        let user_id = Uuid::new_v4();
+        let mut tokens = Vec::new();
+        for _ in 0..150 {
+            let access = AccessToken::new(Uuid::new_v4().to_string());
+            let token_response = StandardTokenResponse::new(
+                access.clone(),
+                BasicTokenType::Bearer,
+                EmptyExtraTokenFields {},
+            );
+            pod_a
+                .store_token(&token_response, "notify-test", Some(user_id), &[], None)
+                .await?;
+            assert!(pod_b.get_by_access_token(&access).await?.is_some());
+            tokens.push(access);
+        }
+
+        assert_eq!(pod_a.revoke_all_for_user(user_id, None).await?, 150);
+
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        for access in &tokens {
+            while pod_b.get_by_access_token(access).await?.is_some() {
+                assert!(std::time::Instant::now() < deadline, "pod B never saw the revocation");
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        }
+
+        listener.abort();
+
+        Ok(())
+    }
+
+    #[cfg(feature = "redis")]
+    #[tokio::test]
+    async fn test_redis_cache_is_shared_between_instances() -> Result<(), Box<dyn std::error::Error>> {
+        use oauth2::TokenResponse;
+        use oauth2_pg_store::RedisCachedTokenStore;
+
+        let url = setup_redis().await;
+        let prefix = format!("{}:", Uuid::new_v4());
+
+        // Two instances sharing one backend and one Redis.
+        let backend = InMemoryTokenStore::new();
+        let pod_a = RedisCachedTokenStore::new(backend.clone(), &url)?
+            .with_key_prefix(prefix.clone())
+            .with_ttl(Duration::from_secs(60));
+        let pod_b = RedisCachedTokenStore::new(backend.clone(), &url)?
+            .with_key_prefix(prefix)
+            .with_ttl(Duration::from_secs(60));
+
+        let issue = |expires_in: u64| {
+            let mut token_response = StandardTokenResponse::new(
+                AccessToken::new(Uuid::new_v4().to_string()),
+                BasicTokenType::Bearer,
+                EmptyExtraTokenFields {},
+            );
+            token_response.set_expires_in(Some(&Duration::from_secs(expires_in)));
+            token_response.set_refresh_token(Some(RefreshToken::new(Uuid::new_v4().to_string())));
+            token_response
+        };
+
+        // A token cached by one instance is served to the other, even once revoked
+        // behind the cache's back.
+        let token_response = issue(3600);
+        let access = token_response.access_token().clone();
+        pod_a.store_token(&token_response, "redis-test", None, &[], None).await?;
+        assert!(pod_a.get_by_access_token(&access).await?.is_some());
+        backend.revoke_by_access_token(&access).await?;
+        assert!(pod_b.get_by_access_token(&access).await?.is_some());
+
+        // Never served past expires_at, even within the TTL.
+        let token_response = issue(1);
+        let access = token_response.access_token().clone();
+        pod_a.store_token(&token_response, "redis-test", None, &[], None).await?;
+        assert!(pod_a.get_by_access_token(&access).await?.is_some());
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+        assert!(pod_b.get_by_access_token(&access).await?.is_none());
+
+        // Revoked through one instance, by access or by refresh token: rejected by both.
+        let by_access = issue(3600);
+        let by_refresh = issue(3600);
+        for token_response in [&by_access, &by_refresh] {
+            pod_a.store_token(token_response, "redis-test", None, &[], None).await?;
+            assert!(pod_b.get_by_access_token(token_response.access_token()).await?.is_some());
+        }
+        pod_a.revoke_by_access_token(by_access.access_token()).await?;
+        pod_a
+            .revoke(by_refresh.refresh_token().unwrap().secret(), None, "redis-test")
+            .await?;
+        for token_response in [&by_access, &by_refresh] {
+            assert!(pod_b.get_by_access_token(token_response.access_token()).await?.is_none());
+        }
+
+        // Bulk revocation drops every cached token of the user but the one kept.
+        let user_id = Uuid::new_v4();
+        let mut tokens = Vec::new();
+        for _ in 0..3 {
+            let token_response = issue(3600);
+            pod_a
+                .store_token(&token_response, "redis-test", Some(user_id), &[], None)
+                .await?;
+            let stored = pod_b.get_by_access_token(token_response.access_token()).await?;
+            tokens.push((token_response.access_token().clone(), stored.unwrap().id));
+        }
+        let keep = tokens[0].1;
+        assert_eq!(pod_a.revoke_all_for_user(user_id, Some(keep)).await?, 2);
+        for (access, id) in &tokens {
+            let found = pod_b.get_by_access_token(access).await?;
+            assert_eq!(found.is_some(), *id == keep);
+        }
+
+        // Rotation drops the rotated-out token and caches its successor.
+        let old = issue(3600);
+        pod_a.store_token(&old, "redis-test", None, &[], None).await?;
+        assert!(pod_b.get_by_access_token(old.access_token()).await?.is_some());
+        let new = issue(3600);
+        pod_a
+            .rotate_refresh_token(old.refresh_token().unwrap(), &new, None)
+            .await?;
+        assert!(pod_b.get_by_access_token(old.access_token()).await?.is_none());
+        assert!(pod_b.get_by_access_token(new.access_token()).await?.is_some());
+
+        Ok(())
+    }
+
+    #[cfg(feature = "redis")]
+    #[tokio::test]
+    async fn test_redis_cache_reads_through_when_redis_hangs() -> Result<(), Box<dyn std::error::Error>> {
+        use oauth2_pg_store::RedisCachedTokenStore;
+
+        // Accepts connections but never answers.
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let url = format!("redis://{}", listener.local_addr()?);
+        let server = tokio::spawn(async move {
+            let mut sockets = Vec::new();
+            while let Ok((socket, _)) = listener.accept().await {
+                sockets.push(socket);
+            }
+        });
+
+        let store = RedisCachedTokenStore::new(InMemoryTokenStore::new(), &url)?
+            .with_connection_timeout(Duration::from_millis(200))
+            .with_response_timeout(Duration::from_millis(200));
+
+        let access = AccessToken::new(Uuid::new_v4().to_string());
+        let token_response = StandardTokenResponse::new(
+            access.clone(),
+            BasicTokenType::Bearer,
+            EmptyExtraTokenFields {},
+        );
+        store
+            .store_token(&token_response, "redis-test", None, &[], None)
+            .await?;
+
+        let started = std::time::Instant::now();
+        assert!(store.get_by_access_token(&access).await?.is_some());
+        assert!(started.elapsed() < Duration::from_secs(2));
+
+        // The revocation reaches the inner store, but could not be applied to Redis.
+        assert!(matches!(
+            store.revoke_by_access_token(&access).await,
+            Err(Error::Cache(_))
+        ));
+        assert!(store.inner().get_by_access_token(&access).await?.is_none());
+
+        server.abort();
+
+        Ok(())
+    }
+
+    #[cfg(feature = "redis")]
+    #[tokio::test]
+    async fn test_redis_cache_backs_off_after_failed_connect() -> Result<(), Box<dyn std::error::Error>> {
+        use oauth2_pg_store::RedisCachedTokenStore;
+
+        // Accepts connections but never completes the handshake.
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let url = format!("redis://{}", listener.local_addr()?);
+        let server = tokio::spawn(async move {
+            let mut sockets = Vec::new();
+            while let Ok((socket, _)) = listener.accept().await {
+                sockets.push(socket);
+            }
+        });
+
+        let store = RedisCachedTokenStore::new(InMemoryTokenStore::new(), &url)?
+            .with_connection_timeout(Duration::from_millis(500))
+            .with_response_timeout(Duration::from_millis(500));
+
+        let access = AccessToken::new(Uuid::new_v4().to_string());
+        let token_response = StandardTokenResponse::new(
+            access.clone(),
+            BasicTokenType::Bearer,
+            EmptyExtraTokenFields {},
+        );
+        store
+            .inner()
+            .store_token(&token_response, "redis-test", None, &[], None)
+            .await?;
+
+        let started = std::time::Instant::now();
+        assert!(store.get_by_access_token(&access).await?.is_some());
+        assert!(started.elapsed() >= Duration::from_millis(500));
+
+        // Within the backoff window, lookups read through without trying Redis.
+        for _ in 0..3 {
+            let started = std::time::Instant::now();
+            assert!(store.get_by_access_token(&access).await?.is_some());
+            assert!(started.elapsed() < Duration::from_millis(250));
+        }
+
+        server.abort();
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_usage_tracking_batches_writes() -> Result<(), Box<dyn std::error::Error>> {
+        let pool = setup_test_db().await;
+        let store = PgTokenStore::new(pool.clone()).with_usage_tracking(Duration::from_secs(3600));
+
+        let user_id = Uuid::new_v4();
+        let access = AccessToken::new(Uuid::new_v4().to_string());
+        let mut token_response = StandardTokenResponse::new(
+            access.clone(),
+            BasicTokenType::Bearer,
+            EmptyExtraTokenFields {},
+        );
+        token_response.set_expires_in(Some(&Duration::from_secs(3600)));
+        store
+            .store_token(&token_response, "usage-test", Some(user_id), &[], None)
+            .await?;
+
+        for _ in 0..3 {
+            assert!(store.get_by_access_token(&access).await?.is_some());
+        }
+
+        // Nothing is written until the flush interval runs out or the buffer is flushed.
+        let listed = store.list_tokens_for_user(user_id, PageRequest::first(10)).await?;
+        assert_eq!(listed.tokens[0].use_count, 0);
+        assert!(listed.tokens[0].last_used_at.is_none());
+
+        assert_eq!(store.flush_usage().await?, 1);
+        assert_eq!(store.flush_usage().await?, 0);
+
+        let found = store.get_by_access_token(&access).await?.unwrap();
+        assert_eq!(found.use_count, 3);
+        let last_used_at = found.last_used_at.expect("last_used_at not recorded");
+        assert!(last_used_at >= found.issued_at);
+
+        // Misses record nothing.
+        let unknown = AccessToken::new(Uuid::new_v4().to_string());
+        assert!(store.get_by_access_token(&unknown).await?.is_none());
+        assert_eq!(store.flush_usage().await?, 1);
+        let listed = store.list_tokens_for_user(user_id, PageRequest::first(10)).await?;
+        assert_eq!(listed.tokens[0].use_count, 4);
+        assert!(listed.tokens[0].last_used_at > Some(last_used_at));
+
+        // Without tracking, lookups never write.
+        let untracked = PgTokenStore::new(pool);
+        untracked.get_by_access_token(&access).await?;
+        assert_eq!(untracked.flush_usage().await?, 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_usage_tracking_flushes_after_store_is_dropped() -> Result<(), Box<dyn std::error::Error>> {
+        let pool = setup_test_db().await;
+        let store = PgTokenStore::new(pool.clone()).with_usage_tracking(Duration::from_millis(200));
+
+        let access = AccessToken::new(Uuid::new_v4().to_string());
+        let token_response = StandardTokenResponse::new(
+            access.clone(),
+            BasicTokenType::Bearer,
+            EmptyExtraTokenFields {},
+        );
+        store
+            .store_token(&token_response, "usage-test", None, &[], None)
+            .await?;
+
+        assert!(store.get_by_access_token(&access).await?.is_some());
+        drop(store);
+
+        // The flusher writes the last batch on its next tick, then stops.
+        let untracked = PgTokenStore::new(pool);
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        loop {
+            let found = untracked.find_by_access_token(&access).await?.unwrap();
+            if found.use_count == 1 {
+                break;
+            }
+            assert!(std::time::Instant::now() < deadline, "usage was never flushed");
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_usage_tracking_flushes_after_interval() -> Result<(), Box<dyn std::error::Error>> {
+        let store = InMemoryTokenStore::new().with_usage_tracking(Duration::from_millis(300));
+
+        let access = AccessToken::new(Uuid::new_v4().to_string());
+        let token_response = StandardTokenResponse::new(
+            access.clone(),
+            BasicTokenType::Bearer,
+            EmptyExtraTokenFields {},
+        );
+        store
+            .store_token(&token_response, "usage-test", None, &[], None)
+            .await?;
+
+        assert!(store.get_by_access_token(&access).await?.is_some());
+        tokio::time::sleep(Duration::from_millis(400)).await;
+
+        // The first lookup past the interval writes the batch in the background.
+        assert!(store.get_by_access_token(&access).await?.is_some());
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        loop {
+            let found = store.get_by_access_token(&access).await?.unwrap();
+            if found.use_count >= 2 {
+                assert!(found.last_used_at.is_some());
+                break;
+            }
+            assert!(std::time::Instant::now() < deadline, "usage was never flushed");
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_idle_timeout() -> Result<(), Box<dyn std::error::Error>> {
+        let pool = setup_test_db().await;
+        let store = PgTokenStore::new(pool).with_idle_timeout(Duration::from_secs(2));
+
+        let user_id = Uuid::new_v4();
+        let access = AccessToken::new(Uuid::new_v4().to_string());
+        let refresh = RefreshToken::new(Uuid::new_v4().to_string());
+        let mut token_response = StandardTokenResponse::new(
+            access.clone(),
+            BasicTokenType::Bearer,
+            EmptyExtraTokenFields {},
+        );
+        token_response.set_expires_in(Some(&Duration::from_secs(3600)));
+        token_response.set_refresh_token(Some(refresh.clone()));
+        store
+            .store_token(&token_response, "idle-test", Some(user_id), &[], None)
+            .await?;
+
+        let issued = store.get_by_access_token(&access).await?.unwrap();
+        assert_eq!(issued.idle_timeout_secs, Some(2));
+        let first_deadline = issued.idle_expires_at.expect("idle deadline not set");
+
+        // Each use keeps the session alive past the original deadline.
+        for _ in 0..2 {
+            tokio::time::sleep(Duration::from_millis(1200)).await;
+            assert!(store.get_by_access_token(&access).await?.is_some());
+        }
+        let touched = store.get_by_refresh_token(&refresh).await?.unwrap();
+        assert!(touched.idle_expires_at.unwrap() > first_deadline + chrono::Duration::seconds(2));
+
+        tokio::time::sleep(Duration::from_millis(2500)).await;
+
+        assert!(store.get_by_access_token(&access).await?.is_none());
+        assert!(store.get_by_refresh_token(&refresh).await?.is_none());
+        let listed = store.list_tokens_for_user(user_id, PageRequest::first(10)).await?;
+        assert!(listed.tokens.is_empty());
+
+        let new_response = StandardTokenResponse::new(
+            AccessToken::new(Uuid::new_v4().to_string()),
+            BasicTokenType::Bearer,
+            EmptyExtraTokenFields {},
+        );
+        let rotated = store.rotate_refresh_token(&refresh, &new_response, None).await;
+        assert!(matches!(rotated, Err(Error::InvalidToken)));
+
+        assert_eq!(store.cleanup().await?, 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_sliding_expiration() -> Result<(), Box<dyn std::error::Error>> {
+        let store = InMemoryTokenStore::new().with_sliding_expiration(Duration::from_secs(3));
+
+        let access = AccessToken::new(Uuid::new_v4().to_string());
+        let mut token_response = StandardTokenResponse::new(
+            access.clone(),
+            BasicTokenType::Bearer,
+            EmptyExtraTokenFields {},
+        );
+        token_response.set_expires_in(Some(&Duration::from_secs(2)));
+        store
+            .store_token(&token_response, "sliding-test", None, &[], None)
+            .await?;
+
+        let issued = store.get_by_access_token(&access).await?.unwrap();
+        assert_eq!(issued.sliding_window_secs, Some(2));
+        let max_expires_at = issued.max_expires_at.expect("max lifetime not set");
+
+        tokio::time::sleep(Duration::from_millis(1200)).await;
+
+        // The window slides forward, but only up to the maximum lifetime.
+        let slid = store.get_by_access_token(&access).await?.unwrap();
+        assert_eq!(slid.expires_at, Some(max_expires_at));
+
+        tokio::time::sleep(Duration::from_millis(1000)).await;
+        assert!(store.get_by_access_token(&access).await?.is_some());
+
+        tokio::time::sleep(Duration::from_millis(1000)).await;
+        assert!(store.get_by_access_token(&access).await?.is_none());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_token_limits() -> Result<(), Box<dyn std::error::Error>> {
+        let pool = setup_test_db().await;
+
+        let issue = |store: PgTokenStore, client_id: &'static str, user_id: Uuid| async move {
+            let access = AccessToken::new(Uuid::new_v4().to_string());
+            let token_response = StandardTokenResponse::new(
+                access.clone(),
+                BasicTokenType::Bearer,
+                EmptyExtraTokenFields {},
+            );
+            store
+                .store_token(&token_response, client_id, Some(user_id), &[], None)
+                .await
+                .map(|()| access)
+        };
+
+        // Rejecting: the third token for the same user and client fails.
+        let rejecting = PgTokenStore::new(pool.clone()).with_token_limits(
+            TokenLimits::new(LimitPolicy::Reject).with_per_user_and_client(2),
+        );
+        let user_id = Uuid::new_v4();
+        issue(rejecting.clone(), "limit-reject", user_id).await?;
+        let second = issue(rejecting.clone(), "limit-reject", user_id).await?;
+        let third = issue(rejecting.clone(), "limit-reject", user_id).await;
+        assert!(matches!(third, Err(Error::LimitExceeded(_))));
+
+        // Other users of the client are unaffected, and revoked tokens free a slot.
+        issue(rejecting.clone(), "limit-reject", Uuid::new_v4()).await?;
+        rejecting.revoke_by_access_token(&second).await?;
+        issue(rejecting.clone(), "limit-reject", user_id).await?;
+
+        // Evicting: the oldest tokens of the client are revoked to make room.
+        let evicting = PgTokenStore::new(pool).with_token_limits(
+            TokenLimits::new(LimitPolicy::EvictOldest)
+                .with_per_user_and_client(2)
+                .with_per_client(3),
+        );
+        let (alice, bob) = (Uuid::new_v4(), Uuid::new_v4());
+        let alice_first = issue(evicting.clone(), "limit-evict", alice).await?;
+        let alice_second = issue(evicting.clone(), "limit-evict", alice).await?;
+        let alice_third = issue(evicting.clone(), "limit-evict", alice).await?;
+        assert!(evicting.get_by_access_token(&alice_first).await?.is_none());
+        assert!(evicting.get_by_access_token(&alice_second).await?.is_some());
+
+        let bob_first = issue(evicting.clone(), "limit-evict", bob).await?;
+        issue(evicting.clone(), "limit-evict", bob).await?;
+        assert!(evicting.get_by_access_token(&alice_second).await?.is_none());
+        assert!(evicting.get_by_access_token(&alice_third).await?.is_some());
+        assert!(evicting.get_by_access_token(&bob_first).await?.is_some());
+
+        let alice_tokens = evicting.list_tokens_for_user(alice, PageRequest::first(10)).await?;
+        let bob_tokens = evicting.list_tokens_for_user(bob, PageRequest::first(10)).await?;
+        assert_eq!(alice_tokens.tokens.len() + bob_tokens.tokens.len(), 3);
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_get_non_existent_token() -> Result<(), Box<dyn std::error::Error>> {
-        let (pool, _container) = setup_test_db().await;
+        let pool = setup_test_db().await;
         let store = PgTokenStore::new(pool);
 
         let token = AccessToken::new(Uuid::new_v4().to_string());